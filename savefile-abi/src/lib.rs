@@ -846,6 +846,7 @@ pub fn parse_return_value_impl<T>(
                 reader: &mut reader,
                 file_version,
                 ephemeral_state: HashMap::new(),
+                limit_state: None,
             };
             deserialize_action(&mut deserializer)
             //T::deserialize(&mut deserializer)
@@ -0,0 +1,217 @@
+//! Implementations of the subcommands
+
+use crate::schema_printer::format_schema;
+use crate::Args;
+use savefile::prelude::*;
+use savefile::{
+    decrypt_savefile, encrypt_savefile, inspect, load_as_json, load_dynamic, read_header,
+    recompress as recompress_file, SchemaDiff,
+};
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::time::UNIX_EPOCH;
+
+type CommandResult = Result<(), Box<dyn Error>>;
+
+const SAVEFILE_MAGIC: &[u8] = b"savefile\0";
+const CRYPTO_MAGIC: &[u8] = b"savefile-crypto\0";
+
+fn open(path: &str) -> Result<BufReader<File>, Box<dyn Error>> {
+    Ok(BufReader::new(
+        File::open(path).map_err(|err| format!("Could not open {}: {}", path, err))?,
+    ))
+}
+
+/// Create `path`, call `write` to fill it, and flush it
+fn create(path: &str, write: impl FnOnce(&mut BufWriter<File>) -> Result<(), SavefileError>) -> CommandResult {
+    let mut f = BufWriter::new(File::create(path).map_err(|err| format!("Could not create {}: {}", path, err))?);
+    write(&mut f)?;
+    f.flush()?;
+    Ok(())
+}
+
+fn read_all(path: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+    let mut data = Vec::new();
+    open(path)?.read_to_end(&mut data)?;
+    Ok(data)
+}
+
+fn password(args: &Args) -> Result<String, Box<dyn Error>> {
+    if let Some(password) = args.option("password") {
+        return Ok(password.to_string());
+    }
+    std::env::var("SAVEFILE_PASSWORD").map_err(|_| "No password given. Use --password or set SAVEFILE_PASSWORD.".into())
+}
+
+/// Print the header of a savefile, including its metadata
+pub(crate) fn header(path: &str, out: &mut dyn Write) -> CommandResult {
+    let mut head = Vec::new();
+    open(path)?.take(18).read_to_end(&mut head)?;
+    if head.starts_with(CRYPTO_MAGIC) && head.len() == 18 {
+        let key = match head[17] {
+            0 => "a key",
+            1 => "a password",
+            2 => "public keys",
+            _ => "an unknown method",
+        };
+        writeln!(out, "encrypted using {} (header version {})", key, head[16])?;
+        return Ok(());
+    }
+    if !head.starts_with(SAVEFILE_MAGIC) {
+        return Err(format!("{} is not a savefile", path).into());
+    }
+    let header = read_header(&mut open(path)?)?;
+    writeln!(out, "savefile lib version: {}", header.savefile_lib_version)?;
+    writeln!(out, "file version: {}", header.version)?;
+    match header.compression {
+        Some(codec) => writeln!(out, "compression: {}", codec)?,
+        None => writeln!(out, "compression: none")?,
+    }
+    match header.checksum {
+        Some(checksum) => writeln!(out, "checksum: {}", checksum_name(checksum))?,
+        None => writeln!(out, "checksum: none")?,
+    }
+    if let Some(metadata) = header.metadata {
+        writeln!(out, "app name: {}", metadata.app_name)?;
+        if let Some(created) = metadata.created {
+            let seconds = created
+                .duration_since(UNIX_EPOCH)
+                .map(|x| x.as_secs().to_string())
+                .unwrap_or_else(|_| "before 1970".to_string());
+            writeln!(out, "created: {} (seconds since 1970)", seconds)?;
+        }
+        writeln!(out, "description: {}", metadata.description)?;
+        writeln!(out, "thumbnail: {} bytes", metadata.thumbnail.len())?;
+    }
+    Ok(())
+}
+
+fn checksum_name(checksum: ChecksumAlgorithm) -> &'static str {
+    match checksum {
+        ChecksumAlgorithm::Crc32c => "crc32c",
+        ChecksumAlgorithm::XxHash64 => "xxhash64",
+        _ => "unknown",
+    }
+}
+
+/// The contents of a `.schema` file
+enum SchemaFile {
+    /// The schema of a savefile type
+    Data(Schema),
+    /// The definition of a trait, as written by `savefile_abi::verify_compatiblity`
+    Abi(AbiTraitDefinition),
+}
+
+/// Parse `data` as a `.schema` file, which is an uncompressed savefile without
+/// schema, containing only a [Schema] or an [AbiTraitDefinition].
+fn parse_schema_file(data: &[u8]) -> Option<SchemaFile> {
+    if data.len() < 16 || !data.starts_with(SAVEFILE_MAGIC) || data[15] != 0 {
+        return None;
+    }
+    let mut reader = data;
+    if let Ok(schema) = load_noschema::<Schema>(&mut reader, u32::MAX) {
+        if reader.is_empty() {
+            return Some(SchemaFile::Data(schema));
+        }
+    }
+    let mut reader = data;
+    if let Ok(definition) = load_noschema::<AbiTraitDefinition>(&mut reader, u32::MAX) {
+        if reader.is_empty() {
+            return Some(SchemaFile::Abi(definition));
+        }
+    }
+    None
+}
+
+/// Print the schema of a savefile, or the contents of a `.schema` file
+pub(crate) fn schema(path: &str, out: &mut dyn Write) -> CommandResult {
+    let data = read_all(path)?;
+    match parse_schema_file(&data) {
+        Some(SchemaFile::Data(schema)) => {
+            writeln!(out, "{}", format_schema(&schema))?;
+        }
+        Some(SchemaFile::Abi(definition)) => {
+            writeln!(out, "{:#?}", definition)?;
+        }
+        None => {
+            let info = inspect(&mut &data[..])?;
+            writeln!(out, "version {}:", info.header.version)?;
+            writeln!(out, "{}", format_schema(&info.schema))?;
+        }
+    }
+    Ok(())
+}
+
+/// Print the contents of a savefile as JSON
+pub(crate) fn dump(path: &str, out: &mut dyn Write) -> CommandResult {
+    let json = load_as_json(&mut open(path)?)?;
+    serde_json::to_writer_pretty(&mut *out, &json)?;
+    writeln!(out)?;
+    Ok(())
+}
+
+pub(crate) fn recompress(args: &Args) -> CommandResult {
+    let codec = match args.option("codec").unwrap_or("zstd") {
+        "none" => None,
+        "bzip2" => Some(CompressionCodec::Bzip2),
+        "zstd" => Some(CompressionCodec::Zstd),
+        "lz4" => Some(CompressionCodec::Lz4),
+        "deflate" => Some(CompressionCodec::Deflate),
+        other => return Err(format!("Unknown codec '{}'", other).into()),
+    };
+    let mut options = codec.map(CompressionOptions::new);
+    if let Some(level) = args.option("level") {
+        let level = level.parse().map_err(|_| format!("Invalid level '{}'", level))?;
+        options = options.map(|options| options.with_level(level));
+    }
+    let mut input = open(args.arg(0))?;
+    create(args.arg(1), |output| recompress_file(&mut input, output, options))
+}
+
+pub(crate) fn decompress(args: &Args) -> CommandResult {
+    let mut input = open(args.arg(0))?;
+    create(args.arg(1), |output| recompress_file(&mut input, output, None))
+}
+
+pub(crate) fn encrypt(args: &Args) -> CommandResult {
+    let password = password(args)?;
+    let mut kdf = KdfParams::default();
+    if let Some(iterations) = args.option("iterations") {
+        kdf.iterations = iterations
+            .parse()
+            .map_err(|_| format!("Invalid iteration count '{}'", iterations))?;
+    }
+    let mut input = open(args.arg(0))?;
+    create(args.arg(1), |output| {
+        encrypt_savefile(&mut input, output, &password, kdf)
+    })
+}
+
+pub(crate) fn decrypt(args: &Args) -> CommandResult {
+    let password = password(args)?;
+    let mut input = open(args.arg(0))?;
+    create(args.arg(1), |output| decrypt_savefile(&mut input, output, &password))
+}
+
+/// Check that the savefile at `path` can be read, and that its schema is the one in `schema_path`
+pub(crate) fn validate(path: &str, schema_path: &str, out: &mut dyn Write) -> CommandResult {
+    let expected = match parse_schema_file(&read_all(schema_path)?) {
+        Some(SchemaFile::Data(schema)) => schema,
+        Some(SchemaFile::Abi(definition)) => {
+            return Err(format!(
+                "{} describes the ABI of trait {}, not the contents of a savefile",
+                schema_path, definition.name
+            )
+            .into())
+        }
+        None => return Err(format!("{} is not a schema file", schema_path).into()),
+    };
+    let file = load_dynamic(&mut open(path)?)?;
+    let diff = SchemaDiff::new(&expected, &file.schema);
+    if !diff.is_empty() {
+        return Err(format!("{} does not match {}:\n{}", path, schema_path, diff).into());
+    }
+    writeln!(out, "{} matches {} (file version {})", path, schema_path, file.version)?;
+    Ok(())
+}
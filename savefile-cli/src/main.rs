@@ -0,0 +1,136 @@
+//! Command line tool for inspecting and converting files written by savefile.
+//!
+//! Run `savefile help` for a list of commands.
+
+mod commands;
+mod schema_printer;
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::process::ExitCode;
+
+const USAGE: &str = "\
+Usage: savefile <command> [arguments]
+
+Commands:
+  header <file>                   Print the header and metadata of a savefile
+  schema <file>                   Print the schema of a savefile, or of a .schema file
+  dump <file>                     Print the contents of a savefile as JSON
+  recompress <in> <out>           Copy a savefile, changing its compression
+      --codec <codec>             none, bzip2, zstd, lz4 or deflate (default: zstd)
+      --level <level>             Compression level, see CompressionOptions
+  decompress <in> <out>           Copy a savefile, removing its compression
+  encrypt <in> <out>              Encrypt a savefile using a password
+      --iterations <count>        Number of PBKDF2 iterations
+  decrypt <in> <out>              Decrypt a savefile encrypted using a password
+  validate <file> <schema-file>   Check that a savefile matches a .schema file
+  help                            Print this message
+
+The password for encrypt and decrypt is given using --password <password>,
+or using the SAVEFILE_PASSWORD environment variable.
+";
+
+/// Positional arguments and `--name value` options of a command
+pub(crate) struct Args {
+    positional: Vec<String>,
+    options: HashMap<String, String>,
+}
+
+impl Args {
+    fn parse(args: impl Iterator<Item = String>) -> Result<Args, String> {
+        let mut positional = Vec::new();
+        let mut options = HashMap::new();
+        let mut args = args.peekable();
+        while let Some(arg) = args.next() {
+            if let Some(name) = arg.strip_prefix("--") {
+                let value = args
+                    .next()
+                    .ok_or_else(|| format!("Option --{} requires a value", name))?;
+                options.insert(name.to_string(), value);
+            } else {
+                positional.push(arg);
+            }
+        }
+        Ok(Args { positional, options })
+    }
+
+    /// Fail unless there are exactly `count` positional arguments, and no options other than `allowed`
+    fn expect(&self, count: usize, allowed: &[&str]) -> Result<(), String> {
+        if self.positional.len() != count {
+            return Err(format!("Expected {} arguments, got {}", count, self.positional.len()));
+        }
+        if let Some(unknown) = self.options.keys().find(|name| !allowed.contains(&name.as_str())) {
+            return Err(format!("Unknown option --{}", unknown));
+        }
+        Ok(())
+    }
+
+    pub(crate) fn arg(&self, index: usize) -> &str {
+        &self.positional[index]
+    }
+
+    pub(crate) fn option(&self, name: &str) -> Option<&str> {
+        self.options.get(name).map(|value| value.as_str())
+    }
+}
+
+fn run(command: &str, args: Args) -> Result<(), Box<dyn Error>> {
+    let out = &mut std::io::stdout().lock();
+    match command {
+        "header" => {
+            args.expect(1, &[])?;
+            commands::header(args.arg(0), out)
+        }
+        "schema" => {
+            args.expect(1, &[])?;
+            commands::schema(args.arg(0), out)
+        }
+        "dump" => {
+            args.expect(1, &[])?;
+            commands::dump(args.arg(0), out)
+        }
+        "recompress" => {
+            args.expect(2, &["codec", "level"])?;
+            commands::recompress(&args)
+        }
+        "decompress" => {
+            args.expect(2, &[])?;
+            commands::decompress(&args)
+        }
+        "encrypt" => {
+            args.expect(2, &["password", "iterations"])?;
+            commands::encrypt(&args)
+        }
+        "decrypt" => {
+            args.expect(2, &["password"])?;
+            commands::decrypt(&args)
+        }
+        "validate" => {
+            args.expect(2, &[])?;
+            commands::validate(args.arg(0), args.arg(1), out)
+        }
+        "help" | "--help" | "-h" => {
+            print!("{}", USAGE);
+            Ok(())
+        }
+        _ => Err(format!("Unknown command '{}'. Run 'savefile help' for usage.", command).into()),
+    }
+}
+
+fn main() -> ExitCode {
+    let mut args = std::env::args().skip(1);
+    let Some(command) = args.next() else {
+        eprint!("{}", USAGE);
+        return ExitCode::FAILURE;
+    };
+    let result = Args::parse(args)
+        .map_err(|err| err.into())
+        .and_then(|args| run(&command, args));
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("savefile {}: {}", command, err);
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,132 @@
+//! Formats a [Schema] as Rust-like type declarations
+
+use savefile::prelude::*;
+use std::fmt::Write;
+
+/// Format `schema` as a type, with nested structs and enums spelled out on indented lines
+pub(crate) fn format_schema(schema: &Schema) -> String {
+    let mut out = String::new();
+    write_schema(&mut out, schema, 0);
+    out
+}
+
+fn indent(out: &mut String, level: usize) {
+    for _ in 0..level {
+        out.push_str("    ");
+    }
+}
+
+fn write_fields(out: &mut String, fields: &[Field], level: usize) {
+    // Tuple structs and variants have fields named "0", "1", ...
+    let tuple = fields.iter().enumerate().all(|(i, field)| field.name == i.to_string());
+    if tuple {
+        out.push('(');
+        for (i, field) in fields.iter().enumerate() {
+            if i > 0 {
+                out.push_str(", ");
+            }
+            write_schema(out, &field.value, level);
+        }
+        out.push(')');
+    } else {
+        out.push_str(" {\n");
+        for field in fields {
+            indent(out, level + 1);
+            let _ = write!(out, "{}: ", field.name);
+            write_schema(out, &field.value, level + 1);
+            out.push_str(",\n");
+        }
+        indent(out, level);
+        out.push('}');
+    }
+}
+
+fn primitive_name(primitive: &SchemaPrimitive) -> &'static str {
+    match primitive {
+        SchemaPrimitive::schema_i8 => "i8",
+        SchemaPrimitive::schema_u8 => "u8",
+        SchemaPrimitive::schema_i16 => "i16",
+        SchemaPrimitive::schema_u16 => "u16",
+        SchemaPrimitive::schema_i32 => "i32",
+        SchemaPrimitive::schema_u32 => "u32",
+        SchemaPrimitive::schema_i64 => "i64",
+        SchemaPrimitive::schema_u64 => "u64",
+        SchemaPrimitive::schema_string(_) => "String",
+        SchemaPrimitive::schema_f32 => "f32",
+        SchemaPrimitive::schema_f64 => "f64",
+        SchemaPrimitive::schema_bool => "bool",
+        SchemaPrimitive::schema_canary1 => "canary",
+        SchemaPrimitive::schema_u128 => "u128",
+        SchemaPrimitive::schema_i128 => "i128",
+        SchemaPrimitive::schema_char => "char",
+    }
+}
+
+fn write_schema(out: &mut String, schema: &Schema, level: usize) {
+    match schema {
+        Schema::Struct(schema) => {
+            let _ = write!(out, "struct {}", schema.dbg_name);
+            if !schema.fields.is_empty() {
+                write_fields(out, &schema.fields, level);
+            }
+        }
+        Schema::Enum(schema) => {
+            let _ = writeln!(out, "enum {} {{", schema.dbg_name);
+            for variant in &schema.variants {
+                indent(out, level + 1);
+                out.push_str(&variant.name);
+                if !variant.fields.is_empty() {
+                    write_fields(out, &variant.fields, level + 1);
+                }
+                let _ = writeln!(out, " = {},", variant.discriminant);
+            }
+            indent(out, level);
+            out.push('}');
+        }
+        Schema::Primitive(primitive) => out.push_str(primitive_name(primitive)),
+        Schema::Vector(item, _) => {
+            out.push_str("Vec<");
+            write_schema(out, item, level);
+            out.push('>');
+        }
+        Schema::Array(array) => {
+            out.push('[');
+            write_schema(out, &array.item_type, level);
+            let _ = write!(out, "; {}]", array.count);
+        }
+        Schema::SchemaOption(item) => {
+            out.push_str("Option<");
+            write_schema(out, item, level);
+            out.push('>');
+        }
+        Schema::Undefined => out.push_str("<undefined>"),
+        Schema::ZeroSize => out.push_str("()"),
+        Schema::Custom(name) => {
+            let _ = write!(out, "<custom: {}>", name);
+        }
+        Schema::Boxed(item) => {
+            out.push_str("Box<");
+            write_schema(out, item, level);
+            out.push('>');
+        }
+        Schema::Slice(item) => {
+            out.push('[');
+            write_schema(out, item, level);
+            out.push(']');
+        }
+        Schema::Str => out.push_str("str"),
+        Schema::Reference(item) => {
+            out.push('&');
+            write_schema(out, item, level);
+        }
+        Schema::Trait(_, definition) => {
+            let _ = write!(out, "dyn {}", definition.name);
+        }
+        Schema::FnClosure(_, definition) => {
+            let _ = write!(out, "dyn {}", definition.name);
+        }
+        Schema::Recursion(depth) => {
+            let _ = write!(out, "<recursion {} levels up>", depth);
+        }
+    }
+}
@@ -0,0 +1,202 @@
+use savefile::prelude::*;
+use savefile::{save_compressed_with, save_with_checksum, save_with_metadata, FileMetadata};
+use savefile_derive::Savefile;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+#[derive(Savefile, Debug, PartialEq)]
+enum Terrain {
+    Grass,
+    Water { depth: u32 },
+}
+
+#[derive(Savefile, Debug, PartialEq)]
+struct Level {
+    name: String,
+    tiles: Vec<Terrain>,
+}
+
+fn level() -> Level {
+    Level {
+        name: "meadow".to_string(),
+        tiles: vec![Terrain::Grass, Terrain::Water { depth: 3 }],
+    }
+}
+
+/// A file path unique to this test process
+fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("savefile_cli_{}_{}", std::process::id(), name))
+}
+
+fn savefile(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_savefile"))
+        .args(args)
+        .env_remove("SAVEFILE_PASSWORD")
+        .output()
+        .unwrap()
+}
+
+/// Run the tool, expecting success, and return its standard output
+fn run(args: &[&str]) -> String {
+    let output = savefile(args);
+    assert!(
+        output.status.success(),
+        "savefile {:?} failed: {}",
+        args,
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8(output.stdout).unwrap()
+}
+
+/// Run the tool, expecting failure, and return its error output
+fn run_failing(args: &[&str]) -> String {
+    let output = savefile(args);
+    assert!(!output.status.success(), "savefile {:?} unexpectedly succeeded", args);
+    String::from_utf8(output.stderr).unwrap()
+}
+
+fn path_str(path: &Path) -> &str {
+    path.to_str().unwrap()
+}
+
+#[test]
+fn test_cli_header_schema_and_dump() {
+    let path = temp_path("inspect.bin");
+    let mut data = Vec::new();
+    save_compressed_with(&mut data, 2, &level(), CompressionOptions::new(CompressionCodec::Zstd)).unwrap();
+    std::fs::write(&path, data).unwrap();
+
+    assert_eq!(
+        run(&["header", path_str(&path)]),
+        "savefile lib version: 2\nfile version: 2\ncompression: zstd\nchecksum: none\n"
+    );
+    let schema = run(&["schema", path_str(&path)]);
+    assert!(schema.starts_with("version 2:\nstruct Level {\n    name: String,\n"));
+    assert!(schema.contains("Water {\n"));
+    let json: serde_json::Value = serde_json::from_str(&run(&["dump", path_str(&path)])).unwrap();
+    assert_eq!(
+        json,
+        serde_json::json!({"name": "meadow", "tiles": ["Grass", {"Water": {"depth": 3}}]})
+    );
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_cli_header_metadata() {
+    let path = temp_path("metadata.bin");
+    let mut metadata = FileMetadata::new("Level editor");
+    metadata.created = Some(std::time::UNIX_EPOCH + std::time::Duration::from_secs(86400));
+    metadata.description = "First level".to_string();
+    metadata.thumbnail = vec![0; 16];
+    let mut data = Vec::new();
+    save_with_metadata(&mut data, 1, &level(), &metadata).unwrap();
+    std::fs::write(&path, data).unwrap();
+
+    assert!(run(&["header", path_str(&path)]).ends_with(
+        "checksum: none\napp name: Level editor\ncreated: 86400 (seconds since 1970)\n\
+         description: First level\nthumbnail: 16 bytes\n"
+    ));
+    assert!(run(&["schema", path_str(&path)]).starts_with("version 1:\nstruct Level {"));
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_cli_recompress_keeps_checksum() {
+    let plain = temp_path("plain.bin");
+    let lz4 = temp_path("lz4.bin");
+    let decompressed = temp_path("decompressed.bin");
+    let mut data = Vec::new();
+    save_with_checksum(&mut data, 1, &level(), ChecksumAlgorithm::Crc32c).unwrap();
+    std::fs::write(&plain, &data).unwrap();
+
+    run(&["recompress", path_str(&plain), path_str(&lz4), "--codec", "lz4"]);
+    assert!(run(&["header", path_str(&lz4)]).contains("compression: lz4\nchecksum: crc32c\n"));
+    let loaded: Level = load_file(&lz4, 1).unwrap();
+    assert_eq!(loaded, level());
+
+    run(&["decompress", path_str(&lz4), path_str(&decompressed)]);
+    assert_eq!(std::fs::read(&decompressed).unwrap(), data);
+
+    let err = run_failing(&["recompress", path_str(&plain), path_str(&lz4), "--codec", "rar"]);
+    assert!(err.contains("Unknown codec 'rar'"));
+    for path in [plain, lz4, decompressed] {
+        std::fs::remove_file(path).unwrap();
+    }
+}
+
+#[test]
+fn test_cli_encrypt_and_decrypt() {
+    let plain = temp_path("secret.bin");
+    let encrypted = temp_path("secret.enc");
+    let decrypted = temp_path("secret.dec");
+    save_file(&plain, 1, &level()).unwrap();
+
+    let password = ["--password", "hunter2"];
+    let mut args = vec![
+        "encrypt",
+        path_str(&plain),
+        path_str(&encrypted),
+        "--iterations",
+        "1000",
+    ];
+    args.extend(password);
+    run(&args);
+    assert!(run(&["header", path_str(&encrypted)]).starts_with("encrypted using a password"));
+    let loaded: Level = load_encrypted_file(&encrypted, 1, "hunter2").unwrap();
+    assert_eq!(loaded, level());
+
+    let err = run_failing(&[
+        "decrypt",
+        path_str(&encrypted),
+        path_str(&decrypted),
+        "--password",
+        "wrong",
+    ]);
+    assert!(err.contains("password"), "{}", err);
+    let mut args = vec!["decrypt", path_str(&encrypted), path_str(&decrypted)];
+    args.extend(password);
+    run(&args);
+    assert_eq!(std::fs::read(&decrypted).unwrap(), std::fs::read(&plain).unwrap());
+    for path in [plain, encrypted, decrypted] {
+        std::fs::remove_file(path).unwrap();
+    }
+}
+
+#[derive(Savefile, Debug, PartialEq)]
+struct OtherLevel {
+    name: String,
+    tiles: Vec<u8>,
+}
+
+#[test]
+fn test_cli_validate() {
+    let path = temp_path("validate.bin");
+    let schema_path = temp_path("level.schema");
+    let other_schema_path = temp_path("other.schema");
+    save_file(&path, 1, &level()).unwrap();
+    save_file_noschema(&schema_path, 1, &get_schema::<Level>(1)).unwrap();
+    save_file_noschema(&other_schema_path, 1, &get_schema::<OtherLevel>(1)).unwrap();
+
+    assert!(run(&["validate", path_str(&path), path_str(&schema_path)]).contains("matches"));
+    assert!(run(&["schema", path_str(&schema_path)]).starts_with("struct Level {"));
+    let err = run_failing(&["validate", path_str(&path), path_str(&other_schema_path)]);
+    assert!(err.contains("does not match"), "{}", err);
+
+    let abi_schema = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/../savefile-test/schemas/savefile_ArgInterfaceV2_0.schema"
+    );
+    let err = run_failing(&["validate", path_str(&path), abi_schema]);
+    assert!(err.contains("describes the ABI of trait ArgInterfaceV2"), "{}", err);
+    for path in [path, schema_path, other_schema_path] {
+        std::fs::remove_file(path).unwrap();
+    }
+}
+
+#[test]
+fn test_cli_usage_errors() {
+    assert!(run(&["help"]).starts_with("Usage: savefile"));
+    assert!(run_failing(&["frobnicate"]).contains("Unknown command"));
+    assert!(run_failing(&["header"]).contains("Expected 1 arguments"));
+    assert!(run_failing(&["dump", "x", "--level", "3"]).contains("Unknown option --level"));
+}
@@ -1,4 +1,4 @@
-use proc_macro2::{Span, TokenStream};
+use proc_macro2::{Span, TokenStream, TokenTree};
 use quote::ToTokens;
 use syn::spanned::Spanned;
 use syn::{Expr, GenericParam, Generics, Lit, Type, WhereClause};
@@ -93,6 +93,61 @@ pub(crate) fn check_is_remove(field_type: &syn::Type) -> RemovedType {
     RemovedType::NotRemoved
 }
 
+/// True if the given lifetime occurs anywhere in the given type, like in `&'a str` or `Option<Cow<'a, str>>`.
+pub(crate) fn type_mentions_lifetime(field_type: &syn::Type, lifetime: &syn::Lifetime) -> bool {
+    fn scan(tokens: TokenStream, lifetime: &syn::Lifetime) -> bool {
+        let mut after_apostrophe = false;
+        for tok in tokens.into_iter() {
+            match tok {
+                TokenTree::Group(group) => {
+                    if scan(group.stream(), lifetime) {
+                        return true;
+                    }
+                    after_apostrophe = false;
+                }
+                TokenTree::Punct(punct) => {
+                    after_apostrophe = punct.as_char() == '\'';
+                }
+                TokenTree::Ident(ident) => {
+                    if after_apostrophe && ident == lifetime.ident {
+                        return true;
+                    }
+                    after_apostrophe = false;
+                }
+                TokenTree::Literal(_) => {
+                    after_apostrophe = false;
+                }
+            }
+        }
+        false
+    }
+    let mut tokens = TokenStream::new();
+    field_type.to_tokens(&mut tokens);
+    scan(tokens, lifetime)
+}
+
+/// True if any of the type parameters of `generics` occurs in the given type, like `T` in `Vec<T>`.
+pub(crate) fn type_mentions_type_param(field_type: &syn::Type, generics: &Generics) -> bool {
+    fn scan(tokens: TokenStream, params: &[&syn::Ident]) -> bool {
+        tokens.into_iter().any(|tok| match tok {
+            TokenTree::Group(group) => scan(group.stream(), params),
+            TokenTree::Ident(ident) => params.iter().any(|x| **x == ident),
+            _ => false,
+        })
+    }
+    let params: Vec<&syn::Ident> = generics
+        .params
+        .iter()
+        .filter_map(|x| match x {
+            GenericParam::Type(t) => Some(&t.ident),
+            _ => None,
+        })
+        .collect();
+    let mut tokens = TokenStream::new();
+    field_type.to_tokens(&mut tokens);
+    scan(tokens, &params)
+}
+
 pub(crate) fn overlap<'a>(b: &'a VersionRange) -> impl Fn(&'a VersionRange) -> bool {
     assert!(b.to >= b.from);
     move |a: &'a VersionRange| {
@@ -1,10 +1,14 @@
-use crate::common::{check_is_remove, get_extra_where_clauses, parse_attr_tag, FieldInfo, RemovedType};
+use crate::common::{
+    check_is_remove, get_extra_where_clauses, parse_attr_tag, type_mentions_lifetime, FieldInfo, RemovedType,
+};
 use crate::get_enum_size;
 use proc_macro2::{Literal, TokenStream};
 use syn::spanned::Spanned;
 use syn::DeriveInput;
 
-fn implement_deserialize(field_infos: Vec<FieldInfo>) -> Vec<TokenStream> {
+/// If 'borrowed' is given, fields mentioning that lifetime are deserialized using
+/// the DeserializeBorrowed-trait. All other fields use the regular Deserialize-trait.
+fn implement_deserialize(field_infos: Vec<FieldInfo>, borrowed: Option<&syn::Lifetime>) -> Vec<TokenStream> {
     let span = proc_macro2::Span::call_site();
     let defspan = proc_macro2::Span::call_site();
     let removeddef = quote_spanned! { defspan => _savefile::prelude::Removed };
@@ -18,6 +22,15 @@ fn implement_deserialize(field_infos: Vec<FieldInfo>) -> Vec<TokenStream> {
 
         let is_removed = check_is_remove(field_type);
 
+        let field_deserialize = match borrowed {
+            Some(lifetime) if type_mentions_lifetime(field_type, lifetime) => quote_spanned! { span =>
+                <#field_type as _savefile::prelude::DeserializeBorrowed<#lifetime>>::deserialize_borrowed(#local_deserializer)?
+            },
+            _ => quote_spanned! { span =>
+                <#field_type as _savefile::prelude::Deserialize>::deserialize(#local_deserializer)?
+            },
+        };
+
         let verinfo = parse_attr_tag(field.attrs);
         let (field_from_version, field_to_version, default_fn, default_val) = (
             verinfo.version_from,
@@ -67,9 +80,7 @@ fn implement_deserialize(field_infos: Vec<FieldInfo>) -> Vec<TokenStream> {
                 );
                 //TODO: Better message, tell user how to do this annotation
             };
-            quote_spanned! { span =>
-                <#field_type as _savefile::prelude::Deserialize>::deserialize(#local_deserializer)?
-            }
+            field_deserialize
         } else if verinfo.ignore {
             quote_spanned! { span =>
                 #effective_default_val
@@ -99,7 +110,7 @@ fn implement_deserialize(field_infos: Vec<FieldInfo>) -> Vec<TokenStream> {
             quote_spanned! { span =>
                 #(#version_mappings)*
                 if #local_deserializer.file_version >= #field_from_version && #local_deserializer.file_version <= #field_to_version {
-                    <#field_type as _savefile::prelude::Deserialize>::deserialize(#local_deserializer)?
+                    #field_deserialize
                 } else {
                     #effective_default_val
                 }
@@ -116,13 +127,72 @@ fn implement_deserialize(field_infos: Vec<FieldInfo>) -> Vec<TokenStream> {
     output
 }
 
+fn all_field_types(input: &DeriveInput) -> Vec<&syn::Type> {
+    match &input.data {
+        syn::Data::Enum(enum1) => enum1
+            .variants
+            .iter()
+            .flat_map(|variant| variant.fields.iter().map(|field| &field.ty))
+            .collect(),
+        syn::Data::Struct(struc) => struc.fields.iter().map(|field| &field.ty).collect(),
+        _ => vec![],
+    }
+}
+
+/// Implements Deserialize, and if the type has a single lifetime parameter, also
+/// DeserializeBorrowed.
+///
+/// For types with a single lifetime parameter, fields mentioning the lifetime are
+/// required to implement the respective trait using where-clauses. This means that
+/// Deserialize is not implemented for types with fields like `&'a str`, which can only
+/// be deserialized by borrowing, and that DeserializeBorrowed is not implemented for
+/// types with fields which cannot be borrowed.
 pub fn savefile_derive_crate_deserialize(input: DeriveInput) -> TokenStream {
+    let mut lifetimes = input.generics.lifetimes();
+    let borrowed_lifetime = match (lifetimes.next(), lifetimes.next()) {
+        (Some(lifetime_def), None) => Some(lifetime_def.lifetime.clone()),
+        _ => None,
+    };
+
+    let owned = implement_deserialize_trait(input.clone(), borrowed_lifetime.as_ref(), false);
+    let borrowed = if borrowed_lifetime.is_some() {
+        implement_deserialize_trait(input, borrowed_lifetime.as_ref(), true)
+    } else {
+        quote! {}
+    };
+    quote! {
+        #owned
+        #borrowed
+    }
+}
+
+fn implement_deserialize_trait(input: DeriveInput, lifetime: Option<&syn::Lifetime>, is_borrowed: bool) -> TokenStream {
     let span = proc_macro2::Span::call_site();
     let defspan = proc_macro2::Span::call_site();
 
+    let borrowed = if is_borrowed { lifetime } else { None };
+
+    let mut generics = input.generics.clone();
+    if let Some(lifetime) = lifetime {
+        let field_trait = if is_borrowed {
+            quote! { _savefile::prelude::DeserializeBorrowed<#lifetime> }
+        } else {
+            quote! { _savefile::prelude::Deserialize }
+        };
+        let lifetime_field_types: Vec<_> = all_field_types(&input)
+            .into_iter()
+            .filter(|field_type| type_mentions_lifetime(field_type, lifetime))
+            .collect();
+        let where_clause = generics.make_where_clause();
+        for field_type in lifetime_field_types {
+            where_clause
+                .predicates
+                .push(syn::parse_quote!(#field_type : #field_trait));
+        }
+    }
+
     let name = input.ident;
 
-    let generics = input.generics;
     let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
     let extra_where = get_extra_where_clauses(
         &generics,
@@ -130,16 +200,34 @@ pub fn savefile_derive_crate_deserialize(input: DeriveInput) -> TokenStream {
         quote! {_savefile::prelude::Deserialize + _savefile::prelude::Packed},
     );
 
-    let deserialize = quote_spanned! {defspan=>
-        _savefile::prelude::Deserialize
+    let deserialize = if let Some(lifetime) = borrowed {
+        quote_spanned! {defspan=>
+            _savefile::prelude::DeserializeBorrowed<#lifetime>
+        }
+    } else {
+        quote_spanned! {defspan=>
+            _savefile::prelude::Deserialize
+        }
+    };
+
+    let deserialize_fn = if borrowed.is_some() {
+        quote! { deserialize_borrowed }
+    } else {
+        quote! { deserialize }
     };
 
     let uses = quote_spanned! { defspan =>
         extern crate savefile as _savefile;
     };
 
-    let deserializer = quote_spanned! {defspan=>
-        _savefile::prelude::Deserializer<impl std::io::Read>
+    let deserializer = if let Some(lifetime) = borrowed {
+        quote_spanned! {defspan=>
+            _savefile::prelude::Deserializer<&#lifetime [u8]>
+        }
+    } else {
+        quote_spanned! {defspan=>
+            _savefile::prelude::Deserializer<impl std::io::Read>
+        }
     };
 
     let saveerr = quote_spanned! {defspan=>
@@ -175,7 +263,7 @@ pub fn savefile_derive_crate_deserialize(input: DeriveInput) -> TokenStream {
                             })
                             .collect();
 
-                        let fields_deserialized = implement_deserialize(field_infos);
+                        let fields_deserialized = implement_deserialize(field_infos, borrowed);
 
                         output.push(quote!( #var_idx => #variant_name_spanned{ #(#fields_deserialized,)* } ));
                     }
@@ -192,7 +280,7 @@ pub fn savefile_derive_crate_deserialize(input: DeriveInput) -> TokenStream {
                                 attrs: &field.attrs,
                             })
                             .collect();
-                        let fields_deserialized = implement_deserialize(field_infos);
+                        let fields_deserialized = implement_deserialize(field_infos, borrowed);
 
                         output.push(quote!( #var_idx => #variant_name_spanned( #(#fields_deserialized,)*) ));
                     }
@@ -218,8 +306,7 @@ pub fn savefile_derive_crate_deserialize(input: DeriveInput) -> TokenStream {
                     #[automatically_derived]
                     impl #impl_generics #deserialize for #name #ty_generics #where_clause #extra_where {
                         #[allow(unused_comparisons, unused_variables)]
-                        fn deserialize(deserializer: &mut #deserializer) -> Result<Self,#saveerr> {
-
+                        fn #deserialize_fn(deserializer: &mut #deserializer) -> Result<Self,#saveerr> {
                             Ok(match #variant_deserializer {
                                 #(#output,)*
                                 _ => return Err(_savefile::prelude::SavefileError::GeneralError{msg:format!("Corrupt file - unknown enum variant detected.")})
@@ -245,7 +332,7 @@ pub fn savefile_derive_crate_deserialize(input: DeriveInput) -> TokenStream {
                         })
                         .collect();
 
-                    let output1 = implement_deserialize(field_infos);
+                    let output1 = implement_deserialize(field_infos, borrowed);
                     quote! {Ok(#name {
                         #(#output1,)*
                     })}
@@ -263,7 +350,7 @@ pub fn savefile_derive_crate_deserialize(input: DeriveInput) -> TokenStream {
                             attrs: &field.attrs,
                         })
                         .collect();
-                    let output1 = implement_deserialize(field_infos);
+                    let output1 = implement_deserialize(field_infos, borrowed);
 
                     quote! {Ok(#name (
                         #(#output1,)*
@@ -282,7 +369,7 @@ pub fn savefile_derive_crate_deserialize(input: DeriveInput) -> TokenStream {
                         #[automatically_derived]
                         impl #impl_generics #deserialize for #name #ty_generics #where_clause #extra_where {
                         #[allow(unused_comparisons, unused_variables)]
-                        fn deserialize(deserializer: &mut #deserializer) -> Result<Self,#saveerr> {
+                        fn #deserialize_fn(deserializer: &mut #deserializer) -> Result<Self,#saveerr> {
                             #output
                         }
                     }
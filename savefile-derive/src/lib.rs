@@ -24,7 +24,7 @@ extern crate proc_macro_error2;
 
 use common::{
     check_is_remove, compile_time_check_reprc, compile_time_size, get_extra_where_clauses, parse_attr_tag,
-    path_to_string, FieldInfo,
+    path_to_string, type_mentions_type_param, FieldInfo,
 };
 use proc_macro2::{Span, TokenTree};
 use proc_macro2::TokenStream;
@@ -516,6 +516,7 @@ pub fn savefile_abi_exportable(
                     file_version: cursor.read_u32::<LittleEndian>()?,
                     reader: &mut cursor,
                     ephemeral_state: HashMap::new(),
+                    limit_state: None,
                 };
 
                 match method_number {
@@ -1574,6 +1575,8 @@ fn implement_withschema(
     let local_version = quote_spanned! { defspan => local_version};
     let Field = quote_spanned! { defspan => _savefile::prelude::Field };
     let WithSchema = quote_spanned! { defspan => _savefile::prelude::WithSchema };
+    let FieldDefault = quote_spanned! { defspan => _savefile::prelude::FieldDefault };
+    let Serializer = quote_spanned! { defspan => _savefile::prelude::Serializer };
     let fields1 = quote_spanned! { defspan => fields1 };
 
     let structname = Ident::new(structname, defspan);
@@ -1619,6 +1622,28 @@ fn implement_withschema(
         };
         let removed = check_is_remove(field.ty);
         let field_type = &field.ty;
+        let default_val = match (&verinfo.default_val, &verinfo.default_fn) {
+            (Some(default_val), _) => Some(quote! { #default_val }),
+            (None, Some(default_fn)) => Some(quote! { #default_fn() }),
+            (None, None) => None,
+        };
+        let with_default = match default_val {
+            Some(_) if removed.is_removed() => quote! {},
+            // The WithSchema impl only requires the type parameters to implement WithSchema,
+            // so fields involving them cannot be serialized here.
+            Some(_) if type_mentions_type_param(field_type, generics) => {
+                quote! { .with_default(#FieldDefault::Unavailable) }
+            }
+            Some(default_val) => quote! {
+                .with_default(#FieldDefault::Serialized(|version| {
+                    let value: #field_type = #default_val;
+                    let mut data = std::vec::Vec::new();
+                    #Serializer::bare_serialize(&mut data, version, &value)?;
+                    Ok(data)
+                }))
+            },
+            None => quote! {},
+        };
         if field_from_version == 0 && field_to_version == u32::MAX {
             if removed.is_removed() {
                 abort!(
@@ -1626,7 +1651,7 @@ fn implement_withschema(
                     "The Removed type can only be used for removed fields. Use the savefile_version attribute."
                 );
             }
-            fields.push(quote_spanned!( span => #fields1.push(unsafe{#Field::unsafe_new(#name_str.to_string(), std::boxed::Box::new(<#field_type as #WithSchema>::schema(#local_version, context)), #offset)} )));
+            fields.push(quote_spanned!( span => #fields1.push(unsafe{#Field::unsafe_new(#name_str.to_string(), std::boxed::Box::new(<#field_type as #WithSchema>::schema(#local_version, context)), #offset)} #with_default)));
         } else {
             let mut version_mappings = Vec::new();
             let offset = if field_to_version != u32::MAX {
@@ -1650,7 +1675,7 @@ fn implement_withschema(
                 #(#version_mappings)*
 
                 if #local_version >= #field_from_version && #local_version <= #field_to_version {
-                    #fields1.push(unsafe{#Field ::unsafe_new( #name_str.to_string(), std::boxed::Box::new(<#field_type as #WithSchema>::schema(#local_version, context)), #offset )} );
+                    #fields1.push(unsafe{#Field ::unsafe_new( #name_str.to_string(), std::boxed::Box::new(<#field_type as #WithSchema>::schema(#local_version, context)), #offset )} #with_default);
                 }
                 ));
         }
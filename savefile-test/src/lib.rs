@@ -26,7 +26,9 @@ extern crate byteorder;
 extern crate indexmap;
 extern crate rand;
 extern crate rustc_hash;
+extern crate serde_json;
 extern crate smallvec;
+extern crate tokio;
 
 use indexmap::IndexMap;
 use indexmap::IndexSet;
@@ -39,12 +41,33 @@ extern crate parking_lot;
 extern crate savefile_abi;
 
 mod savefile_abi_test;
+mod test_archive;
 mod test_arrayvec;
+mod test_async;
+mod test_atomic;
+mod test_borrowed;
+mod test_checksum;
+mod test_compression;
+mod test_encryption;
 mod test_enum_many_variants;
+mod test_forward_compatible;
 mod test_generic;
+mod test_golden;
+mod test_header;
 mod test_introspect;
+mod test_json;
+mod test_lazy;
+mod test_lenient;
+mod test_limits;
 mod test_nested_non_repr_c;
 mod test_nested_repr_c;
+mod test_options;
+mod test_projection;
+mod test_schema_diff;
+mod test_schema_history;
+mod test_signing;
+mod test_stream;
+mod test_value;
 mod test_versioning;
 
 #[cfg(feature = "external_benchmarks")]
@@ -53,6 +76,8 @@ mod ext_benchmark;
 
 use std::io::BufWriter;
 use std::io::Cursor;
+use std::ops::Deref;
+use std::path::Path;
 
 pub fn assert_roundtrip<E: Serialize + Deserialize + Debug + PartialEq>(sample: E) {
     assert_roundtrip_version(sample, 0, true)
@@ -112,6 +137,37 @@ pub fn roundtrip_version<E: Serialize + Deserialize>(sample: E, version: u32) ->
     assert_eq!(f.position() as usize, f_internal_size);
     roundtrip_result
 }
+
+/// An empty scratch directory below the system temp dir, removed again when dropped.
+pub struct TestDir(PathBuf);
+
+impl Deref for TestDir {
+    type Target = Path;
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl AsRef<Path> for TestDir {
+    fn as_ref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TestDir {
+    fn drop(&mut self) {
+        _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+/// Create a fresh, empty directory for a test. `name` must be unique across all tests.
+pub fn test_dir(name: &str) -> TestDir {
+    let dir = std::env::temp_dir().join(format!("savefile_{}_{}", std::process::id(), name));
+    _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    TestDir(dir)
+}
+
 #[derive(Debug, Savefile, PartialEq)]
 pub enum TestStructEnum {
     Variant1 { a: u8, b: u8 },
@@ -1204,7 +1260,7 @@ pub fn test_encrypted_file1() {
 pub fn test_encrypted_file_bad_password() {
     save_encrypted_file("test2.bin", 1, &47usize, "mypassword").unwrap();
     let result = load_encrypted_file::<usize, _>("test2.bin", 1, "mypassword2");
-    assert!(result.is_err());
+    assert!(matches!(result, Err(SavefileError::WrongPassword)));
 }
 
 #[test]
@@ -0,0 +1,111 @@
+use savefile::prelude::*;
+use savefile::ArchiveEntry;
+use std::io::Cursor;
+
+#[derive(Savefile, Debug, PartialEq)]
+struct World {
+    name: String,
+    tiles: Vec<u8>,
+}
+
+#[derive(Savefile, Debug, PartialEq)]
+struct Player {
+    name: String,
+    #[savefile_versions = "1.."]
+    level: u32,
+}
+
+#[derive(Savefile, Debug, PartialEq)]
+struct Settings {
+    volume: f32,
+}
+
+fn sample_world() -> World {
+    World {
+        name: "Middle earth".to_string(),
+        tiles: vec![3u8; 1000],
+    }
+}
+
+fn sample_archive(compress_world: bool) -> Vec<u8> {
+    let mut writer = ArchiveWriter::new(Cursor::new(Vec::new())).unwrap();
+    if compress_world {
+        writer.add_entry_compressed("world", 0, &sample_world()).unwrap();
+    } else {
+        writer.add_entry("world", 0, &sample_world()).unwrap();
+    }
+    writer
+        .add_entry(
+            "players",
+            1,
+            &vec![Player {
+                name: "Frodo".to_string(),
+                level: 3,
+            }],
+        )
+        .unwrap();
+    writer.add_entry("settings", 0, &Settings { volume: 0.5 }).unwrap();
+    writer.finish().unwrap().into_inner()
+}
+
+#[test]
+pub fn test_archive_roundtrip() {
+    for compress in [false, true] {
+        let data = sample_archive(compress);
+        let mut reader = ArchiveReader::new(Cursor::new(data)).unwrap();
+        assert_eq!(
+            reader.entry_names().collect::<Vec<_>>(),
+            vec!["world", "players", "settings"]
+        );
+        let settings: Settings = reader.load_entry("settings", 0).unwrap();
+        assert_eq!(settings.volume, 0.5);
+        let world: World = reader.load_entry("world", 0).unwrap();
+        assert_eq!(world, sample_world());
+        let players: Vec<Player> = reader.load_entry("players", 2).unwrap();
+        assert_eq!(players[0].level, 3);
+    }
+}
+
+#[test]
+pub fn test_archive_listing() {
+    let data = sample_archive(true);
+    let reader = ArchiveReader::new(Cursor::new(data)).unwrap();
+    let world: &ArchiveEntry = reader.entry("world").unwrap();
+    assert_eq!(world.version, 0);
+    assert!(world.compressed);
+    let players = reader.entry("players").unwrap();
+    assert_eq!(players.version, 1);
+    assert!(!players.compressed);
+    assert!(reader.entries().iter().all(|x| x.size > 0));
+    assert!(reader.entry("missing").is_none());
+}
+
+#[test]
+pub fn test_archive_errors() {
+    let data = sample_archive(false);
+    let mut reader = ArchiveReader::new(Cursor::new(data)).unwrap();
+    assert!(reader.load_entry::<Settings>("missing", 0).is_err());
+    assert!(matches!(
+        reader.load_entry::<Vec<Player>>("players", 0),
+        Err(SavefileError::WrongVersion { .. })
+    ));
+    assert!(matches!(
+        reader.load_entry::<World>("settings", 0),
+        Err(SavefileError::IncompatibleSchema { .. })
+    ));
+
+    let mut writer = ArchiveWriter::new(Vec::new()).unwrap();
+    writer.add_entry("a", 0, &1u32).unwrap();
+    assert!(writer.add_entry("a", 0, &2u32).is_err());
+}
+
+#[test]
+pub fn test_archive_is_not_a_plain_savefile() {
+    let data = sample_archive(false);
+    assert!(matches!(
+        load_from_mem::<World>(&data, 0),
+        Err(SavefileError::IncompatibleSchema { .. })
+    ));
+    let plain = save_to_mem(0, &sample_world()).unwrap();
+    assert!(ArchiveReader::new(Cursor::new(plain)).is_err());
+}
@@ -0,0 +1,76 @@
+use savefile::prelude::*;
+use std::sync::Arc;
+use tokio::io::AsyncWriteExt;
+
+#[derive(Savefile, Debug, PartialEq)]
+struct Document {
+    title: String,
+    pages: Vec<u32>,
+}
+
+fn runtime() -> tokio::runtime::Runtime {
+    tokio::runtime::Builder::new_current_thread().build().unwrap()
+}
+
+fn big_document() -> Document {
+    Document {
+        title: "Large".to_string(),
+        pages: (0..1_000_000).collect(),
+    }
+}
+
+#[test]
+pub fn test_async_roundtrip() {
+    let rt = runtime();
+    let doc = Arc::new(big_document());
+    let mut buf: Vec<u8> = Vec::new();
+    rt.block_on(save_async(&mut buf, 0, doc.clone())).unwrap();
+
+    // Compatible with the blocking API
+    assert_eq!(buf, save_to_mem(0, &*doc).unwrap());
+
+    let loaded: Document = rt.block_on(load_async(&mut &buf[..], 0)).unwrap();
+    assert_eq!(&loaded, &*doc);
+}
+
+#[test]
+pub fn test_async_compressed() {
+    let rt = runtime();
+    let doc = Arc::new(big_document());
+    let mut buf: Vec<u8> = Vec::new();
+    rt.block_on(save_compressed_async(&mut buf, 0, doc.clone())).unwrap();
+    let loaded: Document = load_from_mem(&buf, 0).unwrap();
+    assert_eq!(&loaded, &*doc);
+    let loaded: Document = rt.block_on(load_async(&mut &buf[..], 0)).unwrap();
+    assert_eq!(&loaded, &*doc);
+}
+
+#[test]
+pub fn test_async_load_does_not_wait_for_eof() {
+    let rt = runtime();
+    let doc = Document {
+        title: "Small".to_string(),
+        pages: vec![1, 2, 3],
+    };
+    let (mut client, mut server) = tokio::io::duplex(1024);
+    let data = save_to_mem(0, &doc).unwrap();
+    rt.block_on(client.write_all(&data)).unwrap();
+    // 'client' is kept open, so the reader never sees the end of the input
+    let loaded: Document = rt.block_on(load_async(&mut server, 0)).unwrap();
+    assert_eq!(loaded, doc);
+    drop(client);
+}
+
+#[test]
+pub fn test_async_errors() {
+    let rt = runtime();
+    let data = save_to_mem(0, &42u32).unwrap();
+    let wrong_schema: Result<Document, _> = rt.block_on(load_async(&mut &data[..], 0));
+    match wrong_schema {
+        Err(SavefileError::IncompatibleSchema { .. }) => {}
+        other => panic!("Unexpected result: {:?}", other),
+    }
+
+    let truncated: Result<u32, _> = rt.block_on(load_async(&mut &data[..data.len() - 1], 0));
+    assert!(truncated.is_err());
+}
@@ -0,0 +1,87 @@
+use savefile::prelude::*;
+use savefile::{save_file_compressed_atomic, write_file_atomic};
+use std::path::Path;
+use test_dir;
+
+#[derive(Savefile, Debug, PartialEq)]
+struct GameState {
+    level: u32,
+    player: String,
+}
+
+fn dir_entries(dir: &Path) -> Vec<String> {
+    let mut entries: Vec<String> = std::fs::read_dir(dir)
+        .unwrap()
+        .map(|x| x.unwrap().file_name().to_string_lossy().to_string())
+        .collect();
+    entries.sort();
+    entries
+}
+
+fn state(level: u32) -> GameState {
+    GameState {
+        level,
+        player: "hero".to_string(),
+    }
+}
+
+#[test]
+#[cfg(not(miri))]
+pub fn test_atomic_save_and_overwrite() {
+    let dir = test_dir("atomic_overwrite");
+    let path = dir.join("game.sav");
+    save_file_atomic(&path, 0, &state(1), false).unwrap();
+    save_file_atomic(&path, 0, &state(2), false).unwrap();
+    let loaded: GameState = load_file(&path, 0).unwrap();
+    assert_eq!(loaded, state(2));
+    assert_eq!(dir_entries(&dir), vec!["game.sav"]);
+}
+
+#[test]
+#[cfg(not(miri))]
+pub fn test_atomic_keeps_backup() {
+    let dir = test_dir("atomic_backup");
+    let path = dir.join("game.sav");
+    save_file_atomic(&path, 0, &state(1), true).unwrap();
+    // No previous file, so no backup
+    assert_eq!(dir_entries(&dir), vec!["game.sav"]);
+    save_file_atomic(&path, 0, &state(2), true).unwrap();
+    save_file_compressed_atomic(&path, 0, &state(3), true).unwrap();
+    assert_eq!(dir_entries(&dir), vec!["game.sav", "game.sav.bak"]);
+    let loaded: GameState = load_file(&path, 0).unwrap();
+    assert_eq!(loaded, state(3));
+    let backup: GameState = load_file(dir.join("game.sav.bak"), 0).unwrap();
+    assert_eq!(backup, state(2));
+}
+
+#[test]
+#[cfg(not(miri))]
+pub fn test_atomic_failed_write_keeps_old_file() {
+    let dir = test_dir("atomic_failure");
+    let path = dir.join("game.sav");
+    save_file_atomic(&path, 0, &state(1), false).unwrap();
+    let result = write_file_atomic(&path, true, |writer| {
+        savefile::save(writer, 0, &state(2))?;
+        Err(SavefileError::GeneralError {
+            msg: "Simulated failure".into(),
+        })
+    });
+    assert!(result.is_err());
+    // Neither the temporary file nor a backup is left behind
+    assert_eq!(dir_entries(&dir), vec!["game.sav"]);
+    let loaded: GameState = load_file(&path, 0).unwrap();
+    assert_eq!(loaded, state(1));
+}
+
+#[test]
+#[cfg(not(miri))]
+pub fn test_atomic_encrypted() {
+    let dir = test_dir("atomic_encrypted");
+    let path = dir.join("game.sav");
+    save_encrypted_file_atomic(&path, 0, &state(5), "secret", true).unwrap();
+    save_encrypted_file_atomic(&path, 0, &state(6), "secret", true).unwrap();
+    let loaded: GameState = load_encrypted_file(&path, 0, "secret").unwrap();
+    assert_eq!(loaded, state(6));
+    let backup: GameState = load_encrypted_file(dir.join("game.sav.bak"), 0, "secret").unwrap();
+    assert_eq!(backup, state(5));
+}
@@ -0,0 +1,138 @@
+use savefile::prelude::*;
+use std::borrow::Cow;
+
+#[derive(Savefile, Debug, PartialEq)]
+struct Asset<'a> {
+    name: &'a str,
+    data: &'a [u8],
+    description: Cow<'a, str>,
+    tag: Option<&'a str>,
+    id: u32,
+    owned: String,
+}
+
+#[derive(Savefile, Debug, PartialEq)]
+enum Entry<'a> {
+    Empty,
+    Named { name: &'a str, value: u64 },
+    Asset(Asset<'a>),
+}
+
+#[derive(Savefile, Debug, PartialEq)]
+struct OwnedText<'a> {
+    text: Cow<'a, str>,
+}
+
+fn sample_asset() -> Asset<'static> {
+    Asset {
+        name: "grass",
+        data: &[1, 2, 3, 4],
+        description: Cow::Borrowed("A grass texture"),
+        tag: Some("terrain"),
+        id: 42,
+        owned: "owned".to_string(),
+    }
+}
+
+fn is_within(buf: &[u8], ptr: *const u8) -> bool {
+    let range = buf.as_ptr_range();
+    range.start <= ptr && ptr < range.end
+}
+
+#[test]
+pub fn test_borrowed_struct() {
+    let asset = sample_asset();
+    let buf = save_to_mem(0, &asset).unwrap();
+    let loaded: Asset = load_from_mem_borrowed(&buf, 0).unwrap();
+    assert_eq!(loaded, asset);
+    assert!(is_within(&buf, loaded.name.as_ptr()));
+    assert!(is_within(&buf, loaded.data.as_ptr()));
+    assert!(matches!(loaded.description, Cow::Borrowed(_)));
+    assert!(is_within(&buf, loaded.description.as_ptr()));
+    assert!(is_within(&buf, loaded.tag.unwrap().as_ptr()));
+}
+
+#[test]
+pub fn test_borrowed_enum() {
+    for entry in [
+        Entry::Empty,
+        Entry::Named {
+            name: "hello",
+            value: 17,
+        },
+        Entry::Asset(sample_asset()),
+    ] {
+        let buf = save_to_mem(0, &entry).unwrap();
+        let loaded: Entry = load_from_mem_borrowed(&buf, 0).unwrap();
+        assert_eq!(loaded, entry);
+    }
+}
+
+#[test]
+pub fn test_borrowed_type_is_also_owned_deserializable() {
+    let text = OwnedText {
+        text: Cow::Borrowed("some text"),
+    };
+    let buf = save_to_mem(0, &text).unwrap();
+    let loaded: OwnedText = load_from_mem(&buf, 0).unwrap();
+    assert!(matches!(loaded.text, Cow::Owned(_)));
+    let borrowed: OwnedText = load_from_mem_borrowed(&buf, 0).unwrap();
+    assert!(matches!(borrowed.text, Cow::Borrowed(_)));
+    assert_eq!(loaded, borrowed);
+}
+
+#[test]
+pub fn test_borrowed_slice_alignment() {
+    let samples: &[u32] = &[1, 2, 3, 0xdeadbeef];
+    let buf = save_to_mem(0, &samples).unwrap();
+    // Place the saved data at every possible offset relative to the alignment of u32.
+    // The slice can only be borrowed when its elements end up correctly aligned.
+    let mut storage = vec![0u32; buf.len() / 4 + 2];
+    let storage_bytes = unsafe { std::slice::from_raw_parts_mut(storage.as_mut_ptr() as *mut u8, storage.len() * 4) };
+    let mut successes = 0;
+    for offset in 0..4 {
+        storage_bytes[offset..offset + buf.len()].copy_from_slice(&buf);
+        let input = &storage_bytes[offset..offset + buf.len()];
+        if let Ok(loaded) = load_from_mem_borrowed::<&[u32]>(input, 0) {
+            assert_eq!(loaded, samples);
+            assert!(is_within(input, loaded.as_ptr() as *const u8));
+            successes += 1;
+        }
+    }
+    assert_eq!(successes, 1);
+}
+
+#[test]
+pub fn test_borrowed_rejects_truncated_and_compressed() {
+    let asset = sample_asset();
+    let buf = save_to_mem(0, &asset).unwrap();
+    assert!(load_from_mem_borrowed::<Asset>(&buf[..buf.len() - 1], 0).is_err());
+
+    let mut compressed = Vec::new();
+    savefile::save_compressed(&mut compressed, 0, &asset).unwrap();
+    assert!(load_from_mem_borrowed::<Asset>(&compressed, 0).is_err());
+}
+
+#[derive(Savefile, Debug, PartialEq)]
+struct Inner {
+    x: u32,
+}
+
+#[derive(Savefile, Debug, PartialEq)]
+pub struct WithPhantom<'a> {
+    value: u32,
+    inner: Inner,
+    marker: std::marker::PhantomData<&'a ()>,
+}
+
+#[test]
+pub fn test_lifetime_without_borrowed_fields() {
+    let value = WithPhantom {
+        value: 3,
+        inner: Inner { x: 4 },
+        marker: std::marker::PhantomData,
+    };
+    let buf = save_to_mem(0, &value).unwrap();
+    let loaded: WithPhantom = load_from_mem(&buf, 0).unwrap();
+    assert_eq!(loaded, value);
+}
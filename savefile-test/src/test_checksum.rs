@@ -0,0 +1,128 @@
+use savefile::prelude::*;
+use savefile::{load_file, save_file_with_checksum, save_with_checksum};
+use std::io::Cursor;
+
+#[derive(Savefile, Debug, PartialEq)]
+struct Measurements {
+    station: String,
+    values: Vec<u32>,
+    calibrated: bool,
+}
+
+fn measurements() -> Measurements {
+    Measurements {
+        station: "north".to_string(),
+        values: vec![17, 4711, 0, 99],
+        calibrated: true,
+    }
+}
+
+const ALGORITHMS: [ChecksumAlgorithm; 2] = [ChecksumAlgorithm::Crc32c, ChecksumAlgorithm::XxHash64];
+
+/// Header (15 bytes) + compression byte + checksum type byte
+const PAYLOAD_START: usize = 17;
+
+#[test]
+pub fn test_checksum_roundtrip() {
+    for algorithm in ALGORITHMS {
+        let mut buf = Vec::new();
+        save_with_checksum(&mut buf, 0, &measurements(), algorithm).unwrap();
+        assert_eq!(buf.len(), save_to_mem(0, &measurements()).unwrap().len() + 9);
+        let loaded: Measurements = load_from_mem(&buf, 0).unwrap();
+        assert_eq!(loaded, measurements());
+    }
+}
+
+#[test]
+#[cfg(not(miri))]
+pub fn test_checksum_file_roundtrip() {
+    save_file_with_checksum("test_checksum.bin", 0, &measurements(), ChecksumAlgorithm::Crc32c).unwrap();
+    let loaded: Measurements = load_file("test_checksum.bin", 0).unwrap();
+    assert_eq!(loaded, measurements());
+}
+
+#[derive(Savefile, Debug, PartialEq)]
+struct Reading {
+    sensor: u16,
+    value: f64,
+    timestamp: u64,
+    valid: bool,
+}
+
+#[test]
+pub fn test_checksum_detects_bit_flips() {
+    let reading = Reading {
+        sensor: 3,
+        value: 21.5,
+        timestamp: 1_700_000_000,
+        valid: true,
+    };
+    for algorithm in ALGORITHMS {
+        let mut buf = Vec::new();
+        save_with_checksum(&mut buf, 0, &reading, algorithm).unwrap();
+        for pos in PAYLOAD_START..buf.len() {
+            for bit in 0..8 {
+                let mut corrupt = buf.clone();
+                corrupt[pos] ^= 1 << bit;
+                match load_from_mem::<Reading>(&corrupt, 0) {
+                    Err(SavefileError::ChecksumMismatch { expected, actual }) => {
+                        assert_ne!(expected, actual);
+                    }
+                    other => panic!("Flip at {}:{} was not detected: {:?}", pos, bit, other),
+                }
+            }
+        }
+    }
+}
+
+#[test]
+pub fn test_checksum_detects_corrupt_length() {
+    let mut buf = Vec::new();
+    save_with_checksum(&mut buf, 0, &measurements(), ChecksumAlgorithm::Crc32c).unwrap();
+    // The length of 'values' is stored just before its 16 bytes of data, followed by the bool and the checksum.
+    let len_pos = buf.len() - 8 - 1 - 16 - 8;
+    assert_eq!(buf[len_pos], 4);
+    for corrupt_len in [3, 5, 6] {
+        let mut corrupt = buf.clone();
+        corrupt[len_pos] = corrupt_len;
+        match load_from_mem::<Measurements>(&corrupt, 0) {
+            Err(SavefileError::ChecksumMismatch { .. }) => {}
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+}
+
+#[test]
+pub fn test_checksum_keeps_schema_errors() {
+    let mut buf = Vec::new();
+    save_with_checksum(&mut buf, 0, &measurements(), ChecksumAlgorithm::Crc32c).unwrap();
+    match load_from_mem::<u32>(&buf, 0) {
+        Err(SavefileError::IncompatibleSchema { .. }) => {}
+        other => panic!("Unexpected result: {:?}", other),
+    }
+}
+
+#[test]
+pub fn test_checksum_error_does_not_read_past_payload() {
+    let mut buf = Vec::new();
+    save_with_checksum(&mut buf, 0, &measurements(), ChecksumAlgorithm::Crc32c).unwrap();
+    // Make the station name invalid utf8, so loading fails before reaching the checksum
+    let name_pos = buf.windows(5).position(|x| x == b"north").unwrap();
+    buf[name_pos] = 0xff;
+    let file_len = buf.len();
+    assert!(matches!(
+        load_from_mem::<Measurements>(&buf, 0),
+        Err(SavefileError::ChecksumMismatch { .. })
+    ));
+
+    // A reader may have more data after the file, which is not the checksum,
+    // or may be a socket which is never closed. It is not read to the end.
+    buf.extend_from_slice(&[0; 100]);
+    let mut reader = Cursor::new(&buf[..]);
+    match load::<Measurements>(&mut reader, 0) {
+        Err(SavefileError::ChecksumMismatch { .. }) => panic!("Trailing data was taken for the checksum"),
+        Err(_) => {}
+        Ok(_) => panic!("Corrupt data was loaded"),
+    }
+    assert!(reader.position() <= file_len as u64);
+}
@@ -0,0 +1,136 @@
+use savefile::prelude::*;
+use savefile::{
+    read_header, recompress, save_compressed, save_compressed_with, save_with_checksum, save_with_metadata,
+    FileMetadata,
+};
+
+#[derive(Savefile, Debug, PartialEq)]
+struct Autosave {
+    name: String,
+    cells: Vec<u32>,
+}
+
+fn autosave() -> Autosave {
+    Autosave {
+        name: "autosave".to_string(),
+        cells: (0..100_000).map(|x| x % 17).collect(),
+    }
+}
+
+const ALL_CODECS: [CompressionCodec; 4] = [
+    CompressionCodec::Bzip2,
+    CompressionCodec::Zstd,
+    CompressionCodec::Lz4,
+    CompressionCodec::Deflate,
+];
+
+#[test]
+pub fn test_compression_codecs_roundtrip() {
+    let data = autosave();
+    let uncompressed = save_to_mem(0, &data).unwrap();
+    for codec in ALL_CODECS {
+        let mut buf = Vec::new();
+        save_compressed_with(&mut buf, 0, &data, CompressionOptions::new(codec)).unwrap();
+        assert!(buf.len() < uncompressed.len() / 4, "{} did not compress", codec);
+        let loaded: Autosave = load_from_mem(&buf, 0).unwrap();
+        assert_eq!(loaded, data);
+    }
+}
+
+#[test]
+pub fn test_compression_levels() {
+    let data = autosave();
+    for (codec, level) in [
+        (CompressionCodec::Bzip2, 1),
+        (CompressionCodec::Bzip2, 100),
+        (CompressionCodec::Zstd, 0),
+        (CompressionCodec::Zstd, 19),
+        (CompressionCodec::Lz4, 5),
+        (CompressionCodec::Deflate, 0),
+        (CompressionCodec::Deflate, 9),
+    ] {
+        let mut buf = Vec::new();
+        save_compressed_with(&mut buf, 0, &data, CompressionOptions::new(codec).with_level(level)).unwrap();
+        let loaded: Autosave = load_from_mem(&buf, 0).unwrap();
+        assert_eq!(loaded, data);
+    }
+}
+
+#[test]
+pub fn test_compression_header_byte() {
+    let data = 42u32;
+    let mut legacy = Vec::new();
+    save_compressed(&mut legacy, 0, &data).unwrap();
+    // The compression byte follows the magic, the library version and the file version.
+    assert_eq!(legacy[15], 1);
+    let mut default = Vec::new();
+    save_compressed_with(&mut default, 0, &data, CompressionOptions::default()).unwrap();
+    assert_eq!(legacy, default);
+
+    for (codec, byte) in ALL_CODECS.iter().zip(1u8..) {
+        let mut buf = Vec::new();
+        save_compressed_with(&mut buf, 0, &data, CompressionOptions::new(*codec)).unwrap();
+        assert_eq!(buf[15], byte);
+    }
+}
+
+fn header_lib_version(buf: &[u8]) -> u16 {
+    u16::from_le_bytes([buf[9], buf[10]])
+}
+
+#[test]
+pub fn test_compression_header_lib_version() {
+    let data = 42u32;
+    // Files readable by savefile versions predating the new codecs keep the old version
+    assert_eq!(header_lib_version(&save_to_mem(0, &data).unwrap()), 1);
+    let mut legacy = Vec::new();
+    save_compressed(&mut legacy, 0, &data).unwrap();
+    assert_eq!(header_lib_version(&legacy), 1);
+
+    // Those versions would take any other compression byte for bzip2
+    for codec in &ALL_CODECS[1..] {
+        let mut buf = Vec::new();
+        save_compressed_with(&mut buf, 0, &data, CompressionOptions::new(*codec)).unwrap();
+        assert_eq!(header_lib_version(&buf), 2, "{}", codec);
+        assert_eq!(read_header(&mut &buf[..]).unwrap().savefile_lib_version, 2);
+        assert_eq!(load_from_mem::<u32>(&buf, 0).unwrap(), 42);
+
+        let mut recompressed = Vec::new();
+        recompress(&mut &legacy[..], &mut recompressed, Some(CompressionOptions::new(*codec))).unwrap();
+        assert_eq!(header_lib_version(&recompressed), 2);
+    }
+    let mut buf = Vec::new();
+    save_with_checksum(&mut buf, 0, &data, ChecksumAlgorithm::Crc32c).unwrap();
+    assert_eq!(header_lib_version(&buf), 2);
+    let mut buf = Vec::new();
+    save_with_metadata(&mut buf, 0, &data, &FileMetadata::new("test")).unwrap();
+    assert_eq!(header_lib_version(&buf), 2);
+
+    buf[9] = 3;
+    match load_from_mem::<u32>(&buf, 0) {
+        Err(SavefileError::GeneralError { msg }) => assert!(msg.contains("future, incompatible version")),
+        other => panic!("Unexpected result: {:?}", other),
+    }
+}
+
+#[test]
+pub fn test_compression_unknown_codec() {
+    let mut buf = Vec::new();
+    save_compressed_with(&mut buf, 0, &42u32, CompressionOptions::new(CompressionCodec::Zstd)).unwrap();
+    buf[15] = 100;
+    match load_from_mem::<u32>(&buf, 0) {
+        Err(SavefileError::GeneralError { msg }) => assert!(msg.contains("unknown compression codec")),
+        other => panic!("Unexpected result: {:?}", other),
+    }
+}
+
+#[test]
+pub fn test_compression_not_compiled_in_message() {
+    let err = SavefileError::CompressionSupportNotCompiledIn {
+        codec: CompressionCodec::Zstd,
+    };
+    assert_eq!(
+        err.to_string(),
+        "Compression support missing - recompile with zstd feature enabled."
+    );
+}
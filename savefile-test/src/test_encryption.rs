@@ -0,0 +1,307 @@
+use savefile::prelude::*;
+use savefile::{SavefileError, Serializer};
+use std::io::Write;
+use std::path::PathBuf;
+use test_dir;
+
+#[derive(Savefile, Debug, PartialEq)]
+struct Secret {
+    id: u32,
+    text: String,
+}
+
+fn secret() -> Secret {
+    Secret {
+        id: 42,
+        text: "attack at dawn".to_string(),
+    }
+}
+
+/// Keep the tests fast. The default iteration count is slow in debug builds.
+fn fast_kdf() -> KdfParams {
+    KdfParams { iterations: 1000 }
+}
+
+#[test]
+#[cfg(not(miri))]
+fn test_encrypted_roundtrip_with_kdf() {
+    let dir = test_dir("encryption_roundtrip");
+    let path = dir.join("secret.bin");
+    save_encrypted_file_with_kdf(&path, 1, &secret(), "hunter2", fast_kdf()).unwrap();
+    let loaded: Secret = load_encrypted_file(&path, 1, "hunter2").unwrap();
+    assert_eq!(loaded, secret());
+}
+
+#[test]
+#[cfg(not(miri))]
+fn test_encrypted_wrong_password() {
+    let dir = test_dir("encryption_wrong_password");
+    let path = dir.join("secret.bin");
+    save_encrypted_file_with_kdf(&path, 1, &secret(), "hunter2", fast_kdf()).unwrap();
+    let result = load_encrypted_file::<Secret, _>(&path, 1, "hunter3");
+    assert!(matches!(result, Err(SavefileError::WrongPassword)));
+}
+
+#[test]
+#[cfg(not(miri))]
+fn test_encrypted_same_password_different_salt() {
+    let dir = test_dir("encryption_salt");
+    save_encrypted_file_with_kdf(dir.join("a.bin"), 1, &secret(), "hunter2", fast_kdf()).unwrap();
+    save_encrypted_file_with_kdf(dir.join("b.bin"), 1, &secret(), "hunter2", fast_kdf()).unwrap();
+    let a = std::fs::read(dir.join("a.bin")).unwrap();
+    let b = std::fs::read(dir.join("b.bin")).unwrap();
+    assert_ne!(a, b);
+    // The salt follows the magic, header version, kdf id and iteration count
+    assert_ne!(a[22..38], b[22..38]);
+}
+
+#[test]
+#[cfg(not(miri))]
+fn test_encrypted_zero_iterations_rejected() {
+    let dir = test_dir("encryption_zero_iterations");
+    let path = dir.join("secret.bin");
+    let result = save_encrypted_file_with_kdf(&path, 1, &secret(), "hunter2", KdfParams { iterations: 0 });
+    assert!(result.is_err());
+}
+
+#[test]
+#[cfg(not(miri))]
+fn test_encrypted_excessive_iterations_rejected() {
+    let dir = test_dir("encryption_excessive_iterations");
+    let path = dir.join("secret.bin");
+    let too_many = KdfParams {
+        iterations: KdfParams::MAX_ITERATIONS + 1,
+    };
+    assert!(save_encrypted_file_with_kdf(&path, 1, &secret(), "hunter2", too_many).is_err());
+
+    // A crafted file must not make loading derive a key for hours
+    save_encrypted_file_with_kdf(&path, 1, &secret(), "hunter2", fast_kdf()).unwrap();
+    let mut data = std::fs::read(&path).unwrap();
+    // The iteration count follows the magic, header version and kdf id
+    data[18..22].copy_from_slice(&u32::MAX.to_le_bytes());
+    std::fs::write(&path, &data).unwrap();
+    match load_encrypted_file::<Secret, _>(&path, 1, "hunter2") {
+        Err(SavefileError::GeneralError { msg }) => assert!(msg.contains("exceeds the maximum"), "{}", msg),
+        other => panic!("Unexpected result: {:?}", other),
+    }
+}
+
+/// SHA256 of "legacy password", which is how keys were derived before the kdf header was added
+const LEGACY_KEY: [u8; 32] = [
+    0xc7, 0x2d, 0x01, 0xa2, 0xfd, 0x0d, 0xd7, 0x2b, 0x3e, 0xb2, 0xff, 0x6b, 0xf0, 0x72, 0x4f, 0xe7, 0x22, 0xe5, 0x77,
+    0x74, 0x38, 0xee, 0x9d, 0x84, 0xd9, 0x3d, 0x6c, 0x5a, 0x14, 0xaa, 0x7b, 0xf0,
+];
+
+fn write_legacy_file(path: &PathBuf) {
+    let mut f = std::fs::File::create(path).unwrap();
+    let mut writer = CryptoWriter::new(&mut f, LEGACY_KEY).unwrap();
+    Serializer::save(&mut writer, 1, &secret(), true).unwrap();
+    writer.flush().unwrap();
+}
+
+#[test]
+#[cfg(not(miri))]
+fn test_encrypted_load_legacy_file() {
+    let dir = test_dir("encryption_legacy");
+    let path = dir.join("secret.bin");
+    write_legacy_file(&path);
+    let loaded: Secret = load_encrypted_file(&path, 1, "legacy password").unwrap();
+    assert_eq!(loaded, secret());
+}
+
+#[test]
+#[cfg(not(miri))]
+fn test_encrypted_load_legacy_file_wrong_password() {
+    let dir = test_dir("encryption_legacy_wrong_password");
+    let path = dir.join("secret.bin");
+    write_legacy_file(&path);
+    let result = load_encrypted_file::<Secret, _>(&path, 1, "other password");
+    assert!(matches!(result, Err(SavefileError::WrongPassword)));
+}
+
+#[test]
+#[cfg(not(miri))]
+fn test_encrypted_truncated_files() {
+    let dir = test_dir("encryption_truncated");
+    save_encrypted_file_with_kdf(dir.join("full.bin"), 1, &secret(), "hunter2", fast_kdf()).unwrap();
+    let full = std::fs::read(dir.join("full.bin")).unwrap();
+    for len in [0, 5, 16, 20, 40, 70, 80, full.len() - 1] {
+        let path = dir.join(format!("truncated{}.bin", len));
+        std::fs::write(&path, &full[..len]).unwrap();
+        assert!(load_encrypted_file::<Secret, _>(&path, 1, "hunter2").is_err());
+    }
+}
+
+const KEY: [u8; 32] = [7; 32];
+
+#[test]
+#[cfg(not(miri))]
+fn test_encrypted_stream_roundtrip_raw_key() {
+    let mut buf = Vec::new();
+    save_encrypted(&mut buf, 1, &secret(), &KEY, b"").unwrap();
+    let loaded: Secret = load_encrypted(&mut &buf[..], 1, &KEY, b"").unwrap();
+    assert_eq!(loaded, secret());
+}
+
+#[test]
+#[cfg(not(miri))]
+fn test_encrypted_mem_wrong_key() {
+    let data = save_encrypted_to_mem(1, &secret(), &KEY, b"").unwrap();
+    let result = load_encrypted_from_mem::<Secret>(&data, 1, &[8; 32], b"");
+    assert!(matches!(result, Err(SavefileError::WrongPassword)));
+}
+
+#[test]
+#[cfg(not(miri))]
+fn test_encrypted_mem_aad() {
+    let data = save_encrypted_to_mem(1, &secret(), &KEY, b"user 1").unwrap();
+    let loaded: Secret = load_encrypted_from_mem(&data, 1, &KEY, b"user 1").unwrap();
+    assert_eq!(loaded, secret());
+    assert!(load_encrypted_from_mem::<Secret>(&data, 1, &KEY, b"user 2").is_err());
+    assert!(load_encrypted_from_mem::<Secret>(&data, 1, &KEY, b"").is_err());
+}
+
+#[test]
+#[cfg(not(miri))]
+fn test_encrypted_mem_tampered() {
+    let mut data = save_encrypted_to_mem(1, &secret(), &KEY, b"").unwrap();
+    let last = data.len() - 1;
+    data[last] ^= 1;
+    assert!(load_encrypted_from_mem::<Secret>(&data, 1, &KEY, b"").is_err());
+}
+
+/// Key provider which supports key rotation, keeping old keys for decryption
+struct Keyring {
+    current: &'static str,
+    keys: Vec<(&'static str, [u8; 32])>,
+}
+
+impl KeyProvider for Keyring {
+    fn encryption_key(&self) -> Result<(Vec<u8>, [u8; 32]), SavefileError> {
+        let id = self.current.as_bytes().to_vec();
+        let key = self.decryption_key(&id)?;
+        Ok((id, key))
+    }
+    fn decryption_key(&self, key_id: &[u8]) -> Result<[u8; 32], SavefileError> {
+        self.keys
+            .iter()
+            .find(|(id, _)| id.as_bytes() == key_id)
+            .map(|(_, key)| *key)
+            .ok_or_else(|| SavefileError::GeneralError {
+                msg: "unknown key".into(),
+            })
+    }
+}
+
+#[test]
+#[cfg(not(miri))]
+fn test_encrypted_key_provider_rotation() {
+    let mut keyring = Keyring {
+        current: "2023",
+        keys: vec![("2023", [1; 32])],
+    };
+    let old = save_encrypted_to_mem(1, &secret(), &keyring, b"").unwrap();
+
+    keyring.keys.push(("2024", [2; 32]));
+    keyring.current = "2024";
+    let new = save_encrypted_to_mem(1, &secret(), &keyring, b"").unwrap();
+
+    assert_eq!(
+        load_encrypted_from_mem::<Secret>(&old, 1, &keyring, b"").unwrap(),
+        secret()
+    );
+    assert_eq!(
+        load_encrypted_from_mem::<Secret>(&new, 1, &keyring, b"").unwrap(),
+        secret()
+    );
+
+    // A raw key cannot be used for data encrypted with a key id
+    assert!(load_encrypted_from_mem::<Secret>(&new, 1, &[2; 32], b"").is_err());
+}
+
+#[test]
+#[cfg(not(miri))]
+fn test_encrypted_key_and_password_not_mixed() {
+    let dir = test_dir("encryption_not_mixed");
+    let path = dir.join("secret.bin");
+    std::fs::write(&path, save_encrypted_to_mem(1, &secret(), &KEY, b"").unwrap()).unwrap();
+    assert!(load_encrypted_file::<Secret, _>(&path, 1, "hunter2").is_err());
+
+    save_encrypted_file_with_kdf(&path, 1, &secret(), "hunter2", fast_kdf()).unwrap();
+    let data = std::fs::read(&path).unwrap();
+    assert!(load_encrypted_from_mem::<Secret>(&data, 1, &KEY, b"").is_err());
+}
+
+#[test]
+#[cfg(not(miri))]
+fn test_encrypted_large_stream_aad() {
+    // Spans several encrypted chunks
+    let big: Vec<u64> = (0..100_000).collect();
+    let data = save_encrypted_to_mem(1, &big, &KEY, b"blob 17").unwrap();
+    let loaded: Vec<u64> = load_encrypted_from_mem(&data, 1, &KEY, b"blob 17").unwrap();
+    assert_eq!(loaded, big);
+    assert!(load_encrypted_from_mem::<Vec<u64>>(&data, 1, &KEY, b"blob 18").is_err());
+}
+
+#[test]
+#[cfg(not(miri))]
+fn test_encrypted_for_recipients() {
+    let backend = RecipientSecretKey::generate();
+    let other = RecipientSecretKey::generate();
+    let mut data = Vec::new();
+    save_encrypted_for_recipients(&mut data, 1, &secret(), &[backend.public_key()], b"").unwrap();
+
+    let loaded: Secret = load_encrypted_as_recipient(&mut &data[..], 1, &backend, b"").unwrap();
+    assert_eq!(loaded, secret());
+
+    let result = load_encrypted_as_recipient::<Secret>(&mut &data[..], 1, &other, b"");
+    assert!(matches!(result, Err(SavefileError::WrongPassword)));
+}
+
+#[test]
+#[cfg(not(miri))]
+fn test_encrypted_for_several_recipients() {
+    let recipients: Vec<RecipientSecretKey> = (0..3).map(|_| RecipientSecretKey::generate()).collect();
+    let public_keys: Vec<RecipientPublicKey> = recipients.iter().map(|x| x.public_key()).collect();
+    let mut data = Vec::new();
+    save_encrypted_for_recipients(&mut data, 1, &secret(), &public_keys, b"report").unwrap();
+    for recipient in &recipients {
+        let loaded: Secret = load_encrypted_as_recipient(&mut &data[..], 1, recipient, b"report").unwrap();
+        assert_eq!(loaded, secret());
+        assert!(load_encrypted_as_recipient::<Secret>(&mut &data[..], 1, recipient, b"other").is_err());
+    }
+}
+
+#[test]
+#[cfg(not(miri))]
+fn test_recipient_keys_from_bytes() {
+    let secret_key = RecipientSecretKey::generate();
+    let restored = RecipientSecretKey::from_bytes(secret_key.to_bytes());
+    let public_key = RecipientPublicKey::from_bytes(secret_key.public_key().to_bytes());
+    assert_eq!(restored.public_key(), public_key);
+
+    let mut data = Vec::new();
+    save_encrypted_for_recipients(&mut data, 1, &secret(), &[public_key], b"").unwrap();
+    let loaded: Secret = load_encrypted_as_recipient(&mut &data[..], 1, &restored, b"").unwrap();
+    assert_eq!(loaded, secret());
+}
+
+#[test]
+#[cfg(not(miri))]
+fn test_encrypted_for_recipients_errors() {
+    let mut data = Vec::new();
+    assert!(save_encrypted_for_recipients(&mut data, 1, &secret(), &[], b"").is_err());
+
+    // A low order point would give a predictable shared secret
+    let mut data = Vec::new();
+    let low_order = RecipientPublicKey::from_bytes([0; 32]);
+    assert!(save_encrypted_for_recipients(&mut data, 1, &secret(), &[low_order], b"").is_err());
+
+    // Recipient data cannot be loaded with a raw key, and vice versa
+    let backend = RecipientSecretKey::generate();
+    let mut data = Vec::new();
+    save_encrypted_for_recipients(&mut data, 1, &secret(), &[backend.public_key()], b"").unwrap();
+    assert!(load_encrypted_from_mem::<Secret>(&data, 1, &KEY, b"").is_err());
+    let data = save_encrypted_to_mem(1, &secret(), &KEY, b"").unwrap();
+    assert!(load_encrypted_as_recipient::<Secret>(&mut &data[..], 1, &backend, b"").is_err());
+}
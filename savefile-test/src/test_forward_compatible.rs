@@ -0,0 +1,131 @@
+use savefile::prelude::*;
+use savefile::{load_forward_compatible, SavefileError};
+
+#[derive(Savefile, Debug, PartialEq)]
+enum Weather {
+    Sunny,
+    Rain { millimeters: u32 },
+}
+
+#[derive(Savefile, Debug, PartialEq)]
+struct Forecast {
+    city: String,
+    days: Vec<Weather>,
+}
+
+/// `Forecast`, as a newer version of the program has it
+#[derive(Savefile, Debug, PartialEq)]
+enum WeatherV2 {
+    Sunny,
+    Rain {
+        millimeters: u32,
+        #[savefile_versions = "2.."]
+        thunder: bool,
+    },
+    #[savefile_versions = "2.."]
+    Snow,
+}
+
+#[derive(Savefile, Debug, PartialEq)]
+struct ForecastV2 {
+    city: String,
+    days: Vec<WeatherV2>,
+    #[savefile_versions = "2.."]
+    source: Option<String>,
+}
+
+/// A newer version which inserted a field before the existing ones
+#[derive(Savefile, Debug, PartialEq)]
+struct ForecastInserted {
+    #[savefile_versions = "2.."]
+    country: String,
+    city: String,
+    days: Vec<Weather>,
+}
+
+fn forecast_v2(days: Vec<WeatherV2>) -> Vec<u8> {
+    let mut data = Vec::new();
+    save(
+        &mut data,
+        2,
+        &ForecastV2 {
+            city: "Oslo".to_string(),
+            days,
+            source: Some("radar".to_string()),
+        },
+    )
+    .unwrap();
+    data
+}
+
+#[test]
+fn test_forward_compatible_skips_added_fields() {
+    let data = forecast_v2(vec![
+        WeatherV2::Sunny,
+        WeatherV2::Rain {
+            millimeters: 4,
+            thunder: true,
+        },
+    ]);
+    match load::<Forecast>(&mut &data[..], 1) {
+        Err(SavefileError::WrongVersion { .. }) => {}
+        other => panic!("Expected WrongVersion, got {:?}", other),
+    }
+    let forecast: Forecast = load_forward_compatible(&mut &data[..], 1).unwrap();
+    assert_eq!(
+        forecast,
+        Forecast {
+            city: "Oslo".to_string(),
+            days: vec![Weather::Sunny, Weather::Rain { millimeters: 4 }],
+        }
+    );
+}
+
+#[test]
+fn test_forward_compatible_fails_on_present_unknown_variant() {
+    let data = forecast_v2(vec![WeatherV2::Sunny, WeatherV2::Snow]);
+    let err = load_forward_compatible::<Forecast>(&mut &data[..], 1).unwrap_err();
+    assert!(
+        err.to_string()
+            .contains("$.days[1]: enum Weather in memory has no variant Snow"),
+        "{}",
+        err
+    );
+}
+
+#[test]
+fn test_forward_compatible_rejects_other_changes() {
+    let mut data = Vec::new();
+    let forecast = ForecastInserted {
+        country: "Norway".to_string(),
+        city: "Oslo".to_string(),
+        days: vec![],
+    };
+    save(&mut data, 2, &forecast).unwrap();
+    let err = load_forward_compatible::<Forecast>(&mut &data[..], 1).unwrap_err();
+    assert!(
+        err.to_string()
+            .contains("the file has field 'country' where memory has field 'city'"),
+        "{}",
+        err
+    );
+}
+
+#[test]
+fn test_forward_compatible_loads_older_files_normally() {
+    let forecast = Forecast {
+        city: "Bergen".to_string(),
+        days: vec![Weather::Rain { millimeters: 30 }],
+    };
+    let mut data = Vec::new();
+    save(&mut data, 1, &forecast).unwrap();
+    let loaded: ForecastV2 = load_forward_compatible(&mut &data[..], 2).unwrap();
+    assert_eq!(loaded.source, None);
+    assert_eq!(
+        loaded.days,
+        vec![WeatherV2::Rain {
+            millimeters: 30,
+            thunder: false
+        }]
+    );
+}
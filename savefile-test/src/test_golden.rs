@@ -0,0 +1,73 @@
+use savefile::prelude::*;
+use savefile::{load_golden_files, verify_golden_files};
+use test_dir;
+use test_schema_history::SavedGame;
+
+#[test]
+#[cfg(not(miri))]
+fn test_golden_files_of_checked_in_type() {
+    let sample = SavedGame {
+        player: "ace".to_string(),
+        level: 3,
+        inventory: vec!["sword".to_string()],
+    };
+    verify_golden_files("golden", 1, &sample).unwrap();
+}
+
+mod released {
+    #[derive(Savefile, Debug, PartialEq)]
+    pub struct Settings {
+        pub volume: u8,
+    }
+}
+
+mod current {
+    #[derive(Savefile, Debug, PartialEq)]
+    pub struct Settings {
+        pub volume: u8,
+        #[savefile_versions = "1.."]
+        #[savefile_default_val = "7"]
+        pub balance: u8,
+    }
+}
+
+mod buggy {
+    #[derive(Savefile, Debug, PartialEq)]
+    pub struct Settings {
+        pub volume: u8,
+        #[savefile_versions = "1.."]
+        pub balance: u8,
+    }
+}
+
+#[test]
+#[cfg(not(miri))]
+fn test_golden_files_detect_changed_default() {
+    let dir = test_dir("golden_default");
+    verify_golden_files(&dir, 0, &released::Settings { volume: 3 }).unwrap();
+    assert!(dir.join("savefile_Settings_0.golden").exists());
+    assert_eq!(
+        std::fs::read_to_string(dir.join("savefile_Settings_0.loaded_by_0.txt")).unwrap(),
+        "$ = Settings\n$.volume = 3\n"
+    );
+
+    let sample = current::Settings { volume: 5, balance: 1 };
+    verify_golden_files(&dir, 1, &sample).unwrap();
+    verify_golden_files(&dir, 1, &sample).unwrap();
+    assert_eq!(
+        load_golden_files::<current::Settings>(&dir, 1).unwrap(),
+        vec![(0, current::Settings { volume: 3, balance: 7 }), (1, sample)]
+    );
+
+    let buggy_sample = buggy::Settings { volume: 5, balance: 1 };
+    match verify_golden_files(&dir, 1, &buggy_sample) {
+        Err(SavefileError::GeneralError { msg }) => {
+            assert!(
+                msg.contains("savefile_Settings_0.golden no longer loads as recorded"),
+                "{}",
+                msg
+            );
+        }
+        other => panic!("Expected golden file mismatch, got {:?}", other),
+    }
+}
@@ -0,0 +1,83 @@
+use savefile::prelude::*;
+use savefile::{
+    inspect, load_lazy, read_header, recompress, save_compressed_with, save_with_metadata, FileMetadata, Lazy,
+};
+use std::io::Cursor;
+use std::time::{Duration, SystemTime};
+
+#[derive(Savefile, Debug)]
+struct Map {
+    name: String,
+    heights: Lazy<Vec<u8>>,
+}
+
+fn map() -> Map {
+    Map {
+        name: "Island".to_string(),
+        heights: Lazy::new(vec![1, 2, 3]),
+    }
+}
+
+fn metadata() -> FileMetadata {
+    FileMetadata {
+        app_name: "Mapper".to_string(),
+        created: Some(SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000)),
+        description: "A small island".to_string(),
+        thumbnail: vec![0x89, b'P', b'N', b'G'],
+    }
+}
+
+#[test]
+fn test_read_header_and_inspect() {
+    let mut data = Vec::new();
+    save_compressed_with(&mut data, 3, &map(), CompressionOptions::new(CompressionCodec::Zstd)).unwrap();
+
+    let header = read_header(&mut &data[..]).unwrap();
+    assert_eq!(header.version, 3);
+    assert_eq!(header.savefile_lib_version, 2);
+    assert!(header.is_compressed());
+    assert_eq!(header.compression, Some(CompressionCodec::Zstd));
+    assert_eq!(header.checksum, None);
+    assert_eq!(header.metadata, None);
+
+    let info = inspect(&mut &data[..]).unwrap();
+    assert_eq!(info.header, header);
+    assert_eq!(info.schema, get_schema::<Map>(3));
+}
+
+#[test]
+fn test_metadata() {
+    let mut data = Vec::new();
+    save_with_metadata(&mut data, 1, &map(), &metadata()).unwrap();
+
+    let header = read_header(&mut &data[..]).unwrap();
+    assert!(!header.is_compressed());
+    assert_eq!(header.metadata, Some(metadata()));
+    assert_eq!(inspect(&mut &data[..]).unwrap().schema, get_schema::<Map>(1));
+
+    // The metadata is skipped when loading, also lazily
+    let mut loaded: Map = load_from_mem(&data, 1).unwrap();
+    assert_eq!(loaded.heights.get().unwrap(), &vec![1, 2, 3]);
+    let mut reader = Cursor::new(&data[..]);
+    let mut loaded: Map = load_lazy(&mut reader, 1).unwrap();
+    assert!(!loaded.heights.is_loaded());
+    assert_eq!(loaded.heights.get_from(&mut reader).unwrap(), &vec![1, 2, 3]);
+    let mut text = Vec::new();
+    save_with_metadata(&mut text, 0, &"Island".to_string(), &metadata()).unwrap();
+    let borrowed: &str = load_from_mem_borrowed(&text, 0).unwrap();
+    assert_eq!(borrowed, "Island");
+
+    // Recompression keeps the metadata
+    let mut compressed = Vec::new();
+    recompress(
+        &mut &data[..],
+        &mut compressed,
+        Some(CompressionOptions::new(CompressionCodec::Lz4)),
+    )
+    .unwrap();
+    let header = read_header(&mut &compressed[..]).unwrap();
+    assert_eq!(header.compression, Some(CompressionCodec::Lz4));
+    assert_eq!(header.metadata, Some(metadata()));
+    let loaded: Map = load_from_mem(&compressed, 1).unwrap();
+    assert_eq!(loaded.name, "Island");
+}
@@ -0,0 +1,150 @@
+use savefile::prelude::*;
+use savefile::{load_as_json, load_dynamic, save_from_json, Value};
+use serde_json::json;
+use std::collections::BTreeMap;
+
+#[derive(Savefile, Debug, PartialEq)]
+enum Shape {
+    Empty,
+    Circle(f32),
+    Rect { width: u32, height: u32 },
+}
+
+#[derive(Savefile, Debug, PartialEq)]
+struct Drawing {
+    title: String,
+    initial: char,
+    shapes: Vec<Shape>,
+    origin: [i16; 2],
+    layer: Option<u8>,
+    nested: Option<Option<u8>>,
+    tags: BTreeMap<String, u64>,
+    big: u128,
+    scale: f64,
+}
+
+fn drawing() -> Drawing {
+    let mut tags = BTreeMap::new();
+    tags.insert("a".to_string(), 1);
+    Drawing {
+        title: "sketch".to_string(),
+        initial: 'ß',
+        shapes: vec![Shape::Empty, Shape::Circle(1.1), Shape::Rect { width: 3, height: 4 }],
+        origin: [-1, 2],
+        layer: None,
+        nested: Some(None),
+        tags,
+        big: u128::MAX,
+        scale: f64::INFINITY,
+    }
+}
+
+fn drawing_json() -> serde_json::Value {
+    json!({
+        "title": "sketch",
+        "initial": "ß",
+        "shapes": ["Empty", {"Circle": {"0": 1.1}}, {"Rect": {"width": 3, "height": 4}}],
+        "origin": [-1, 2],
+        "layer": null,
+        "nested": [null],
+        "tags": [{"key": "a", "value": 1}],
+        "big": "340282366920938463463374607431768211455",
+        "scale": "inf"
+    })
+}
+
+fn schema() -> Schema {
+    Drawing::schema(1, &mut WithSchemaContext::new())
+}
+
+#[test]
+fn test_json_export() {
+    let data = save_to_mem(1, &drawing()).unwrap();
+    let exported = load_as_json(&mut &data[..]).unwrap();
+    assert_eq!(exported, drawing_json());
+    // Fields are kept in declaration order
+    let text = serde_json::to_string(&exported).unwrap();
+    assert!(text.starts_with(r#"{"title":"sketch","initial":"ß","shapes":"#));
+}
+
+#[test]
+fn test_json_import() {
+    let mut data = Vec::new();
+    save_from_json(&mut data, 1, &schema(), &drawing_json()).unwrap();
+    let loaded: Drawing = load_from_mem(&data, 1).unwrap();
+    assert_eq!(loaded, drawing());
+    assert_eq!(data, save_to_mem(1, &drawing()).unwrap());
+}
+
+#[test]
+fn test_json_roundtrip_through_text() {
+    let data = save_to_mem(1, &drawing()).unwrap();
+    let file = load_dynamic(&mut &data[..]).unwrap();
+    let text = serde_json::to_string_pretty(&file.value.to_json()).unwrap();
+    let json: serde_json::Value = serde_json::from_str(&text).unwrap();
+    assert_eq!(Value::from_json(&json, &file.schema).unwrap(), file.value);
+}
+
+fn mismatch_path(json: serde_json::Value) -> String {
+    match Value::from_json(&json, &schema()) {
+        Err(SavefileError::JsonMismatch { path, .. }) => path,
+        other => panic!("Expected JSON mismatch, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_json_errors_point_at_path() {
+    let mut json = drawing_json();
+    json["shapes"][2]["Rect"]["width"] = json!(-3);
+    assert_eq!(mismatch_path(json), "$.shapes[2].Rect.width");
+
+    let mut json = drawing_json();
+    json["shapes"][1] = json!("Triangle");
+    assert_eq!(mismatch_path(json), "$.shapes[1]");
+
+    let mut json = drawing_json();
+    json["tags"][0]["value"] = json!("many");
+    assert_eq!(mismatch_path(json), "$.tags[0].value");
+
+    let mut json = drawing_json();
+    json["origin"] = json!([1, 2, 3]);
+    assert_eq!(mismatch_path(json), "$.origin");
+
+    let mut json = drawing_json();
+    json.as_object_mut().unwrap().remove("title");
+    assert_eq!(mismatch_path(json), "$");
+
+    let mut json = drawing_json();
+    json["initial"] = json!("ab");
+    let err = Value::from_json(&json, &schema()).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "JSON does not match schema at $.initial: expected a string with a single char, found a string"
+    );
+}
+
+#[derive(Savefile, Debug, PartialEq)]
+struct TreeNode {
+    label: u32,
+    children: Vec<TreeNode>,
+}
+
+#[test]
+fn test_json_recursive() {
+    let tree = TreeNode {
+        label: 1,
+        children: vec![TreeNode {
+            label: 2,
+            children: vec![],
+        }],
+    };
+    let json = load_as_json(&mut &save_to_mem(0, &tree).unwrap()[..]).unwrap();
+    assert_eq!(json, json!({"label": 1, "children": [{"label": 2, "children": []}]}));
+
+    let mut json = json;
+    json["children"][0]["children"] = json!([{"label": 3, "children": []}]);
+    let mut data = Vec::new();
+    save_from_json(&mut data, 0, &TreeNode::schema(0, &mut WithSchemaContext::new()), &json).unwrap();
+    let loaded: TreeNode = load_from_mem(&data, 0).unwrap();
+    assert_eq!(loaded.children[0].children[0].label, 3);
+}
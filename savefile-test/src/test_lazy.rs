@@ -0,0 +1,82 @@
+use savefile::prelude::*;
+use savefile::{load_dynamic, load_lazy, save_compressed_with, Lazy};
+use std::io::Cursor;
+
+#[derive(Savefile, Debug, Clone)]
+struct Level {
+    name: String,
+    terrain: Lazy<Vec<u32>>,
+    signs: Lazy<Vec<String>>,
+    score: u64,
+}
+
+fn level() -> Level {
+    Level {
+        name: "Cave".to_string(),
+        terrain: Lazy::new((0..10000).collect()),
+        signs: Lazy::new(vec!["Beware".to_string(), "Exit".to_string()]),
+        score: 1234,
+    }
+}
+
+#[test]
+fn test_lazy_reads_from_seekable_reader() {
+    let mut data = Vec::new();
+    save(&mut data, 0, &level()).unwrap();
+
+    let mut reader = Cursor::new(&data[..]);
+    let mut loaded: Level = load_lazy(&mut reader, 0).unwrap();
+    assert_eq!(loaded.name, "Cave");
+    assert_eq!(loaded.score, 1234);
+    assert!(!loaded.terrain.is_loaded());
+    assert!(loaded.terrain.get().is_err());
+    assert_eq!(
+        loaded.introspect_child(1).unwrap().val().introspect_value(),
+        "Lazy (not loaded)"
+    );
+    assert!(save(&mut Vec::new(), 0, &loaded).is_err());
+
+    assert_eq!(
+        *loaded.signs.get_from(&mut reader).unwrap(),
+        vec!["Beware".to_string(), "Exit".to_string()]
+    );
+    assert_eq!(loaded.terrain.get_from(&mut reader).unwrap().len(), 10000);
+    assert_eq!(loaded.terrain.get_loaded().unwrap()[9999], 9999);
+
+    let mut saved_again = Vec::new();
+    save(&mut saved_again, 0, &loaded).unwrap();
+    assert_eq!(saved_again, data);
+}
+
+#[test]
+fn test_lazy_keeps_bytes_when_not_seeking() {
+    let mut data = Vec::new();
+    save_compressed_with(&mut data, 0, &level(), CompressionOptions::new(CompressionCodec::Zstd)).unwrap();
+
+    let loaded: Level = load_lazy(&mut Cursor::new(&data[..]), 0).unwrap();
+    assert!(!loaded.signs.is_loaded());
+    // Saving copies the stored bytes, without deserializing them
+    let mut plain = Vec::new();
+    save(&mut plain, 0, &loaded).unwrap();
+    assert!(!loaded.signs.is_loaded());
+
+    let mut loaded: Level = load_from_mem(&plain, 0).unwrap();
+    assert_eq!(loaded.signs.get().unwrap().len(), 2);
+    assert_eq!(loaded.terrain.clone().into_inner().unwrap().len(), 10000);
+}
+
+#[test]
+fn test_lazy_schema_is_readable_without_lazy() {
+    let mut data = Vec::new();
+    save(&mut data, 0, &level()).unwrap();
+    let file = load_dynamic(&mut &data[..]).unwrap();
+    let signs = file.value.field("signs").unwrap();
+    assert_eq!(signs.field("length"), Some(&savefile::Value::U64(8 + 14 + 12)));
+    assert_eq!(
+        signs.field("value"),
+        Some(&savefile::Value::Vector(vec![
+            savefile::Value::String("Beware".to_string()),
+            savefile::Value::String("Exit".to_string())
+        ]))
+    );
+}
@@ -0,0 +1,272 @@
+use savefile::prelude::*;
+use savefile::{load_lenient, save_compressed_with, SavefileError};
+use std::collections::HashMap;
+
+#[derive(Savefile, Debug, PartialEq)]
+enum ItemV1 {
+    Sword { damage: u32 },
+    Shield,
+    Potion(u8),
+}
+
+#[derive(Savefile, Debug, PartialEq)]
+struct PlayerV1 {
+    name: String,
+    level: u32,
+    nickname: String,
+    items: Vec<ItemV1>,
+    stats: HashMap<String, u32>,
+}
+
+/// `PlayerV1`, with fields reordered, added and removed, without a new version
+#[derive(Savefile, Debug, PartialEq)]
+enum Item {
+    Shield,
+    Sword { sharp: bool, damage: u32 },
+    Potion(u8),
+}
+
+#[derive(Savefile, Debug, PartialEq)]
+struct Player {
+    level: u32,
+    name: String,
+    items: Vec<Item>,
+    title: Option<String>,
+    position: [i32; 2],
+    stats: HashMap<String, u32>,
+}
+
+fn player_v1() -> PlayerV1 {
+    PlayerV1 {
+        name: "Alice".to_string(),
+        level: 7,
+        nickname: "Al".to_string(),
+        items: vec![ItemV1::Sword { damage: 3 }, ItemV1::Shield, ItemV1::Potion(2)],
+        stats: vec![("strength".to_string(), 12)].into_iter().collect(),
+    }
+}
+
+#[test]
+fn test_lenient_matches_fields_by_name() {
+    let mut data = Vec::new();
+    save(&mut data, 0, &player_v1()).unwrap();
+
+    match load::<Player>(&mut &data[..], 0) {
+        Err(SavefileError::IncompatibleSchema { .. }) => {}
+        other => panic!("Expected IncompatibleSchema, got {:?}", other),
+    }
+    let player: Player = load_lenient(&mut &data[..], 0).unwrap();
+    assert_eq!(
+        player,
+        Player {
+            level: 7,
+            name: "Alice".to_string(),
+            items: vec![
+                Item::Sword {
+                    sharp: false,
+                    damage: 3
+                },
+                Item::Shield,
+                Item::Potion(2)
+            ],
+            title: None,
+            position: [0, 0],
+            stats: vec![("strength".to_string(), 12)].into_iter().collect(),
+        }
+    );
+}
+
+#[test]
+fn test_lenient_ignores_versions() {
+    // A file of a later version can be loaded, as long as the data can be matched
+    let mut data = Vec::new();
+    save_compressed_with(
+        &mut data,
+        5,
+        &player_v1(),
+        CompressionOptions::new(CompressionCodec::Zstd),
+    )
+    .unwrap();
+    let player: Player = load_lenient(&mut &data[..], 1).unwrap();
+    assert_eq!(player.name, "Alice");
+    assert_eq!(player.items.len(), 3);
+}
+
+#[derive(Savefile, Debug, PartialEq)]
+struct TreeV1 {
+    label: String,
+    children: Vec<TreeV1>,
+}
+
+#[derive(Savefile, Debug, PartialEq)]
+struct Tree {
+    children: Vec<Tree>,
+    weight: u16,
+    label: String,
+}
+
+#[test]
+fn test_lenient_recursive_type() {
+    let tree = TreeV1 {
+        label: "root".to_string(),
+        children: vec![TreeV1 {
+            label: "leaf".to_string(),
+            children: vec![],
+        }],
+    };
+    let mut data = Vec::new();
+    save(&mut data, 0, &tree).unwrap();
+    let loaded: Tree = load_lenient(&mut &data[..], 0).unwrap();
+    assert_eq!(
+        loaded,
+        Tree {
+            children: vec![Tree {
+                children: vec![],
+                weight: 0,
+                label: "leaf".to_string()
+            }],
+            weight: 0,
+            label: "root".to_string(),
+        }
+    );
+}
+
+#[derive(Savefile, Debug, PartialEq)]
+enum NarrowItem {
+    Sword { damage: u32 },
+    Shield,
+}
+
+#[derive(Savefile, Debug, PartialEq)]
+struct NarrowPlayer {
+    items: Vec<NarrowItem>,
+}
+
+#[derive(Savefile, Debug, PartialEq)]
+struct RetypedPlayer {
+    level: u64,
+}
+
+#[test]
+fn test_lenient_errors() {
+    let mut data = Vec::new();
+    save(&mut data, 0, &player_v1()).unwrap();
+
+    let err = load_lenient::<NarrowPlayer>(&mut &data[..], 0).unwrap_err();
+    assert!(
+        err.to_string()
+            .contains("$.items[2]: enum NarrowItem no longer has variant Potion"),
+        "{}",
+        err
+    );
+    let err = load_lenient::<RetypedPlayer>(&mut &data[..], 0).unwrap_err();
+    assert!(
+        err.to_string()
+            .contains("$.level: the file has u32 where memory has u64"),
+        "{}",
+        err
+    );
+
+    let mut data = Vec::new();
+    save_noschema(&mut data, 0, &player_v1()).unwrap();
+    assert!(load_lenient::<Player>(&mut &data[..], 0).is_err());
+}
+
+#[derive(Savefile, Debug, PartialEq)]
+struct Settings {
+    volume: u8,
+}
+
+fn default_language() -> String {
+    "en".to_string()
+}
+
+fn no_presets<T>() -> Vec<T> {
+    Vec::new()
+}
+
+/// `Settings`, with fields which declare default values
+#[derive(Savefile, Debug, PartialEq)]
+struct SettingsWithDefaults {
+    #[savefile_default_val = "80"]
+    brightness: u8,
+    volume: u8,
+    #[savefile_default_fn = "default_language"]
+    language: String,
+    fullscreen: bool,
+}
+
+#[derive(Savefile, Debug, PartialEq)]
+struct GenericSettings<T: 'static> {
+    volume: u8,
+    #[savefile_default_fn = "no_presets"]
+    presets: Vec<T>,
+}
+
+#[test]
+fn test_lenient_declared_defaults() {
+    let mut data = Vec::new();
+    save(&mut data, 0, &Settings { volume: 5 }).unwrap();
+
+    let settings: SettingsWithDefaults = load_lenient(&mut &data[..], 0).unwrap();
+    assert_eq!(
+        settings,
+        SettingsWithDefaults {
+            brightness: 80,
+            volume: 5,
+            language: "en".to_string(),
+            fullscreen: false,
+        }
+    );
+
+    let err = load_lenient::<GenericSettings<u32>>(&mut &data[..], 0).unwrap_err();
+    assert!(
+        err.to_string()
+            .contains("$.presets: the file has no value for it, and its declared default cannot be used"),
+        "{}",
+        err
+    );
+}
+
+#[derive(Savefile, Debug, PartialEq)]
+struct TerrainV1 {
+    heights: Vec<u16>,
+}
+
+#[derive(Savefile, Debug)]
+struct LevelV1 {
+    name: String,
+    terrain: Lazy<TerrainV1>,
+}
+
+#[derive(Savefile, Debug, PartialEq)]
+struct Terrain {
+    heights: Vec<u16>,
+    water_level: u32,
+}
+
+#[derive(Savefile, Debug)]
+struct Level {
+    name: String,
+    terrain: Lazy<Terrain>,
+}
+
+#[test]
+fn test_lenient_lazy_value_gains_field() {
+    let level = LevelV1 {
+        name: "Caves".to_string(),
+        terrain: Lazy::new(TerrainV1 { heights: vec![1, 2, 3] }),
+    };
+    let mut data = Vec::new();
+    save(&mut data, 0, &level).unwrap();
+
+    let mut loaded: Level = load_lenient(&mut &data[..], 0).unwrap();
+    assert_eq!(loaded.name, "Caves");
+    assert_eq!(
+        loaded.terrain.get().unwrap(),
+        &Terrain {
+            heights: vec![1, 2, 3],
+            water_level: 0,
+        }
+    );
+}
@@ -0,0 +1,212 @@
+use savefile::prelude::*;
+use savefile::{load_from_mem_with_limits, load_lazy_with_limits, load_projection_with_limits, LimitKind, LoadLimits};
+use std::collections::HashMap;
+use std::io::Cursor;
+
+#[derive(Savefile, Debug, PartialEq)]
+enum Tree {
+    Leaf,
+    Node(Box<Tree>, Box<Tree>),
+}
+
+#[derive(Savefile, Debug, PartialEq)]
+struct Upload {
+    name: String,
+    tags: Vec<String>,
+    grid: Vec<Vec<u32>>,
+    counts: HashMap<String, u32>,
+}
+
+fn upload() -> Upload {
+    Upload {
+        name: "level one".to_string(),
+        tags: vec!["small".to_string(), "easy".to_string()],
+        grid: vec![vec![1, 2, 3], vec![4, 5, 6]],
+        counts: vec![("trees".to_string(), 12)].into_iter().collect(),
+    }
+}
+
+fn limit_exceeded(data: &[u8], limits: LoadLimits) -> (LimitKind, u64, u64) {
+    match load_from_mem_with_limits::<Upload>(data, 0, &limits) {
+        Err(SavefileError::LimitExceeded { limit, value, max }) => (limit, value, max),
+        other => panic!("Expected a limit to be exceeded, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_load_within_limits() {
+    let data = save_to_mem(0, &upload()).unwrap();
+    let limits = LoadLimits::new()
+        .with_max_allocated_bytes(100_000)
+        .with_max_collection_len(16)
+        .with_max_string_len(64)
+        .with_max_depth(4);
+    let loaded: Upload = load_from_mem_with_limits(&data, 0, &limits).unwrap();
+    assert_eq!(loaded, upload());
+    let unlimited: Upload = load_from_mem_with_limits(&data, 0, &LoadLimits::new()).unwrap();
+    assert_eq!(unlimited, upload());
+}
+
+#[test]
+fn test_load_exceeding_limits() {
+    let data = save_to_mem(0, &upload()).unwrap();
+    // The schema stored in the file is limited as well
+    assert_eq!(
+        limit_exceeded(&data, LoadLimits::new().with_max_string_len(5)).0,
+        LimitKind::StringLen
+    );
+    assert_eq!(
+        limit_exceeded(&data, LoadLimits::new().with_max_collection_len(2)).0,
+        LimitKind::CollectionLen
+    );
+    assert_eq!(
+        limit_exceeded(&data, LoadLimits::new().with_max_allocated_bytes(20)).0,
+        LimitKind::AllocatedBytes
+    );
+    // The Vec<u32> elements of grid are nested three levels deep
+    assert_eq!(
+        limit_exceeded(&data, LoadLimits::new().with_max_depth(1)).0,
+        LimitKind::Depth
+    );
+}
+
+#[test]
+fn test_hostile_length() {
+    let mut data = save_to_mem(0, &vec![1u64, 2, 3]).unwrap();
+    // Claim the vector has 2^60 elements
+    let len_pos = data.len() - 3 * 8 - 8;
+    data[len_pos..len_pos + 8].copy_from_slice(&(1u64 << 60).to_le_bytes());
+    let limits = LoadLimits::new().with_max_allocated_bytes(1 << 20);
+    match load_from_mem_with_limits::<Vec<u64>>(&data, 0, &limits) {
+        Err(SavefileError::LimitExceeded { limit, .. }) => assert_eq!(limit, LimitKind::AllocatedBytes),
+        other => panic!("Expected the allocation limit to be exceeded, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_deeply_nested_recursive_type() {
+    // A tree nested a million levels deep, which would overflow the stack if loaded
+    let leaf = save_to_mem(0, &Tree::Leaf).unwrap();
+    let mut data = leaf[..leaf.len() - 1].to_vec();
+    let depth = 1_000_000;
+    data.extend(std::iter::repeat_n(1u8, depth));
+    data.extend(std::iter::repeat_n(0u8, depth + 1));
+
+    let limits = LoadLimits::new().with_max_depth(100);
+    match load_from_mem_with_limits::<Tree>(&data, 0, &limits) {
+        Err(SavefileError::LimitExceeded { limit, value, max }) => {
+            assert_eq!((limit, value, max), (LimitKind::Depth, 101, 100))
+        }
+        other => panic!("Expected the depth limit to be exceeded, got {:?}", other),
+    }
+
+    let shallow = Tree::Node(
+        Box::new(Tree::Node(Box::new(Tree::Leaf), Box::new(Tree::Leaf))),
+        Box::new(Tree::Leaf),
+    );
+    let data = save_to_mem(0, &shallow).unwrap();
+    let loaded: Tree = load_from_mem_with_limits(&data, 0, &limits).unwrap();
+    assert_eq!(loaded, shallow);
+}
+
+#[derive(Savefile, Debug, PartialEq)]
+struct Node {
+    val: u32,
+    next: Option<Box<Node>>,
+}
+
+#[test]
+fn test_no_depth_limit_by_default() {
+    let mut list = None;
+    for val in 0..200 {
+        list = Some(Box::new(Node { val, next: list }));
+    }
+    let data = save_to_mem(0, &list).unwrap();
+    let loaded: Option<Box<Node>> = load_from_mem(&data, 0).unwrap();
+    assert_eq!(loaded, list);
+
+    // Other limits don't limit the depth either
+    let limits = LoadLimits::new().with_max_collection_len(10);
+    let loaded: Option<Box<Node>> = load_from_mem_with_limits(&data, 0, &limits).unwrap();
+    assert_eq!(loaded, list);
+
+    // Only the Box of each node adds a level, not the Option or the struct
+    let limits = LoadLimits::new().with_max_depth(200);
+    let loaded: Option<Box<Node>> = load_from_mem_with_limits(&data, 0, &limits).unwrap();
+    assert_eq!(loaded, list);
+    let limits = LoadLimits::new().with_max_depth(199);
+    match load_from_mem_with_limits::<Option<Box<Node>>>(&data, 0, &limits) {
+        Err(SavefileError::LimitExceeded { limit, .. }) => assert_eq!(limit, LimitKind::Depth),
+        other => panic!("Expected the depth limit to be exceeded, got {:?}", other),
+    }
+}
+
+#[derive(Savefile, Debug)]
+struct LazyUpload {
+    name: String,
+    grid: Lazy<Vec<Vec<u32>>>,
+}
+
+#[test]
+fn test_lazy_values_keep_limits() {
+    let data = save_to_mem(
+        0,
+        &LazyUpload {
+            name: "level one".to_string(),
+            grid: Lazy::new(vec![vec![0; 100]]),
+        },
+    )
+    .unwrap();
+    let limits = LoadLimits::new().with_max_collection_len(16);
+    let mut reader = Cursor::new(&data);
+    let mut lazy: LazyUpload = load_lazy_with_limits(&mut reader, 0, &limits).unwrap();
+    match lazy.grid.get_from(&mut reader) {
+        Err(SavefileError::LimitExceeded { limit, value, max }) => {
+            assert_eq!((limit, value, max), (LimitKind::CollectionLen, 100, 16))
+        }
+        other => panic!("Expected a limit to be exceeded, got {:?}", other),
+    }
+
+    // Kept in memory when loaded without seeking
+    let mut lazy: LazyUpload = load_from_mem_with_limits(&data, 0, &limits).unwrap();
+    assert!(matches!(
+        lazy.grid.get(),
+        Err(SavefileError::LimitExceeded {
+            limit: LimitKind::CollectionLen,
+            ..
+        })
+    ));
+}
+
+#[derive(Savefile, Debug, PartialEq)]
+struct UploadGrid {
+    grid: Vec<Vec<u32>>,
+}
+
+#[test]
+fn test_projection_within_limits() {
+    let mut upload = upload();
+    upload.grid = vec![vec![7; 1000]];
+    let data = save_to_mem(0, &upload).unwrap();
+
+    let limits = LoadLimits::new().with_max_allocated_bytes(100_000);
+    let projected: UploadGrid = load_projection_with_limits(&mut &data[..], 0, &limits).unwrap();
+    assert_eq!(projected.grid, upload.grid);
+
+    // The serialized grid, which is buffered before deserializing it, is about 4000 bytes
+    let limits = LoadLimits::new().with_max_allocated_bytes(1000);
+    match load_projection_with_limits::<UploadGrid>(&mut &data[..], 0, &limits) {
+        Err(SavefileError::LimitExceeded { limit, max, .. }) => {
+            assert_eq!((limit, max), (LimitKind::AllocatedBytes, 1000))
+        }
+        other => panic!("Expected a limit to be exceeded, got {:?}", other),
+    }
+    let limits = LoadLimits::new().with_max_collection_len(100);
+    assert!(matches!(
+        load_projection_with_limits::<UploadGrid>(&mut &data[..], 0, &limits),
+        Err(SavefileError::LimitExceeded {
+            limit: LimitKind::CollectionLen,
+            ..
+        })
+    ));
+}
@@ -0,0 +1,99 @@
+use savefile::prelude::*;
+use savefile::{
+    load_file_noschema, load_noschema, read_header, ChecksumAlgorithm, FileMetadata, LimitKind, LoadLimits,
+    LoadOptions, SaveOptions,
+};
+use test_dir;
+
+#[derive(Savefile, Debug, PartialEq)]
+struct Settings {
+    name: String,
+    volume: u8,
+    recent: Vec<String>,
+}
+
+fn settings() -> Settings {
+    Settings {
+        name: "default".to_string(),
+        volume: 7,
+        recent: vec!["a.txt".to_string(), "b.txt".to_string()],
+    }
+}
+
+#[test]
+fn test_compressed_without_schema_to_mem() {
+    let options = SaveOptions::new(1)
+        .without_schema()
+        .with_compression(CompressionOptions::new(CompressionCodec::Zstd));
+    let data = options.save_to_mem(&settings()).unwrap();
+    assert!(read_header(&mut &data[..]).unwrap().is_compressed());
+
+    let loaded: Settings = LoadOptions::new(1).without_schema().load_from_mem(&data).unwrap();
+    assert_eq!(loaded, settings());
+    // The free functions are equivalent
+    let loaded: Settings = load_noschema(&mut &data[..], 1).unwrap();
+    assert_eq!(loaded, settings());
+}
+
+#[test]
+fn test_checksum_metadata_and_limits() {
+    let metadata = FileMetadata {
+        app_name: "Editor".to_string(),
+        ..FileMetadata::default()
+    };
+    let data = SaveOptions::new(0)
+        .with_checksum(ChecksumAlgorithm::Crc32c)
+        .with_metadata(metadata.clone())
+        .save_to_mem(&settings())
+        .unwrap();
+    let header = read_header(&mut &data[..]).unwrap();
+    assert_eq!(header.checksum, Some(ChecksumAlgorithm::Crc32c));
+    assert_eq!(header.metadata, Some(metadata));
+
+    let loaded: Settings = LoadOptions::new(0).load_from_mem(&data).unwrap();
+    assert_eq!(loaded, settings());
+    let limited = LoadOptions::new(0).with_limits(LoadLimits::new().with_max_collection_len(1));
+    match limited.load_from_mem::<Settings>(&data) {
+        Err(SavefileError::LimitExceeded { limit, .. }) => assert_eq!(limit, LimitKind::CollectionLen),
+        other => panic!("Expected the collection limit to be exceeded, got {:?}", other),
+    }
+}
+
+#[test]
+#[cfg(not(miri))]
+fn test_encrypted_compressed_file() {
+    let dir = test_dir("options_encrypted");
+    let path = dir.join("settings.bin");
+    let options = SaveOptions::new(2)
+        .with_compression(CompressionOptions::new(CompressionCodec::Lz4))
+        .with_checksum(ChecksumAlgorithm::Crc32c)
+        .with_password("hunter2", KdfParams { iterations: 1000 });
+    options.save_file(&path, &settings()).unwrap();
+    assert!(!format!("{:?}", options).contains("hunter2"));
+
+    let loaded: Settings = LoadOptions::new(2).with_password("hunter2").load_file(&path).unwrap();
+    assert_eq!(loaded, settings());
+    let loaded: Settings = load_encrypted_file(&path, 2, "hunter2").unwrap();
+    assert_eq!(loaded, settings());
+    let result = LoadOptions::new(2)
+        .with_password("wrong")
+        .load_file::<Settings, _>(&path);
+    assert!(matches!(result, Err(SavefileError::WrongPassword)));
+}
+
+#[test]
+#[cfg(not(miri))]
+fn test_free_functions_match_options() {
+    let dir = test_dir("options_free_functions");
+    save_file_noschema(dir.join("plain.bin"), 0, &settings()).unwrap();
+    SaveOptions::new(0)
+        .without_schema()
+        .save_file(dir.join("options.bin"), &settings())
+        .unwrap();
+    assert_eq!(
+        std::fs::read(dir.join("plain.bin")).unwrap(),
+        std::fs::read(dir.join("options.bin")).unwrap()
+    );
+    let loaded: Settings = load_file_noschema(dir.join("options.bin"), 0).unwrap();
+    assert_eq!(loaded, settings());
+}
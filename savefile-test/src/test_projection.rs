@@ -0,0 +1,160 @@
+use savefile::prelude::*;
+use savefile::{load_projection, save_compressed_with, SavefileError};
+use std::collections::HashMap;
+
+#[derive(Savefile, Debug, PartialEq)]
+enum Tile {
+    Empty,
+    Wall { height: u8 },
+    Sign(String),
+}
+
+#[derive(Savefile, Debug, PartialEq)]
+struct Node {
+    value: u32,
+    next: Option<Box<Node>>,
+}
+
+#[derive(Savefile, Debug, PartialEq)]
+struct World {
+    name: String,
+    tiles: Vec<Tile>,
+    heights: Vec<u16>,
+    entities: HashMap<String, [f32; 3]>,
+    path: Node,
+    level: u32,
+    seed: Option<u64>,
+}
+
+fn world() -> World {
+    World {
+        name: "Midgard".to_string(),
+        tiles: vec![Tile::Empty, Tile::Wall { height: 3 }, Tile::Sign("Welcome".to_string())],
+        heights: (0..1000).collect(),
+        entities: vec![("troll".to_string(), [1.0, 2.0, 3.0])].into_iter().collect(),
+        path: Node {
+            value: 1,
+            next: Some(Box::new(Node { value: 2, next: None })),
+        },
+        level: 17,
+        seed: Some(99),
+    }
+}
+
+#[test]
+fn test_skip_value() {
+    let world = world();
+    let mut data = Vec::new();
+    Serializer::bare_serialize(&mut data, 0, &world).unwrap();
+    Serializer::bare_serialize(&mut data, 0, &0x1234_5678u32).unwrap();
+
+    let mut reader = &data[..];
+    let mut deserializer = Deserializer {
+        reader: &mut reader,
+        file_version: 0,
+        ephemeral_state: HashMap::new(),
+        limit_state: None,
+    };
+    deserializer.skip_value(&get_schema::<World>(0)).unwrap();
+    assert_eq!(deserializer.read_u32().unwrap(), 0x1234_5678);
+    assert!(reader.is_empty());
+
+    let mut truncated = &data[..data.len() - 10];
+    let mut deserializer = Deserializer {
+        reader: &mut truncated,
+        file_version: 0,
+        ephemeral_state: HashMap::new(),
+        limit_state: None,
+    };
+    assert!(deserializer.skip_value(&get_schema::<World>(0)).is_err());
+}
+
+/// Some of the fields of `World`, in a different order
+#[derive(Savefile, Debug, PartialEq)]
+struct WorldSummary {
+    level: u32,
+    name: String,
+    path: Node,
+}
+
+#[test]
+fn test_load_projection() {
+    let mut data = Vec::new();
+    save_compressed_with(&mut data, 0, &world(), CompressionOptions::new(CompressionCodec::Zstd)).unwrap();
+    let summary: WorldSummary = load_projection(&mut &data[..], 0).unwrap();
+    assert_eq!(
+        summary,
+        WorldSummary {
+            level: 17,
+            name: "Midgard".to_string(),
+            path: world().path,
+        }
+    );
+}
+
+#[derive(Savefile, Debug, PartialEq)]
+struct WrongType {
+    level: u64,
+}
+
+#[derive(Savefile, Debug, PartialEq)]
+struct UnknownField {
+    name: String,
+    owner: String,
+}
+
+#[test]
+fn test_load_projection_errors() {
+    let mut data = Vec::new();
+    save(&mut data, 0, &world()).unwrap();
+    match load_projection::<WrongType>(&mut &data[..], 0) {
+        Err(SavefileError::IncompatibleSchema { message }) => {
+            assert!(message.starts_with("Field 'level' differs"), "{}", message)
+        }
+        other => panic!("Expected IncompatibleSchema, got {:?}", other),
+    }
+    let err = load_projection::<UnknownField>(&mut &data[..], 0).unwrap_err();
+    assert!(err.to_string().contains("no field 'owner'"), "{}", err);
+    let err = load_projection::<u32>(&mut &data[..], 0).unwrap_err();
+    assert!(
+        err.to_string().contains("Only structs can be partially loaded"),
+        "{}",
+        err
+    );
+}
+
+#[derive(Savefile, Debug, PartialEq)]
+struct Chain {
+    label: String,
+    parent: Option<Box<Chain>>,
+}
+
+/// Has the field `parent` of `Chain`, but a `Box<ChainParent>` is not a `Box<Chain>`
+#[derive(Savefile, Debug, PartialEq)]
+struct ChainParent {
+    parent: Option<Box<ChainParent>>,
+}
+
+#[test]
+fn test_load_projection_recursion_to_root() {
+    // Saved as a Box, the schema of `parent` refers to the root, and is the same for both types
+    let chain = Box::new(Chain {
+        label: "child".to_string(),
+        parent: Some(Box::new(Chain {
+            label: "root".to_string(),
+            parent: None,
+        })),
+    });
+    let mut data = Vec::new();
+    save(&mut data, 0, &chain).unwrap();
+    match load_projection::<Box<ChainParent>>(&mut &data[..], 0) {
+        Err(SavefileError::IncompatibleSchema { message }) => {
+            assert!(
+                message.contains("Field 'parent' contains the struct itself"),
+                "{}",
+                message
+            )
+        }
+        other => panic!("Expected IncompatibleSchema, got {:?}", other),
+    }
+}
@@ -0,0 +1,117 @@
+use savefile::prelude::*;
+use savefile::{diff_schema, SchemaDiff, SchemaDifferenceKind, SchemaPathElement};
+
+mod old {
+    #[derive(Savefile)]
+    pub enum Terrain {
+        Grass,
+        Water { depth: u32 },
+        Lava,
+    }
+
+    #[derive(Savefile)]
+    pub struct Tile {
+        pub terrain: Terrain,
+        pub height: u16,
+        pub color: [u8; 3],
+    }
+
+    #[derive(Savefile)]
+    pub struct Level {
+        pub name: String,
+        pub tiles: Vec<Tile>,
+        pub seed: u32,
+    }
+}
+
+mod new {
+    #[derive(Savefile)]
+    pub enum Terrain {
+        Grass,
+        Water { depth: u64 },
+    }
+
+    #[derive(Savefile)]
+    pub struct Tile {
+        pub terrain: Terrain,
+        pub height: u32,
+        pub color: [u8; 4],
+        pub owner: Option<String>,
+    }
+
+    #[derive(Savefile)]
+    pub struct Level {
+        pub name: String,
+        pub tiles: Vec<Tile>,
+        pub seed: u64,
+    }
+}
+
+fn element(name: &str) -> SchemaPathElement {
+    SchemaPathElement::Field(name.to_string())
+}
+
+#[test]
+fn test_schema_diff_lists_every_difference() {
+    let memory = get_schema::<new::Level>(0);
+    let file = get_schema::<old::Level>(0);
+    let diff = SchemaDiff::new(&memory, &file);
+    let kinds: Vec<_> = diff.differences.iter().map(|d| d.kind.clone()).collect();
+    assert_eq!(
+        kinds,
+        vec![
+            SchemaDifferenceKind::FieldCountChange { memory: 4, file: 3 },
+            SchemaDifferenceKind::VariantCountChange { memory: 2, file: 3 },
+            SchemaDifferenceKind::TypeChange,
+            SchemaDifferenceKind::VariantRemoved("Lava".to_string()),
+            SchemaDifferenceKind::TypeChange,
+            SchemaDifferenceKind::ArrayLengthChange { memory: 4, file: 3 },
+            SchemaDifferenceKind::FieldAdded("owner".to_string()),
+            SchemaDifferenceKind::TypeChange,
+        ]
+    );
+
+    let depth = &diff.differences[2];
+    assert_eq!(
+        depth.path,
+        vec![
+            SchemaPathElement::Struct("Level".to_string()),
+            element("tiles"),
+            SchemaPathElement::Element,
+            SchemaPathElement::Struct("Tile".to_string()),
+            element("terrain"),
+            SchemaPathElement::Enum("Terrain".to_string()),
+            SchemaPathElement::Variant("Water".to_string()),
+            element("depth"),
+        ]
+    );
+    assert_eq!(depth.memory, get_schema::<u64>(0));
+    assert_eq!(depth.file, get_schema::<u32>(0));
+    assert_eq!(
+        depth.to_string(),
+        "At location [./Level/tiles/*/Tile/terrainTerrain/Water/depth]: Application protocol has datatype u64, but disk format has u32"
+    );
+    let seed = diff.differences.last().unwrap();
+    assert_eq!(seed.path_string(""), "/Level/seed");
+}
+
+#[test]
+fn test_schema_diff_message_matches_first_difference() {
+    let memory = get_schema::<new::Level>(0);
+    let file = get_schema::<old::Level>(0);
+    let first = diff_schema(&memory, &file, ".".to_string()).unwrap();
+    assert_eq!(
+        first,
+        "At location [./Level/tiles/*/Tile]: In memory struct (struct Tile) has 4 fields, disk format (struct Tile) has 3 fields."
+    );
+    assert_eq!(first, SchemaDiff::new(&memory, &file).differences[0].message("."));
+}
+
+#[test]
+fn test_schema_diff_equal_schemas() {
+    let schema = get_schema::<new::Level>(0);
+    let diff = SchemaDiff::new(&schema, &schema);
+    assert!(diff.is_empty());
+    assert_eq!(diff.to_string(), "");
+    assert_eq!(diff_schema(&schema, &schema, "".into()), None);
+}
@@ -0,0 +1,70 @@
+use savefile::prelude::*;
+use savefile::verify_schema_history;
+use test_dir;
+
+/// The schemas of this type are checked in, in the 'schemas' directory
+#[derive(Savefile, Debug, PartialEq)]
+pub struct SavedGame {
+    pub player: String,
+    pub level: u32,
+    #[savefile_versions = "1.."]
+    pub inventory: Vec<String>,
+}
+
+#[test]
+#[cfg(not(miri))]
+fn test_schema_history_of_checked_in_type() {
+    verify_schema_history::<SavedGame>("schemas", 1).unwrap();
+}
+
+#[test]
+#[cfg(not(miri))]
+fn test_schema_history_records_every_version() {
+    let dir = test_dir("history_records");
+    verify_schema_history::<SavedGame>(&dir, 1).unwrap();
+    assert!(dir.join("savefile_SavedGame_0.schema").exists());
+    assert!(dir.join("savefile_SavedGame_1.schema").exists());
+    let recorded: Schema = load_file_noschema(dir.join("savefile_SavedGame_0.schema"), 1).unwrap();
+    assert_eq!(recorded, get_schema::<SavedGame>(0));
+
+    // Unchanged, and generic types get their own files
+    verify_schema_history::<SavedGame>(&dir, 1).unwrap();
+    verify_schema_history::<Vec<SavedGame>>(&dir, 0).unwrap();
+    assert!(dir.join("savefile_Vec_SavedGame_0.schema").exists());
+}
+
+mod released {
+    #[derive(Savefile)]
+    pub struct Settings {
+        pub volume: u8,
+        pub name: String,
+    }
+}
+
+mod changed {
+    #[derive(Savefile)]
+    pub struct Settings {
+        pub volume: u16,
+        pub name: String,
+        #[savefile_versions = "1.."]
+        pub language: String,
+    }
+}
+
+#[test]
+#[cfg(not(miri))]
+fn test_schema_history_detects_changed_version() {
+    let dir = test_dir("history_changed");
+    verify_schema_history::<released::Settings>(&dir, 0).unwrap();
+    match verify_schema_history::<changed::Settings>(&dir, 1) {
+        Err(SavefileError::IncompatibleSchema { message }) => {
+            assert!(message.starts_with("Schema for version 0 differs from the one recorded in"));
+            assert!(message.contains(
+                "At location [./Settings/volume]: Application protocol has datatype u16, but disk format has u8"
+            ));
+        }
+        other => panic!("Expected incompatible schema, got {:?}", other),
+    }
+    // Version 1 was never recorded, since version 0 failed
+    assert!(!dir.join("savefile_Settings_1.schema").exists());
+}
@@ -0,0 +1,172 @@
+use savefile::prelude::*;
+use savefile::save_signed_compressed_with;
+use std::io::{Read, Write};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+#[derive(Savefile, Debug, PartialEq)]
+struct HighScore {
+    player: String,
+    score: u64,
+    replay: Vec<u8>,
+}
+
+fn high_score() -> HighScore {
+    HighScore {
+        player: "ace".to_string(),
+        score: 31337,
+        replay: (0..200).collect(),
+    }
+}
+
+fn signed(key: &SigningKeyPair) -> Vec<u8> {
+    let mut data = Vec::new();
+    save_signed(&mut data, 1, &high_score(), key).unwrap();
+    data
+}
+
+#[test]
+#[cfg(not(miri))]
+fn test_signed_roundtrip() {
+    let key = SigningKeyPair::generate().unwrap();
+    let data = signed(&key);
+    let loaded: HighScore = load_signed(&mut &data[..], 1, &key.public_key()).unwrap();
+    assert_eq!(loaded, high_score());
+
+    // The signature does not prevent regular loading
+    let loaded: HighScore = load_from_mem(&data, 1).unwrap();
+    assert_eq!(loaded, high_score());
+}
+
+/// Only fixed size fields, so modified data never has absurd sizes
+#[derive(Savefile, Debug, PartialEq)]
+struct MatchResult {
+    player_id: u32,
+    score: u64,
+    replay: [u8; 32],
+}
+
+#[test]
+#[cfg(not(miri))]
+fn test_signed_detects_any_modification() {
+    let key = SigningKeyPair::generate().unwrap();
+    let result = MatchResult {
+        player_id: 17,
+        score: 31337,
+        replay: [3; 32],
+    };
+    let mut data = Vec::new();
+    save_signed(&mut data, 1, &result, &key).unwrap();
+    for pos in 0..data.len() {
+        let mut modified = data.clone();
+        modified[pos] ^= 0x10;
+        let loaded = load_signed::<MatchResult>(&mut &modified[..], 1, &key.public_key());
+        assert!(
+            matches!(loaded, Err(SavefileError::InvalidSignature)),
+            "modification at {} not detected: {:?}",
+            pos,
+            loaded
+        );
+    }
+}
+
+static BALLOTS_DESERIALIZED: AtomicUsize = AtomicUsize::new(0);
+
+/// Counts how often it has been deserialized
+#[derive(Debug, PartialEq)]
+struct Ballot(u32);
+
+impl WithSchema for Ballot {
+    fn schema(version: u32, context: &mut WithSchemaContext) -> Schema {
+        u32::schema(version, context)
+    }
+}
+
+impl Serialize for Ballot {
+    fn serialize(&self, serializer: &mut Serializer<impl Write>) -> Result<(), SavefileError> {
+        self.0.serialize(serializer)
+    }
+}
+
+impl Deserialize for Ballot {
+    fn deserialize(deserializer: &mut Deserializer<impl Read>) -> Result<Self, SavefileError> {
+        BALLOTS_DESERIALIZED.fetch_add(1, Ordering::SeqCst);
+        Ok(Ballot(u32::deserialize(deserializer)?))
+    }
+}
+
+#[test]
+#[cfg(not(miri))]
+fn test_signed_verified_before_deserializing() {
+    let key = SigningKeyPair::generate().unwrap();
+    let mut data = Vec::new();
+    save_signed(&mut data, 1, &Ballot(7), &key).unwrap();
+    let mut forged = data.clone();
+    let last_data_byte = data.len() - 64 - 1;
+    forged[last_data_byte] ^= 1;
+    let result = load_signed::<Ballot>(&mut &forged[..], 1, &key.public_key());
+    assert!(matches!(result, Err(SavefileError::InvalidSignature)));
+    assert_eq!(BALLOTS_DESERIALIZED.load(Ordering::SeqCst), 0);
+
+    let loaded: Ballot = load_signed(&mut &data[..], 1, &key.public_key()).unwrap();
+    assert_eq!(loaded, Ballot(7));
+    assert_eq!(BALLOTS_DESERIALIZED.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+#[cfg(not(miri))]
+fn test_signed_wrong_key() {
+    let key = SigningKeyPair::generate().unwrap();
+    let other = SigningKeyPair::generate().unwrap();
+    let data = signed(&key);
+    let result = load_signed::<HighScore>(&mut &data[..], 1, &other.public_key());
+    assert!(matches!(result, Err(SavefileError::InvalidSignature)));
+}
+
+#[test]
+#[cfg(not(miri))]
+fn test_signed_truncated_or_unsigned() {
+    let key = SigningKeyPair::generate().unwrap();
+    let data = signed(&key);
+    for len in [0, 10, data.len() - 64, data.len() - 1] {
+        assert!(load_signed::<HighScore>(&mut &data[..len], 1, &key.public_key()).is_err());
+    }
+    let unsigned = save_to_mem(1, &high_score()).unwrap();
+    assert!(load_signed::<HighScore>(&mut &unsigned[..], 1, &key.public_key()).is_err());
+}
+
+#[test]
+#[cfg(not(miri))]
+fn test_signed_compressed() {
+    let key = SigningKeyPair::generate().unwrap();
+    let mut data = Vec::new();
+    save_signed_compressed_with(
+        &mut data,
+        1,
+        &high_score(),
+        &key,
+        CompressionOptions::new(CompressionCodec::Zstd),
+    )
+    .unwrap();
+    let loaded: HighScore = load_signed(&mut &data[..], 1, &key.public_key()).unwrap();
+    assert_eq!(loaded, high_score());
+
+    let middle = data.len() / 2;
+    data[middle] ^= 1;
+    let result = load_signed::<HighScore>(&mut &data[..], 1, &key.public_key());
+    assert!(matches!(result, Err(SavefileError::InvalidSignature)));
+}
+
+#[test]
+#[cfg(not(miri))]
+fn test_signed_file_and_keys_from_bytes() {
+    let key = SigningKeyPair::generate().unwrap();
+    let restored = SigningKeyPair::from_seed(key.seed()).unwrap();
+    let public_key = SigningPublicKey::from_bytes(key.public_key().to_bytes());
+    assert_eq!(restored.public_key(), public_key);
+
+    let path = std::env::temp_dir().join(format!("savefile_signed_{}.bin", std::process::id()));
+    save_file_signed(&path, 1, &high_score(), &restored).unwrap();
+    let loaded: HighScore = load_file_signed(&path, 1, &public_key).unwrap();
+    assert_eq!(loaded, high_score());
+    std::fs::remove_file(&path).unwrap();
+}
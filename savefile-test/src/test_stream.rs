@@ -0,0 +1,67 @@
+use savefile::prelude::*;
+
+#[derive(Savefile, Debug, PartialEq)]
+struct TelemetryRecord {
+    id: u32,
+    name: String,
+    values: Vec<f64>,
+}
+
+#[derive(Savefile, Debug, PartialEq)]
+struct OtherRecord {
+    id: u64,
+}
+
+fn sample(id: u32) -> TelemetryRecord {
+    TelemetryRecord {
+        id,
+        name: format!("record{}", id),
+        values: vec![id as f64; id as usize],
+    }
+}
+
+#[test]
+pub fn test_stream_roundtrip() {
+    let mut writer = StreamWriter::new(Vec::new(), 0).unwrap();
+    for i in 0..10 {
+        writer.write_record(&sample(i)).unwrap();
+    }
+    let data = writer.into_inner().unwrap();
+
+    let reader = StreamReader::<_, TelemetryRecord>::new(&data[..], 0).unwrap();
+    let records: Vec<TelemetryRecord> = reader.map(|x| x.unwrap()).collect();
+    assert_eq!(records, (0..10).map(sample).collect::<Vec<_>>());
+}
+
+#[test]
+pub fn test_stream_empty() {
+    let writer = StreamWriter::<_, TelemetryRecord>::new(Vec::new(), 0).unwrap();
+    let data = writer.into_inner().unwrap();
+    let mut reader = StreamReader::<_, TelemetryRecord>::new(&data[..], 0).unwrap();
+    assert!(reader.next().is_none());
+}
+
+#[test]
+pub fn test_stream_truncated_final_record() {
+    let mut writer = StreamWriter::new(Vec::new(), 0).unwrap();
+    for i in 0..3 {
+        writer.write_record(&sample(i)).unwrap();
+    }
+    let data = writer.into_inner().unwrap();
+
+    for cut in 1..20 {
+        let truncated = &data[..data.len() - cut];
+        let reader = StreamReader::<_, TelemetryRecord>::new(truncated, 0).unwrap();
+        let records: Vec<TelemetryRecord> = reader.map(|x| x.unwrap()).collect();
+        assert_eq!(records, vec![sample(0), sample(1)]);
+    }
+}
+
+#[test]
+pub fn test_stream_schema_mismatch() {
+    let mut writer = StreamWriter::new(Vec::new(), 0).unwrap();
+    writer.write_record(&sample(1)).unwrap();
+    let data = writer.into_inner().unwrap();
+    let result = StreamReader::<_, OtherRecord>::new(&data[..], 0);
+    assert!(matches!(result, Err(SavefileError::IncompatibleSchema { .. })));
+}
@@ -0,0 +1,327 @@
+use savefile::prelude::*;
+use savefile::{load_dynamic, save_compressed_with, save_dynamic, save_with_checksum, DynamicFile, Value};
+use std::collections::BTreeMap;
+
+#[derive(Savefile, Debug, PartialEq)]
+enum Shape {
+    Empty,
+    Circle(f64),
+    Rect { width: u32, height: u32 },
+}
+
+#[derive(Savefile, Debug, PartialEq)]
+struct Drawing {
+    title: String,
+    initial: char,
+    shapes: Vec<Shape>,
+    origin: [i16; 2],
+    layer: Option<u8>,
+    hidden: Option<u8>,
+    tags: BTreeMap<String, u64>,
+    big: u128,
+    visible: bool,
+    count: usize,
+}
+
+fn drawing() -> Drawing {
+    let mut tags = BTreeMap::new();
+    tags.insert("a".to_string(), 1);
+    tags.insert("b".to_string(), 2);
+    Drawing {
+        title: "sketch".to_string(),
+        initial: 'ß',
+        shapes: vec![Shape::Empty, Shape::Circle(1.5), Shape::Rect { width: 3, height: 4 }],
+        origin: [-1, 2],
+        layer: Some(7),
+        hidden: None,
+        tags,
+        big: u128::MAX - 1,
+        visible: true,
+        count: 42,
+    }
+}
+
+fn resave(file: &DynamicFile) -> Vec<u8> {
+    let mut data = Vec::new();
+    save_dynamic(&mut data, file).unwrap();
+    data
+}
+
+#[test]
+fn test_value_load_struct() {
+    let data = save_to_mem(1, &drawing()).unwrap();
+    let file = load_dynamic(&mut &data[..]).unwrap();
+    assert_eq!(file.version, 1);
+    let value = &file.value;
+    assert_eq!(value.type_name(), "Drawing");
+    assert_eq!(value.field("title"), Some(&Value::String("sketch".to_string())));
+    assert_eq!(value.field("initial"), Some(&Value::Char('ß')));
+    assert_eq!(
+        value.field("origin"),
+        Some(&Value::Array(vec![Value::I16(-1), Value::I16(2)]))
+    );
+    assert_eq!(value.field("layer"), Some(&Value::Option(Some(Box::new(Value::U8(7))))));
+    assert_eq!(value.field("hidden"), Some(&Value::Option(None)));
+    assert_eq!(value.field("big"), Some(&Value::U128(u128::MAX - 1)));
+    assert_eq!(value.field("visible"), Some(&Value::Bool(true)));
+    assert_eq!(value.field("count"), Some(&Value::U64(42)));
+
+    let Some(Value::Vector(shapes)) = value.field("shapes") else {
+        panic!("Expected vector of shapes");
+    };
+    assert_eq!(
+        shapes[2],
+        Value::Enum {
+            name: "Shape".to_string(),
+            variant: "Rect".to_string(),
+            fields: vec![
+                ("width".to_string(), Value::U32(3)),
+                ("height".to_string(), Value::U32(4)),
+            ]
+        }
+    );
+    let Value::Enum { variant, fields, .. } = &shapes[1] else {
+        panic!("Expected enum");
+    };
+    assert_eq!(variant, "Circle");
+    assert_eq!(fields[0].1, Value::F64(1.5));
+
+    let Some(Value::Vector(tags)) = value.field("tags") else {
+        panic!("Expected vector of map entries");
+    };
+    assert_eq!(tags.len(), 2);
+    assert_eq!(tags[1].field("key"), Some(&Value::String("b".to_string())));
+    assert_eq!(tags[1].field("value"), Some(&Value::U64(2)));
+}
+
+#[test]
+fn test_value_resave_is_identical() {
+    let data = save_to_mem(1, &drawing()).unwrap();
+    let file = load_dynamic(&mut &data[..]).unwrap();
+    assert_eq!(resave(&file), data);
+}
+
+#[test]
+fn test_value_modify_and_load_typed() {
+    let data = save_to_mem(1, &drawing()).unwrap();
+    let mut file = load_dynamic(&mut &data[..]).unwrap();
+    *file.value.field_mut("title").unwrap() = Value::String("edited".to_string());
+    *file.value.field_mut("hidden").unwrap() = Value::Option(Some(Box::new(Value::U8(3))));
+
+    let loaded: Drawing = load_from_mem(&resave(&file), 1).unwrap();
+    let mut expected = drawing();
+    expected.title = "edited".to_string();
+    expected.hidden = Some(3);
+    assert_eq!(loaded, expected);
+}
+
+#[test]
+fn test_value_mismatch_is_rejected() {
+    let data = save_to_mem(1, &drawing()).unwrap();
+    let mut file = load_dynamic(&mut &data[..]).unwrap();
+    *file.value.field_mut("count").unwrap() = Value::U32(1);
+    let mut out = Vec::new();
+    assert!(save_dynamic(&mut out, &file).is_err());
+
+    let mut file = load_dynamic(&mut &data[..]).unwrap();
+    if let Some(Value::Vector(shapes)) = file.value.field_mut("shapes") {
+        shapes.push(Value::Enum {
+            name: "Shape".to_string(),
+            variant: "Triangle".to_string(),
+            fields: vec![],
+        });
+    }
+    assert!(save_dynamic(&mut Vec::new(), &file).is_err());
+}
+
+#[test]
+fn test_value_compressed_and_checksummed() {
+    let mut data = Vec::new();
+    save_compressed_with(
+        &mut data,
+        1,
+        &drawing(),
+        CompressionOptions::new(CompressionCodec::Zstd),
+    )
+    .unwrap();
+    let compressed = load_dynamic(&mut &data[..]).unwrap();
+    let plain = load_dynamic(&mut &save_to_mem(1, &drawing()).unwrap()[..]).unwrap();
+    assert_eq!(compressed, plain);
+
+    let mut data = Vec::new();
+    save_with_checksum(&mut data, 1, &drawing(), ChecksumAlgorithm::Crc32c).unwrap();
+    assert_eq!(load_dynamic(&mut &data[..]).unwrap(), plain);
+    let last = data.len() - 1;
+    data[last] ^= 1;
+    assert!(load_dynamic(&mut &data[..]).is_err());
+}
+
+#[derive(Savefile, Debug, PartialEq)]
+struct OldThing {
+    name: String,
+    #[savefile_versions = "1.."]
+    extra: u32,
+}
+
+#[test]
+fn test_value_any_version() {
+    let data = save_to_mem(
+        7,
+        &OldThing {
+            name: "x".to_string(),
+            extra: 5,
+        },
+    )
+    .unwrap();
+    let file = load_dynamic(&mut &data[..]).unwrap();
+    assert_eq!(file.version, 7);
+    assert_eq!(file.value.field("extra"), Some(&Value::U32(5)));
+
+    let data = save_to_mem(
+        0,
+        &OldThing {
+            name: "x".to_string(),
+            extra: 5,
+        },
+    )
+    .unwrap();
+    let file = load_dynamic(&mut &data[..]).unwrap();
+    assert_eq!(file.version, 0);
+    assert_eq!(file.value.field("extra"), None);
+    assert_eq!(resave(&file), data);
+}
+
+#[derive(Savefile, Debug, PartialEq)]
+#[repr(u16)]
+enum Wide {
+    First,
+    Second(u8),
+}
+
+#[test]
+fn test_value_wide_discriminant() {
+    let data = save_to_mem(0, &vec![Wide::Second(9), Wide::First]).unwrap();
+    let file = load_dynamic(&mut &data[..]).unwrap();
+    let Value::Vector(items) = &file.value else {
+        panic!("Expected vector");
+    };
+    let Value::Enum { variant, .. } = &items[0] else {
+        panic!("Expected enum");
+    };
+    assert_eq!(variant, "Second");
+    assert_eq!(resave(&file), data);
+}
+
+/// Hand-written enum with the sparse discriminants 0 and 1000
+struct Sparse(u16);
+
+impl WithSchema for Sparse {
+    fn schema(_version: u32, _context: &mut WithSchemaContext) -> Schema {
+        let variant = |name: &str, discriminant: u16| Variant {
+            name: name.to_string(),
+            discriminant: discriminant as u8,
+            fields: vec![],
+        };
+        Schema::Enum(SchemaEnum::new(
+            "Sparse".to_string(),
+            2,
+            vec![variant("A", 0), variant("B", 1000)],
+        ))
+    }
+}
+
+impl Serialize for Sparse {
+    fn serialize(&self, serializer: &mut Serializer<impl std::io::Write>) -> Result<(), SavefileError> {
+        serializer.write_u16(self.0)
+    }
+}
+
+#[test]
+fn test_value_ambiguous_discriminants_rejected() {
+    // Only the lowest byte of each discriminant is in the schema, so B cannot be decoded
+    let data = save_to_mem(0, &Sparse(1000)).unwrap();
+    match load_dynamic(&mut &data[..]) {
+        Err(SavefileError::GeneralError { msg }) => assert!(msg.contains("cannot be determined"), "{}", msg),
+        other => panic!("Unexpected result: {:?}", other),
+    }
+}
+
+#[derive(Savefile, Debug, PartialEq)]
+struct TreeNode {
+    label: u32,
+    children: Vec<TreeNode>,
+}
+
+#[derive(Savefile, Debug, PartialEq)]
+enum Expr {
+    Literal(i64),
+    Neg(Box<Expr>),
+    Add(Box<Expr>, Box<Expr>),
+}
+
+#[derive(Savefile, Debug, PartialEq)]
+struct Program {
+    main: Expr,
+    tree: TreeNode,
+}
+
+#[derive(Savefile, Debug, PartialEq)]
+struct Config {
+    program: Program,
+    next: Option<Box<Config>>,
+}
+
+fn config() -> Config {
+    let leaf = |label| TreeNode {
+        label,
+        children: vec![],
+    };
+    Config {
+        program: Program {
+            main: Expr::Add(
+                Box::new(Expr::Neg(Box::new(Expr::Literal(3)))),
+                Box::new(Expr::Add(
+                    Box::new(Expr::Literal(1)),
+                    Box::new(Expr::Neg(Box::new(Expr::Literal(2)))),
+                )),
+            ),
+            tree: TreeNode {
+                label: 1,
+                children: vec![
+                    TreeNode {
+                        label: 2,
+                        children: vec![leaf(3), leaf(4)],
+                    },
+                    leaf(5),
+                ],
+            },
+        },
+        next: Some(Box::new(Config {
+            program: Program {
+                main: Expr::Neg(Box::new(Expr::Neg(Box::new(Expr::Literal(4))))),
+                tree: leaf(6),
+            },
+            next: None,
+        })),
+    }
+}
+
+#[test]
+fn test_value_recursive_types() {
+    let data = save_to_mem(0, &config()).unwrap();
+    let file = load_dynamic(&mut &data[..]).unwrap();
+    assert_eq!(resave(&file), data);
+
+    let Some(Value::Option(Some(next))) = file.value.field("next") else {
+        panic!("Expected next config");
+    };
+    let main = next.field("program").unwrap().field("main").unwrap();
+    let Value::Enum { variant, fields, .. } = main else {
+        panic!("Expected enum");
+    };
+    assert_eq!(variant, "Neg");
+    assert_eq!(fields[0].1.type_name(), "Expr");
+
+    let loaded: Config = load_from_mem(&resave(&file), 0).unwrap();
+    assert_eq!(loaded, config());
+}
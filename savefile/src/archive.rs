@@ -0,0 +1,274 @@
+use crate::{
+    new_schema_deserializer, read_file_header, write_file_header, CompressionOptions, Deserialize, Deserializer,
+    SavefileError, Schema, Serialize, Serializer, WithSchema, WithSchemaContext, CURRENT_SAVEFILE_LIB_VERSION,
+};
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use std::io::{Read, Seek, SeekFrom, Write};
+
+/// The 'file version' written in the header of an archive.
+/// This is the version of the archive container format itself, not of any entry.
+const ARCHIVE_FORMAT_VERSION: u32 = 0;
+
+/// Archives are marked by having this custom schema in their header. This makes
+/// a plain [crate::load] of an archive fail with an 'IncompatibleSchema'-error.
+const ARCHIVE_SCHEMA_MARKER: &str = "savefile-archive";
+
+/// Information about a single entry in an archive.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ArchiveEntry {
+    /// Name of the entry. Unique within the archive.
+    pub name: String,
+    /// The version the entry was saved with
+    pub version: u32,
+    /// True if the entry is bzip2-compressed
+    pub compressed: bool,
+    /// Size of the entry in the archive, in bytes (including its schema)
+    pub size: u64,
+    /// Position of the entry, from the start of the archive
+    offset: u64,
+}
+
+impl ArchiveEntry {
+    fn serialize(&self, serializer: &mut Serializer<impl Write>) -> Result<(), SavefileError> {
+        serializer.write_string(&self.name)?;
+        serializer.write_u32(self.version)?;
+        serializer.write_bool(self.compressed)?;
+        serializer.write_u64(self.offset)?;
+        serializer.write_u64(self.size)?;
+        Ok(())
+    }
+    fn deserialize(deserializer: &mut Deserializer<impl Read>) -> Result<ArchiveEntry, SavefileError> {
+        Ok(ArchiveEntry {
+            name: deserializer.read_string()?,
+            version: deserializer.read_u32()?,
+            compressed: deserializer.read_bool()?,
+            offset: deserializer.read_u64()?,
+            size: deserializer.read_u64()?,
+        })
+    }
+}
+
+/// Write adapter keeping track of the current position in the archive.
+struct CountingWriter<'a, W: Write> {
+    writer: &'a mut W,
+    count: u64,
+}
+
+impl<'a, W: Write> Write for CountingWriter<'a, W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let written = self.writer.write(buf)?;
+        self.count += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// Writes an archive consisting of several named entries.
+///
+/// Each entry has its own version, its own schema and can optionally be
+/// compressed. An index of all entries is written at the end of the archive
+/// by [ArchiveWriter::finish], which makes it possible for [ArchiveReader]
+/// to load a single entry without reading the others.
+///
+/// Entries are written to the underlying writer as they are added.
+pub struct ArchiveWriter<W: Write> {
+    writer: W,
+    position: u64,
+    entries: Vec<ArchiveEntry>,
+}
+
+impl<W: Write> ArchiveWriter<W> {
+    /// Create a new archive, immediately writing the archive header to `writer`.
+    pub fn new(mut writer: W) -> Result<ArchiveWriter<W>, SavefileError> {
+        let mut counter = CountingWriter {
+            writer: &mut writer,
+            count: 0,
+        };
+        write_file_header(&mut counter, CURRENT_SAVEFILE_LIB_VERSION, ARCHIVE_FORMAT_VERSION)?;
+        counter.write_u8(0)?;
+        let mut schema_serializer = Serializer::<W>::new_raw(&mut counter, CURRENT_SAVEFILE_LIB_VERSION as u32);
+        Schema::Custom(ARCHIVE_SCHEMA_MARKER.to_string()).serialize(&mut schema_serializer)?;
+        let position = counter.count;
+        Ok(ArchiveWriter {
+            writer,
+            position,
+            entries: Vec::new(),
+        })
+    }
+
+    /// Add an entry with the given name. The current version of data must be `version`.
+    pub fn add_entry<T: WithSchema + Serialize>(
+        &mut self,
+        name: &str,
+        version: u32,
+        data: &T,
+    ) -> Result<(), SavefileError> {
+        self.add_entry_impl(name, version, data, false)
+    }
+
+    /// Like [ArchiveWriter::add_entry], but compresses the entry using bzip2.
+    /// Note, this function will fail if the bzip2-feature is not enabled.
+    pub fn add_entry_compressed<T: WithSchema + Serialize>(
+        &mut self,
+        name: &str,
+        version: u32,
+        data: &T,
+    ) -> Result<(), SavefileError> {
+        self.add_entry_impl(name, version, data, true)
+    }
+
+    fn add_entry_impl<T: WithSchema + Serialize>(
+        &mut self,
+        name: &str,
+        version: u32,
+        data: &T,
+        compressed: bool,
+    ) -> Result<(), SavefileError> {
+        if self.entries.iter().any(|x| x.name == name) {
+            return Err(SavefileError::GeneralError {
+                msg: format!("Archive already contains an entry named '{}'", name),
+            });
+        }
+        let mut counter = CountingWriter {
+            writer: &mut self.writer,
+            count: 0,
+        };
+        Serializer::save_payload(
+            &mut counter,
+            version,
+            data,
+            Some(T::schema(version, &mut WithSchemaContext::new())),
+            compressed.then(CompressionOptions::default),
+            None,
+            None,
+        )?;
+        let size = counter.count;
+        self.entries.push(ArchiveEntry {
+            name: name.to_string(),
+            version,
+            compressed,
+            size,
+            offset: self.position,
+        });
+        self.position += size;
+        Ok(())
+    }
+
+    /// The entries added so far
+    pub fn entries(&self) -> &[ArchiveEntry] {
+        &self.entries
+    }
+
+    /// Write the index of the archive, flush, and return the underlying writer.
+    /// An archive which has not been finished cannot be read.
+    pub fn finish(mut self) -> Result<W, SavefileError> {
+        let index_offset = self.position;
+        {
+            let mut serializer = Serializer::<W>::new_raw(&mut self.writer, CURRENT_SAVEFILE_LIB_VERSION as u32);
+            serializer.write_usize(self.entries.len())?;
+            for entry in &self.entries {
+                entry.serialize(&mut serializer)?;
+            }
+        }
+        self.writer.write_u64::<LittleEndian>(index_offset)?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+/// Reads an archive previously written by [ArchiveWriter].
+///
+/// Creating the reader only reads the header and the index. Entries are
+/// loaded on demand using [ArchiveReader::load_entry].
+pub struct ArchiveReader<R: Read + Seek> {
+    reader: R,
+    savefile_lib_version: u16,
+    entries: Vec<ArchiveEntry>,
+}
+
+impl<R: Read + Seek> ArchiveReader<R> {
+    /// Open the archive, reading its header and index.
+    pub fn new(mut reader: R) -> Result<ArchiveReader<R>, SavefileError> {
+        reader.seek(SeekFrom::Start(0))?;
+        let (savefile_lib_version, _) = read_file_header(&mut reader, ARCHIVE_FORMAT_VERSION)?;
+        let compressed = reader.read_u8()? != 0;
+        let marker = {
+            let mut schema_deserializer = new_schema_deserializer(&mut reader, savefile_lib_version);
+            Schema::deserialize(&mut schema_deserializer)?
+        };
+        if compressed || marker != Schema::Custom(ARCHIVE_SCHEMA_MARKER.to_string()) {
+            return Err(SavefileError::GeneralError {
+                msg: "File is not a savefile archive.".into(),
+            });
+        }
+
+        reader.seek(SeekFrom::End(-8))?;
+        let index_offset = reader.read_u64::<LittleEndian>()?;
+        reader.seek(SeekFrom::Start(index_offset))?;
+        let entries = {
+            let mut deserializer = new_schema_deserializer(&mut reader, savefile_lib_version);
+            let count = deserializer.read_usize()?;
+            let mut entries = Vec::new();
+            for _ in 0..count {
+                entries.push(ArchiveEntry::deserialize(&mut deserializer)?);
+            }
+            entries
+        };
+        Ok(ArchiveReader {
+            reader,
+            savefile_lib_version,
+            entries,
+        })
+    }
+
+    /// All entries in the archive, in the order they were added
+    pub fn entries(&self) -> &[ArchiveEntry] {
+        &self.entries
+    }
+
+    /// The names of all entries in the archive
+    pub fn entry_names(&self) -> impl Iterator<Item = &str> {
+        self.entries.iter().map(|x| x.name.as_str())
+    }
+
+    /// Find the entry with the given name
+    pub fn entry(&self, name: &str) -> Option<&ArchiveEntry> {
+        self.entries.iter().find(|x| x.name == name)
+    }
+
+    /// Load the entry with the given name.
+    /// The current version of T in memory must be `version`.
+    /// The schema stored with the entry is checked, just like for [crate::load].
+    pub fn load_entry<T: WithSchema + Deserialize>(&mut self, name: &str, version: u32) -> Result<T, SavefileError> {
+        let Some(entry) = self.entry(name) else {
+            return Err(SavefileError::GeneralError {
+                msg: format!("Archive does not contain an entry named '{}'", name),
+            });
+        };
+        if entry.version > version {
+            return Err(SavefileError::WrongVersion {
+                msg: format!(
+                    "Archive entry '{}' has later version ({}) than structs in memory ({}).",
+                    name, entry.version, version
+                ),
+            });
+        }
+        let (offset, size, entry_version) = (entry.offset, entry.size, entry.version);
+        self.reader.seek(SeekFrom::Start(offset))?;
+        let mut entry_reader = (&mut self.reader).take(size);
+        Deserializer::load_payload::<T>(
+            &mut entry_reader,
+            self.savefile_lib_version,
+            entry_version,
+            Some(|version| T::schema(version, &mut WithSchemaContext::new())),
+        )
+    }
+
+    /// Return the underlying reader
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
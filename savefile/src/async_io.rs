@@ -0,0 +1,157 @@
+use crate::{Deserialize, Deserializer, SavefileError, Serialize, Serializer, WithSchema};
+use std::io::{BufWriter, ErrorKind, Read, Write};
+use std::sync::Arc;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::sync::mpsc;
+
+/// Size of the chunks handed between the async task and the blocking (de)serializer thread.
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Number of chunks which may be in flight between the async task and the blocking thread.
+/// This bounds the amount of memory used, regardless of the size of the saved object.
+const CHANNEL_CAPACITY: usize = 4;
+
+/// Sync writer used by the blocking serializer thread, handing chunks over to the async task.
+struct ChannelWriter {
+    tx: mpsc::Sender<Vec<u8>>,
+}
+
+impl Write for ChannelWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.tx
+            .blocking_send(buf.to_vec())
+            .map_err(|_| std::io::Error::new(ErrorKind::BrokenPipe, "Async writer has gone away"))?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Sync reader used by the blocking deserializer thread, receiving chunks from the async task.
+/// The end of the input is signalled by the async task dropping the sender.
+struct ChannelReader {
+    rx: mpsc::Receiver<Vec<u8>>,
+    chunk: Vec<u8>,
+    pos: usize,
+}
+
+impl Read for ChannelReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        while self.pos == self.chunk.len() {
+            match self.rx.blocking_recv() {
+                Some(chunk) => {
+                    self.chunk = chunk;
+                    self.pos = 0;
+                }
+                None => return Ok(0),
+            }
+        }
+        let got = buf.len().min(self.chunk.len() - self.pos);
+        buf[..got].copy_from_slice(&self.chunk[self.pos..self.pos + got]);
+        self.pos += got;
+        Ok(got)
+    }
+}
+
+fn join_error(err: tokio::task::JoinError) -> SavefileError {
+    SavefileError::GeneralError {
+        msg: format!("Savefile blocking task failed: {}", err),
+    }
+}
+
+async fn save_async_impl<T: WithSchema + Serialize + Send + Sync + 'static>(
+    writer: &mut (impl AsyncWrite + Unpin),
+    version: u32,
+    data: Arc<T>,
+    with_compression: bool,
+) -> Result<(), SavefileError> {
+    let (tx, mut rx) = mpsc::channel::<Vec<u8>>(CHANNEL_CAPACITY);
+    let task = tokio::task::spawn_blocking(move || {
+        let mut chunked = BufWriter::with_capacity(CHUNK_SIZE, ChannelWriter { tx });
+        Serializer::save::<T>(&mut chunked, version, &*data, with_compression)?;
+        chunked.flush()?;
+        Ok::<(), SavefileError>(())
+    });
+    while let Some(chunk) = rx.recv().await {
+        writer.write_all(&chunk).await?;
+    }
+    task.await.map_err(join_error)??;
+    writer.flush().await?;
+    Ok(())
+}
+
+/// Write the given `data` to the async `writer`. The current version of data must be `version`.
+///
+/// The data is serialized on a blocking thread (using `tokio::task::spawn_blocking`), and
+/// handed over to the writer in chunks. The serialized object is never buffered in full.
+/// Since the data is accessed from another thread, it must be given as an `Arc`.
+///
+/// The result can be loaded using [load_async] or the regular [crate::load].
+/// This function must be called from within a tokio runtime.
+pub async fn save_async<T: WithSchema + Serialize + Send + Sync + 'static>(
+    writer: &mut (impl AsyncWrite + Unpin),
+    version: u32,
+    data: Arc<T>,
+) -> Result<(), SavefileError> {
+    save_async_impl(writer, version, data, false).await
+}
+
+/// Like [save_async], but compresses the data using 'bzip2' compression format.
+/// Note, this function will fail if the bzip2-feature is not enabled.
+pub async fn save_compressed_async<T: WithSchema + Serialize + Send + Sync + 'static>(
+    writer: &mut (impl AsyncWrite + Unpin),
+    version: u32,
+    data: Arc<T>,
+) -> Result<(), SavefileError> {
+    save_async_impl(writer, version, data, true).await
+}
+
+/// Load an object of type T from the async `reader`. The current version of T in memory must
+/// be `version`. The schema in the data is checked, and compressed data is decompressed,
+/// exactly like for [crate::load].
+///
+/// The input is read in chunks, which are deserialized on a blocking thread (using
+/// `tokio::task::spawn_blocking`). The whole input is never buffered in memory.
+///
+/// Note, the reader is read in chunks, so it may have been read past the end of the savefile
+/// data when this function returns. The reader should thus not contain anything after the
+/// saved object.
+///
+/// This function must be called from within a tokio runtime.
+pub async fn load_async<T: WithSchema + Deserialize + Send + 'static>(
+    reader: &mut (impl AsyncRead + Unpin),
+    version: u32,
+) -> Result<T, SavefileError> {
+    let (tx, rx) = mpsc::channel::<Vec<u8>>(CHANNEL_CAPACITY);
+    let mut task = tokio::task::spawn_blocking(move || {
+        let mut chunked = ChannelReader {
+            rx,
+            chunk: Vec::new(),
+            pos: 0,
+        };
+        Deserializer::load::<T>(&mut chunked, version)
+    });
+    let mut tx = Some(tx);
+    let mut buf = vec![0u8; CHUNK_SIZE];
+    loop {
+        tokio::select! {
+            result = &mut task => {
+                return result.map_err(join_error)?;
+            }
+            got = reader.read(&mut buf), if tx.is_some() => {
+                let got = got?;
+                if got == 0 {
+                    // Signal end of input to the deserializer
+                    tx = None;
+                } else if let Some(sender) = &tx {
+                    if sender.send(buf[..got].to_vec()).await.is_err() {
+                        // The deserializer is done, its result is picked up above
+                        tx = None;
+                    }
+                }
+            }
+        }
+    }
+}
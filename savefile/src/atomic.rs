@@ -0,0 +1,130 @@
+use crate::{SavefileError, Serialize, Serializer, WithSchema};
+use std::ffi::OsString;
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Path of `filepath` with `suffix` appended to the file name
+fn with_suffix(filepath: &Path, suffix: &str) -> PathBuf {
+    let mut name: OsString = filepath.file_name().map(|x| x.to_owned()).unwrap_or_default();
+    name.push(suffix);
+    filepath.with_file_name(name)
+}
+
+/// Create a new, uniquely named, temporary file next to `filepath`
+fn create_temp_sibling(filepath: &Path) -> Result<(PathBuf, File), SavefileError> {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    loop {
+        let unique = COUNTER.fetch_add(1, Ordering::Relaxed);
+        let temp_path = with_suffix(filepath, &format!(".{}.{}.tmp", std::process::id(), unique));
+        match OpenOptions::new().write(true).create_new(true).open(&temp_path) {
+            Ok(file) => return Ok((temp_path, file)),
+            Err(err) if err.kind() == ErrorKind::AlreadyExists => continue,
+            Err(err) => return Err(err.into()),
+        }
+    }
+}
+
+/// Make a rename or link within the directory of `filepath` durable
+fn sync_parent_dir(filepath: &Path) -> Result<(), SavefileError> {
+    #[cfg(unix)]
+    {
+        let dir = match filepath.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        File::open(dir)?.sync_all()?;
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = filepath;
+    }
+    Ok(())
+}
+
+/// Keep the current contents of `filepath` (if any) as `<filepath>.bak`.
+/// The file at `filepath` itself is left in place.
+fn make_backup(filepath: &Path) -> Result<(), SavefileError> {
+    let backup_path = with_suffix(filepath, ".bak");
+    match std::fs::remove_file(&backup_path) {
+        Ok(()) => {}
+        Err(err) if err.kind() == ErrorKind::NotFound => {}
+        Err(err) => return Err(err.into()),
+    }
+    match std::fs::hard_link(filepath, &backup_path) {
+        Ok(()) => Ok(()),
+        Err(err) if err.kind() == ErrorKind::NotFound => Ok(()),
+        Err(_) => {
+            // Hard links are not supported by all file systems
+            match std::fs::copy(filepath, &backup_path) {
+                Ok(_) => Ok(File::open(&backup_path)?.sync_all()?),
+                Err(err) if err.kind() == ErrorKind::NotFound => Ok(()),
+                Err(err) => Err(err.into()),
+            }
+        }
+    }
+}
+
+/// Atomically replace the file at `filepath` with contents produced by `write`.
+///
+/// The contents are written to a temporary file in the same directory, which is
+/// synced to disk and then renamed to `filepath`. The directory is then synced, making
+/// the rename durable. If the process crashes or the machine loses power while
+/// saving, `filepath` contains either the old or the new contents, never a mix.
+///
+/// If `keep_backup` is true, the previous contents of `filepath` (if any) are kept as
+/// `<filepath>.bak`, replacing any earlier backup.
+///
+/// If `write` fails, the temporary file is removed and `filepath` is left untouched.
+pub fn write_file_atomic<P: AsRef<Path>>(
+    filepath: P,
+    keep_backup: bool,
+    write: impl FnOnce(&mut BufWriter<File>) -> Result<(), SavefileError>,
+) -> Result<(), SavefileError> {
+    let filepath = filepath.as_ref();
+    let (temp_path, file) = create_temp_sibling(filepath)?;
+    let result = (|| {
+        let mut writer = BufWriter::new(file);
+        write(&mut writer)?;
+        let file = writer.into_inner().map_err(|err| err.into_error())?;
+        file.sync_all()?;
+        drop(file);
+        if keep_backup {
+            make_backup(filepath)?;
+        }
+        std::fs::rename(&temp_path, filepath)?;
+        Ok(())
+    })();
+    if let Err(err) = result {
+        let _ = std::fs::remove_file(&temp_path);
+        return Err(err);
+    }
+    sync_parent_dir(filepath)
+}
+
+/// Like [crate::save_file], except the file is replaced atomically. See [write_file_atomic].
+/// If `keep_backup` is true, the previous file is kept as `<filepath>.bak`.
+pub fn save_file_atomic<T: WithSchema + Serialize, P: AsRef<Path>>(
+    filepath: P,
+    version: u32,
+    data: &T,
+    keep_backup: bool,
+) -> Result<(), SavefileError> {
+    write_file_atomic(filepath, keep_backup, |writer| {
+        Serializer::save::<T>(writer, version, data, false)
+    })
+}
+
+/// Like [crate::save_file_compressed], except the file is replaced atomically. See [write_file_atomic].
+/// If `keep_backup` is true, the previous file is kept as `<filepath>.bak`.
+pub fn save_file_compressed_atomic<T: WithSchema + Serialize, P: AsRef<Path>>(
+    filepath: P,
+    version: u32,
+    data: &T,
+    keep_backup: bool,
+) -> Result<(), SavefileError> {
+    write_file_atomic(filepath, keep_backup, |writer| {
+        Serializer::save::<T>(writer, version, data, true)
+    })
+}
@@ -0,0 +1,263 @@
+use crate::SavefileError;
+use byteorder::{LittleEndian, WriteBytesExt};
+use std::io::{Read, Write};
+
+/// Flag set in the compression byte of the savefile header, if the file has a checksum.
+/// The compression byte is then followed by a byte identifying the [ChecksumAlgorithm].
+pub(crate) const CHECKSUM_FLAG: u8 = 0x80;
+
+/// Algorithm used to checksum the contents of a savefile.
+///
+/// The checksum covers the schema and the data (before any compression), and is
+/// verified when the file is loaded. A mismatch is reported as
+/// [SavefileError::ChecksumMismatch].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ChecksumAlgorithm {
+    /// CRC-32C (Castagnoli). Always available.
+    Crc32c,
+    /// xxHash64. Faster than CRC-32C for large files. Requires the 'xxhash'-feature.
+    XxHash64,
+}
+
+impl ChecksumAlgorithm {
+    pub(crate) fn header_byte(self) -> u8 {
+        match self {
+            ChecksumAlgorithm::Crc32c => 1,
+            ChecksumAlgorithm::XxHash64 => 2,
+        }
+    }
+
+    pub(crate) fn from_header_byte(byte: u8) -> Result<ChecksumAlgorithm, SavefileError> {
+        match byte {
+            1 => Ok(ChecksumAlgorithm::Crc32c),
+            2 => Ok(ChecksumAlgorithm::XxHash64),
+            _ => Err(SavefileError::GeneralError {
+                msg: format!("File has a checksum of an unknown type ({}).", byte),
+            }),
+        }
+    }
+}
+
+const fn make_crc32c_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0x82F6_3B78
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+static CRC32C_TABLE: [u32; 256] = make_crc32c_table();
+
+/// Incremental checksum calculation
+pub(crate) enum Checksum {
+    Crc32c(u32),
+    #[cfg(feature = "xxhash")]
+    XxHash64(Box<xxhash_rust::xxh64::Xxh64>),
+}
+
+impl Checksum {
+    pub(crate) fn new(algorithm: ChecksumAlgorithm) -> Result<Checksum, SavefileError> {
+        match algorithm {
+            ChecksumAlgorithm::Crc32c => Ok(Checksum::Crc32c(!0)),
+            #[cfg(feature = "xxhash")]
+            ChecksumAlgorithm::XxHash64 => Ok(Checksum::XxHash64(Box::new(xxhash_rust::xxh64::Xxh64::new(0)))),
+            #[cfg(not(feature = "xxhash"))]
+            ChecksumAlgorithm::XxHash64 => Err(SavefileError::GeneralError {
+                msg: "xxHash64 checksum support missing - recompile with xxhash feature enabled.".into(),
+            }),
+        }
+    }
+
+    pub(crate) fn update(&mut self, buf: &[u8]) {
+        match self {
+            Checksum::Crc32c(crc) => {
+                for byte in buf {
+                    *crc = CRC32C_TABLE[((*crc ^ *byte as u32) & 0xff) as usize] ^ (*crc >> 8);
+                }
+            }
+            #[cfg(feature = "xxhash")]
+            Checksum::XxHash64(hasher) => hasher.update(buf),
+        }
+    }
+
+    pub(crate) fn finish(&self) -> u64 {
+        match self {
+            Checksum::Crc32c(crc) => !*crc as u64,
+            #[cfg(feature = "xxhash")]
+            Checksum::XxHash64(hasher) => hasher.digest(),
+        }
+    }
+}
+
+/// Writer which checksums everything written through it
+pub(crate) struct ChecksumWriter<'a, W: Write> {
+    writer: &'a mut W,
+    checksum: Checksum,
+}
+
+impl<'a, W: Write> ChecksumWriter<'a, W> {
+    pub(crate) fn new(writer: &'a mut W, algorithm: ChecksumAlgorithm) -> Result<Self, SavefileError> {
+        Ok(ChecksumWriter {
+            writer,
+            checksum: Checksum::new(algorithm)?,
+        })
+    }
+
+    /// Write the checksum of everything written so far. The checksum itself is not checksummed.
+    pub(crate) fn write_checksum(self) -> Result<(), SavefileError> {
+        self.writer.write_u64::<LittleEndian>(self.checksum.finish())?;
+        Ok(())
+    }
+}
+
+impl<W: Write> Write for ChecksumWriter<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let written = self.writer.write(buf)?;
+        self.checksum.update(&buf[..written]);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// Holds back the last `N` bytes of a stream, passing everything before them on.
+///
+/// Used by readers of data followed by a trailer, when the end of the data is not
+/// known in advance. When the end of the stream is reached, the trailer is held back.
+pub(crate) struct HeldBackTail<const N: usize> {
+    tail: [u8; N],
+    len: usize,
+}
+
+impl<const N: usize> HeldBackTail<N> {
+    pub(crate) fn new() -> Self {
+        HeldBackTail { tail: [0; N], len: 0 }
+    }
+
+    /// Add `buf` to the end of the stream. Bytes which are no longer among
+    /// the last `N` are passed to `emit`, in order.
+    pub(crate) fn push(&mut self, buf: &[u8], mut emit: impl FnMut(&[u8])) {
+        let total = self.len + buf.len();
+        if total <= N {
+            self.tail[self.len..total].copy_from_slice(buf);
+            self.len = total;
+            return;
+        }
+        let to_emit = total - N;
+        let from_tail = to_emit.min(self.len);
+        let from_buf = to_emit - from_tail;
+        emit(&self.tail[..from_tail]);
+        emit(&buf[..from_buf]);
+        let mut new_tail = [0u8; N];
+        for (dst, src) in new_tail
+            .iter_mut()
+            .zip(self.tail[from_tail..self.len].iter().chain(&buf[from_buf..]))
+        {
+            *dst = *src;
+        }
+        self.tail = new_tail;
+        self.len = N;
+    }
+
+    /// The last `N` bytes of the stream, or None if the stream is shorter than that
+    pub(crate) fn get(&self) -> Option<&[u8; N]> {
+        (self.len == N).then_some(&self.tail)
+    }
+}
+
+/// Reader which checksums everything read through it.
+///
+/// Since the reader does not know where the checksummed data ends, the last 8 bytes
+/// read are held back from the checksum. When the end of the data is reached, they
+/// are the stored checksum. This works even if the data was read beyond its end,
+/// for example because a corrupt size made the deserializer read the checksum as data.
+pub(crate) struct ChecksumReader<'a, R: Read> {
+    reader: &'a mut R,
+    checksum: Checksum,
+    tail: HeldBackTail<8>,
+    /// True if the checksum is known to be at the end of `reader`
+    whole_input: bool,
+}
+
+impl<'a, R: Read> ChecksumReader<'a, R> {
+    /// Create a reader checksumming `reader`. `whole_input` must only be true if the
+    /// checksum is at the end of `reader`, and reading to the end will not block indefinitely.
+    pub(crate) fn new(
+        reader: &'a mut R,
+        algorithm: ChecksumAlgorithm,
+        whole_input: bool,
+    ) -> Result<Self, SavefileError> {
+        Ok(ChecksumReader {
+            reader,
+            checksum: Checksum::new(algorithm)?,
+            tail: HeldBackTail::new(),
+            whole_input,
+        })
+    }
+
+    /// Compare the stored checksum with the checksum of the data read so far,
+    /// assuming the end of the checksummed data has been reached
+    fn check(&self) -> Result<(), SavefileError> {
+        let Some(tail) = self.tail.get() else {
+            return Err(SavefileError::ShortRead);
+        };
+        let expected = u64::from_le_bytes(*tail);
+        let actual = self.checksum.finish();
+        if expected != actual {
+            return Err(SavefileError::ChecksumMismatch { expected, actual });
+        }
+        Ok(())
+    }
+
+    /// Read the stored checksum, which must immediately follow the checksummed data,
+    /// and compare it with the checksum of the data read so far.
+    pub(crate) fn verify(mut self) -> Result<(), SavefileError> {
+        let mut stored = [0u8; 8];
+        match self.read_exact(&mut stored) {
+            Ok(()) => self.check(),
+            Err(err) => Err(self.verify_after_error(err.into())),
+        }
+    }
+
+    /// Called when reading the data failed. If this was because the data is corrupt,
+    /// the checksum will not match. If the checksum is known to be at the end of the
+    /// input, reads the rest of the input to find out.
+    /// Returns a [SavefileError::ChecksumMismatch] if the checksum does not match,
+    /// and `error` otherwise.
+    pub(crate) fn verify_after_error(mut self, error: SavefileError) -> SavefileError {
+        if !self.whole_input {
+            return error;
+        }
+        if std::io::copy(&mut self, &mut std::io::sink()).is_err() || self.tail.get().is_none() {
+            return error;
+        }
+        match self.check() {
+            Ok(()) => error,
+            Err(mismatch) => mismatch,
+        }
+    }
+}
+
+impl<R: Read> Read for ChecksumReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let got = self.reader.read(buf)?;
+        let checksum = &mut self.checksum;
+        self.tail.push(&buf[..got], |x| checksum.update(x));
+        Ok(got)
+    }
+}
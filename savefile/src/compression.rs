@@ -0,0 +1,247 @@
+#[allow(unused_imports)] // Unused if no codec feature is enabled
+use crate::{
+    read_file_header, read_schema_and_data, write_schema_and_data, ChecksumAlgorithm, PayloadHeader, PayloadLoader,
+    SavefileError, Schema, Serialize,
+};
+use byteorder::{LittleEndian, WriteBytesExt};
+use std::fmt::{Display, Formatter};
+use std::io::{Read, Write};
+
+/// A compression format supported by savefile.
+///
+/// Each codec is only available if the corresponding cargo feature is enabled
+/// ('bzip2', 'zstd', 'lz4' or 'deflate'). Files record which codec they were
+/// compressed with, so [crate::load] automatically uses the right one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum CompressionCodec {
+    /// bzip2. Very slow, but gives good compression. Requires the 'bzip2'-feature.
+    Bzip2,
+    /// Zstandard. Fast, with good compression. Requires the 'zstd'-feature.
+    Zstd,
+    /// LZ4 (frame format). Very fast, but gives less compression. Requires the 'lz4'-feature.
+    Lz4,
+    /// Raw deflate. Requires the 'deflate'-feature.
+    Deflate,
+}
+
+impl CompressionCodec {
+    /// The value written to the compression byte of the savefile header.
+    /// The value 0 means the file is not compressed. The value 1 was used for
+    /// bzip2 before savefile supported several codecs.
+    pub(crate) fn header_byte(self) -> u8 {
+        match self {
+            CompressionCodec::Bzip2 => 1,
+            CompressionCodec::Zstd => 2,
+            CompressionCodec::Lz4 => 3,
+            CompressionCodec::Deflate => 4,
+        }
+    }
+
+    pub(crate) fn from_header_byte(byte: u8) -> Result<CompressionCodec, SavefileError> {
+        match byte {
+            1 => Ok(CompressionCodec::Bzip2),
+            2 => Ok(CompressionCodec::Zstd),
+            3 => Ok(CompressionCodec::Lz4),
+            4 => Ok(CompressionCodec::Deflate),
+            _ => Err(SavefileError::GeneralError {
+                msg: format!("File is compressed using an unknown compression codec ({}).", byte),
+            }),
+        }
+    }
+}
+
+impl Display for CompressionCodec {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CompressionCodec::Bzip2 => write!(f, "bzip2"),
+            CompressionCodec::Zstd => write!(f, "zstd"),
+            CompressionCodec::Lz4 => write!(f, "lz4"),
+            CompressionCodec::Deflate => write!(f, "deflate"),
+        }
+    }
+}
+
+/// Selects compression codec and level, for use with [crate::save_compressed_with].
+///
+/// The default is bzip2 at its best compression level, which is what
+/// [crate::save_compressed] uses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CompressionOptions {
+    /// The codec to compress with
+    pub codec: CompressionCodec,
+    /// The compression level. If None, a codec specific default is used.
+    ///
+    /// The valid range depends on the codec: 1-9 for bzip2, 1-22 for zstd and 0-9 for deflate.
+    /// Out of range values are clamped. The lz4 codec does not support levels, and ignores this.
+    pub level: Option<u32>,
+}
+
+impl Default for CompressionOptions {
+    fn default() -> Self {
+        CompressionOptions::new(CompressionCodec::Bzip2)
+    }
+}
+
+impl CompressionOptions {
+    /// Use the given codec, at its default compression level
+    pub fn new(codec: CompressionCodec) -> CompressionOptions {
+        CompressionOptions { codec, level: None }
+    }
+    /// Use the given compression level. See [CompressionOptions::level].
+    pub fn with_level(self, level: u32) -> CompressionOptions {
+        CompressionOptions {
+            level: Some(level),
+            ..self
+        }
+    }
+}
+
+/// Write the compressed schema and data. The compression byte must already have been written.
+pub(crate) fn write_compressed<W: Write, T: Serialize>(
+    writer: &mut W,
+    options: CompressionOptions,
+    version: u32,
+    data: &T,
+    with_schema: Option<Schema>,
+    checksum: Option<ChecksumAlgorithm>,
+) -> Result<(), SavefileError> {
+    with_encoder(writer, options, |mut encoder| {
+        write_schema_and_data(&mut encoder, version, data, with_schema, checksum)
+    })
+}
+
+/// Read the compressed schema and data. The compression byte must already have been read.
+pub(crate) fn read_compressed<R: Read, L: PayloadLoader>(
+    reader: &mut R,
+    codec: CompressionCodec,
+    savefile_lib_version: u16,
+    file_ver: u32,
+    loader: L,
+    checksum: Option<ChecksumAlgorithm>,
+    whole_input: bool,
+) -> Result<L::Output, SavefileError> {
+    with_decoder(reader, codec, |mut decoder| {
+        read_schema_and_data(
+            &mut decoder,
+            savefile_lib_version,
+            file_ver,
+            loader,
+            checksum,
+            whole_input,
+        )
+    })
+}
+
+/// Call `write` with a writer compressing into `writer`, then finish the compressed stream.
+#[allow(unused_variables)]
+fn with_encoder<W: Write>(
+    writer: &mut W,
+    options: CompressionOptions,
+    write: impl FnOnce(&mut dyn Write) -> Result<(), SavefileError>,
+) -> Result<(), SavefileError> {
+    match options.codec {
+        #[cfg(feature = "bzip2")]
+        CompressionCodec::Bzip2 => {
+            let level = options.level.unwrap_or(9).clamp(1, 9);
+            let mut encoder = bzip2::write::BzEncoder::new(writer, bzip2::Compression::new(level));
+            write(&mut encoder)?;
+            encoder.finish()?;
+            Ok(())
+        }
+        #[cfg(feature = "zstd")]
+        CompressionCodec::Zstd => {
+            let level = options.level.unwrap_or(3).clamp(1, 22);
+            let mut encoder = zstd::Encoder::new(writer, level as i32)?;
+            write(&mut encoder)?;
+            encoder.finish()?;
+            Ok(())
+        }
+        #[cfg(feature = "lz4")]
+        CompressionCodec::Lz4 => {
+            let mut encoder = lz4_flex::frame::FrameEncoder::new(writer);
+            write(&mut encoder)?;
+            encoder.finish().map_err(std::io::Error::from)?;
+            Ok(())
+        }
+        #[cfg(feature = "deflate")]
+        CompressionCodec::Deflate => {
+            let level = options.level.unwrap_or(6).min(9);
+            let mut encoder = flate2::write::DeflateEncoder::new(writer, flate2::Compression::new(level));
+            write(&mut encoder)?;
+            encoder.finish()?;
+            Ok(())
+        }
+        #[allow(unreachable_patterns)]
+        codec => Err(SavefileError::CompressionSupportNotCompiledIn { codec }),
+    }
+}
+
+/// Call `read` with a reader decompressing `reader` using `codec`.
+#[allow(unused_variables)]
+fn with_decoder<R: Read, O>(
+    reader: &mut R,
+    codec: CompressionCodec,
+    read: impl FnOnce(&mut dyn Read) -> Result<O, SavefileError>,
+) -> Result<O, SavefileError> {
+    match codec {
+        #[cfg(feature = "bzip2")]
+        CompressionCodec::Bzip2 => read(&mut bzip2::read::BzDecoder::new(reader)),
+        #[cfg(feature = "zstd")]
+        CompressionCodec::Zstd => read(&mut zstd::Decoder::new(reader)?),
+        #[cfg(feature = "lz4")]
+        CompressionCodec::Lz4 => read(&mut lz4_flex::frame::FrameDecoder::new(reader)),
+        #[cfg(feature = "deflate")]
+        CompressionCodec::Deflate => read(&mut flate2::read::DeflateDecoder::new(reader)),
+        #[allow(unreachable_patterns)]
+        codec => Err(SavefileError::CompressionSupportNotCompiledIn { codec }),
+    }
+}
+
+/// Copy a complete savefile from `reader` to `writer`, changing its compression.
+/// If `compression` is None, the copy is not compressed.
+///
+/// The data is not deserialized, so this works without knowing the type of the data,
+/// and the file version, any checksum and any metadata are kept as they are.
+pub fn recompress(
+    reader: &mut impl Read,
+    writer: &mut impl Write,
+    compression: Option<CompressionOptions>,
+) -> Result<(), SavefileError> {
+    let (savefile_lib_version, file_ver) = read_file_header(reader, u32::MAX)?;
+    let header = PayloadHeader::read(reader, true)?;
+
+    let source_codec = header.compression;
+    let header = PayloadHeader {
+        compression: compression.map(|options| options.codec),
+        ..header
+    };
+    // The header is copied as is, since the data was written by that savefile version,
+    // unless the new compression codec requires a later version
+    let required_lib_version = PayloadHeader::required_savefile_lib_version(
+        header.compression,
+        header.checksum.is_some(),
+        header.metadata.is_some(),
+    );
+    writer.write_all(b"savefile\0")?;
+    writer.write_u16::<LittleEndian>(savefile_lib_version.max(required_lib_version))?;
+    writer.write_u32::<LittleEndian>(file_ver)?;
+    header.write(writer)?;
+
+    let mut copy_payload = |payload: &mut dyn Read| match compression {
+        Some(options) => with_encoder(writer, options, |encoder| {
+            std::io::copy(payload, encoder)?;
+            Ok(())
+        }),
+        None => {
+            std::io::copy(payload, writer)?;
+            Ok(())
+        }
+    };
+    match source_codec {
+        Some(codec) => with_decoder(reader, codec, copy_payload),
+        None => copy_payload(reader),
+    }?;
+    writer.flush()?;
+    Ok(())
+}
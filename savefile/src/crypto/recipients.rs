@@ -0,0 +1,178 @@
+use super::{load_with_header, save_with_header, KeySource, WRAPPED_KEY_LEN};
+use crate::{Deserialize, SavefileError, Serialize, WithSchema};
+use rand::rngs::OsRng;
+use rand::RngCore;
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM};
+use ring::hkdf;
+use std::io::{Read, Write};
+use x25519_dalek::{PublicKey, StaticSecret};
+
+/// The X25519 public key of a recipient of encrypted data. See [crate::save_encrypted_for_recipients].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RecipientPublicKey(PublicKey);
+
+impl RecipientPublicKey {
+    /// The public key with the given bytes
+    pub fn from_bytes(bytes: [u8; 32]) -> RecipientPublicKey {
+        RecipientPublicKey(PublicKey::from(bytes))
+    }
+    /// The bytes of the public key
+    pub fn to_bytes(&self) -> [u8; 32] {
+        self.0.to_bytes()
+    }
+}
+
+/// The X25519 secret key of a recipient of encrypted data. See [crate::load_encrypted_as_recipient].
+///
+/// The key is zeroed when dropped.
+#[derive(Clone)]
+pub struct RecipientSecretKey(StaticSecret);
+
+impl RecipientSecretKey {
+    /// Generate a new, random, secret key
+    pub fn generate() -> RecipientSecretKey {
+        RecipientSecretKey(StaticSecret::random_from_rng(OsRng))
+    }
+    /// The secret key with the given bytes
+    pub fn from_bytes(bytes: [u8; 32]) -> RecipientSecretKey {
+        RecipientSecretKey(StaticSecret::from(bytes))
+    }
+    /// The bytes of the secret key. Keep these secret.
+    pub fn to_bytes(&self) -> [u8; 32] {
+        self.0.to_bytes()
+    }
+    /// The public key corresponding to this secret key
+    pub fn public_key(&self) -> RecipientPublicKey {
+        RecipientPublicKey(PublicKey::from(&self.0))
+    }
+}
+
+fn crypto_error<E>(_: E) -> SavefileError {
+    SavefileError::CryptographyError
+}
+
+/// The key used to wrap the content key for one recipient. Derived from the secret
+/// shared between the ephemeral key and the recipient key, and both their public keys.
+fn wrapping_key(
+    secret: &StaticSecret,
+    other_public: &PublicKey,
+    ephemeral_public: &[u8; 32],
+    recipient_public: &[u8; 32],
+) -> Result<LessSafeKey, SavefileError> {
+    let shared = secret.diffie_hellman(other_public);
+    if !shared.was_contributory() {
+        return Err(SavefileError::GeneralError {
+            msg: "Invalid X25519 public key.".into(),
+        });
+    }
+    let prk = hkdf::Salt::new(hkdf::HKDF_SHA256, b"savefile x25519 recipient").extract(shared.as_bytes());
+    let info = [&ephemeral_public[..], &recipient_public[..]];
+    let mut key = [0u8; 32];
+    prk.expand(&info, hkdf::HKDF_SHA256)
+        .map_err(crypto_error)?
+        .fill(&mut key)
+        .map_err(crypto_error)?;
+    Ok(LessSafeKey::new(
+        UnboundKey::new(&AES_256_GCM, &key).map_err(crypto_error)?,
+    ))
+}
+
+/// Each wrapping key is only ever used once, so a fixed nonce is safe
+fn wrapping_nonce() -> Nonce {
+    Nonce::assume_unique_for_key([0u8; 12])
+}
+
+/// Write the given `data` to `writer`, encrypted so that only the holders of the secret keys
+/// of the given `recipients` can decrypt it, using [crate::load_encrypted_as_recipient].
+/// The current version of data must be `version`.
+///
+/// The data is encrypted with AES256 using a random key, just like [crate::save_encrypted].
+/// That key is then wrapped (encrypted) for each recipient, using a key agreed between an
+/// ephemeral X25519 key and the public key of the recipient. No secret is needed for saving.
+///
+/// `aad` is additional authenticated data, see [crate::save_encrypted].
+///
+/// The data is not compressed.
+pub fn save_encrypted_for_recipients<T: WithSchema + Serialize>(
+    writer: &mut impl Write,
+    version: u32,
+    data: &T,
+    recipients: &[RecipientPublicKey],
+    aad: &[u8],
+) -> Result<(), SavefileError> {
+    if recipients.is_empty() {
+        return Err(SavefileError::GeneralError {
+            msg: "Data must be encrypted for at least one recipient.".into(),
+        });
+    }
+    let mut content_key = [0u8; 32];
+    OsRng.fill_bytes(&mut content_key);
+
+    let ephemeral = StaticSecret::random_from_rng(OsRng);
+    let ephemeral_public = PublicKey::from(&ephemeral).to_bytes();
+    let mut wrapped_keys = Vec::with_capacity(recipients.len());
+    for recipient in recipients {
+        let recipient_public = recipient.to_bytes();
+        let wrapping_key = wrapping_key(&ephemeral, &recipient.0, &ephemeral_public, &recipient_public)?;
+        let mut wrapped = content_key.to_vec();
+        wrapping_key
+            .seal_in_place_append_tag(wrapping_nonce(), Aad::empty(), &mut wrapped)
+            .map_err(crypto_error)?;
+        let mut wrapped_key = [0u8; WRAPPED_KEY_LEN];
+        wrapped_key.copy_from_slice(&wrapped);
+        wrapped_keys.push(wrapped_key);
+    }
+
+    save_with_header(
+        writer,
+        KeySource::Recipients {
+            ephemeral_public,
+            wrapped_keys,
+        },
+        content_key,
+        aad,
+        version,
+        data,
+    )
+}
+
+/// Load an object of type T, previously saved using [crate::save_encrypted_for_recipients],
+/// from `reader`. `secret_key` must be the secret key of one of the recipients.
+/// The current version of T in memory must be `version`.
+///
+/// `aad` must be the additional authenticated data given when saving.
+///
+/// Returns [SavefileError::WrongPassword] if the data was not encrypted for `secret_key`.
+pub fn load_encrypted_as_recipient<T: WithSchema + Deserialize>(
+    reader: &mut impl Read,
+    version: u32,
+    secret_key: &RecipientSecretKey,
+    aad: &[u8],
+) -> Result<T, SavefileError> {
+    load_with_header(reader, version, aad, |key_source| {
+        let KeySource::Recipients {
+            ephemeral_public,
+            wrapped_keys,
+        } = key_source
+        else {
+            return Err(key_source.mismatch_error());
+        };
+        let recipient_public = secret_key.public_key().to_bytes();
+        let wrapping_key = wrapping_key(
+            &secret_key.0,
+            &PublicKey::from(*ephemeral_public),
+            ephemeral_public,
+            &recipient_public,
+        )?;
+        // Which of the wrapped keys belongs to this recipient is not recorded, try them all
+        for wrapped_key in wrapped_keys {
+            let mut wrapped = *wrapped_key;
+            if let Ok(content_key) = wrapping_key.open_in_place(wrapping_nonce(), Aad::empty(), &mut wrapped) {
+                let mut key = [0u8; 32];
+                key.copy_from_slice(content_key);
+                return Ok(key);
+            }
+        }
+        Err(SavefileError::WrongPassword)
+    })
+}
@@ -0,0 +1,119 @@
+use crate::schema_history::schema_name;
+use crate::{load_file, save_file, Deserialize, Introspect, SavefileError, Serialize, WithSchema};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+fn golden_file_name(path: &Path, name: &str, version: u32) -> PathBuf {
+    path.join(format!("savefile_{}_{}.golden", name, version))
+}
+
+fn dump_file_name(path: &Path, name: &str, version: u32, loaded_by: u32) -> PathBuf {
+    path.join(format!("savefile_{}_{}.loaded_by_{}.txt", name, version, loaded_by))
+}
+
+/// Write one line `path = value` for `value` and each of its descendants
+fn write_dump(dump: &mut String, path: &str, value: &dyn Introspect) {
+    dump.push_str(path);
+    dump.push_str(" = ");
+    dump.push_str(&value.introspect_value().replace('\n', "\\n"));
+    dump.push('\n');
+    let mut index = 0;
+    while let Some(child) = value.introspect_child(index) {
+        write_dump(dump, &format!("{}.{}", path, child.key()), child.val());
+        index += 1;
+    }
+}
+
+/// Verify that sample files saved by earlier versions of `T` still load the same way
+/// using the current code, which has version `version`.
+///
+/// The directory `path` contains a sample file `savefile_{name}_{version}.golden` for each
+/// released version. If there is no sample file for `version`, `sample` is saved as one, using
+/// [crate::save_file]. Sample files for older versions, saved before this was used, can be added by hand.
+///
+/// Every sample file is then loaded with the current code, and compared to introspection dumps of
+/// what it loaded as with earlier versions of the code. These are stored as
+/// `savefile_{name}_{file version}.loaded_by_{code version}.txt`. Every line of the dumps made by
+/// earlier versions must still be present in the dump of the loaded value, so adding fields in new
+/// versions is fine. The dump made by the current version must be identical. Otherwise
+/// [SavefileError::GeneralError] is returned. If there is no dump for the current version, it is
+/// written, which records the default values and conversions of the new version. Review it before
+/// checking it in. If a change of the loaded value of an old file is intended, delete its dumps.
+///
+/// This detects mistakes in `savefile_default_fn`, `savefile_versions_as` conversions and
+/// similar. Call it from a test, and check the files in to source control. Note that the
+/// dump of a `HashMap` depends on its iteration order. Use [load_golden_files] to compare
+/// against expected values instead.
+pub fn verify_golden_files<T: WithSchema + Serialize + Deserialize + Introspect + 'static>(
+    path: impl AsRef<Path>,
+    version: u32,
+    sample: &T,
+) -> Result<(), SavefileError> {
+    let path = path.as_ref();
+    std::fs::create_dir_all(path)?;
+    let name = schema_name::<T>();
+    let current = golden_file_name(path, &name, version);
+    if !current.exists() {
+        save_file(&current, version, sample)?;
+    }
+    for (file_version, loaded) in load_golden_files::<T>(path, version)? {
+        let mut dump = String::new();
+        write_dump(&mut dump, "$", &loaded);
+        let lines: HashSet<&str> = dump.lines().collect();
+        for loaded_by in file_version..version {
+            let dump_file = dump_file_name(path, &name, file_version, loaded_by);
+            if !dump_file.exists() {
+                continue;
+            }
+            let recorded = std::fs::read_to_string(&dump_file)?.replace("\r\n", "\n");
+            if let Some(missing) = recorded.lines().find(|line| !lines.contains(line)) {
+                return Err(SavefileError::GeneralError {
+                    msg: format!(
+                        "{} no longer loads as recorded in {}. Line '{}' is missing from the current result:\n{}",
+                        golden_file_name(path, &name, file_version).display(),
+                        dump_file.display(),
+                        missing,
+                        dump
+                    ),
+                });
+            }
+        }
+        let dump_file = dump_file_name(path, &name, file_version, version);
+        if dump_file.exists() {
+            let recorded = std::fs::read_to_string(&dump_file)?.replace("\r\n", "\n");
+            if recorded != dump {
+                return Err(SavefileError::GeneralError {
+                    msg: format!(
+                        "{} no longer loads as recorded in {}. The current result is:\n{}",
+                        golden_file_name(path, &name, file_version).display(),
+                        dump_file.display(),
+                        dump
+                    ),
+                });
+            }
+        } else {
+            std::fs::write(&dump_file, dump)?;
+        }
+    }
+    Ok(())
+}
+
+/// Load all sample files written by [verify_golden_files] for versions up to and including `version`,
+/// using the current code. Returns the version of each file, and the loaded value, in order of version.
+///
+/// This allows comparing the loaded values against expected values in a test.
+pub fn load_golden_files<T: WithSchema + Deserialize + 'static>(
+    path: impl AsRef<Path>,
+    version: u32,
+) -> Result<Vec<(u32, T)>, SavefileError> {
+    let path = path.as_ref();
+    let name = schema_name::<T>();
+    let mut result = Vec::new();
+    for file_version in 0..=version {
+        let file = golden_file_name(path, &name, file_version);
+        if file.exists() {
+            result.push((file_version, load_file(&file, version)?));
+        }
+    }
+    Ok(result)
+}
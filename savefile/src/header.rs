@@ -0,0 +1,252 @@
+use crate::{
+    read_compressed, read_file_header, read_schema_and_data, ChecksumAlgorithm, CompressionCodec, Deserialize,
+    Deserializer, PayloadLoader, SavefileError, Schema, Serialize, Serializer, CHECKSUM_FLAG,
+    CURRENT_SAVEFILE_LIB_VERSION, EXTENDED_HEADER_SAVEFILE_LIB_VERSION,
+};
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufReader, Read, Write};
+use std::path::Path;
+use std::time::SystemTime;
+
+/// Flag set in the compression byte of the savefile header, if the file has [FileMetadata].
+/// The metadata follows the compression byte (and checksum type), preceded by its length.
+pub(crate) const METADATA_FLAG: u8 = 0x40;
+
+/// Information about a savefile, which can be read using [read_header] without reading
+/// the data. Saved using [crate::save_with_metadata].
+///
+/// The metadata is stored uncompressed, and is not covered by the checksum of the file.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct FileMetadata {
+    /// Name of the application which saved the file
+    pub app_name: String,
+    /// When the file was saved
+    pub created: Option<SystemTime>,
+    /// Description of the contents, for display to users
+    pub description: String,
+    /// A small image of the contents, in any format the application likes
+    pub thumbnail: Vec<u8>,
+}
+
+impl FileMetadata {
+    /// Metadata for a file saved now by the application `app_name`
+    pub fn new(app_name: impl Into<String>) -> FileMetadata {
+        FileMetadata {
+            app_name: app_name.into(),
+            created: Some(SystemTime::now()),
+            ..FileMetadata::default()
+        }
+    }
+
+    fn to_bytes(&self) -> Result<Vec<u8>, SavefileError> {
+        let mut data = Vec::new();
+        let mut serializer = Serializer {
+            writer: &mut data,
+            file_version: CURRENT_SAVEFILE_LIB_VERSION as u32,
+        };
+        serializer.write_string(&self.app_name)?;
+        self.created.serialize(&mut serializer)?;
+        serializer.write_string(&self.description)?;
+        self.thumbnail.serialize(&mut serializer)?;
+        Ok(data)
+    }
+
+    /// Parse metadata written by [FileMetadata::to_bytes]. Later savefile versions
+    /// may add fields at the end, which are ignored.
+    fn from_bytes(data: &[u8], savefile_lib_version: u16) -> Result<FileMetadata, SavefileError> {
+        let mut deserializer = Deserializer {
+            reader: &mut &data[..],
+            file_version: savefile_lib_version as u32,
+            ephemeral_state: HashMap::new(),
+            limit_state: None,
+        };
+        Ok(FileMetadata {
+            app_name: deserializer.read_string()?,
+            created: Option::<SystemTime>::deserialize(&mut deserializer)?,
+            description: deserializer.read_string()?,
+            thumbnail: Vec::<u8>::deserialize(&mut deserializer)?,
+        })
+    }
+}
+
+/// The part of the header following the file version: the compression byte, and what
+/// it says follows it.
+pub(crate) struct PayloadHeader {
+    pub(crate) compression: Option<CompressionCodec>,
+    pub(crate) checksum: Option<ChecksumAlgorithm>,
+    /// The serialized [FileMetadata], if any and if requested when reading
+    pub(crate) metadata: Option<Vec<u8>>,
+}
+
+impl PayloadHeader {
+    pub(crate) fn new(
+        compression: Option<CompressionCodec>,
+        checksum: Option<ChecksumAlgorithm>,
+        metadata: Option<&FileMetadata>,
+    ) -> Result<PayloadHeader, SavefileError> {
+        Ok(PayloadHeader {
+            compression,
+            checksum,
+            metadata: metadata.map(|x| x.to_bytes()).transpose()?,
+        })
+    }
+
+    /// The savefile library version to write to the file header, for a file with the given
+    /// compression, checksum and metadata. See [EXTENDED_HEADER_SAVEFILE_LIB_VERSION].
+    pub(crate) fn required_savefile_lib_version(
+        compression: Option<CompressionCodec>,
+        checksum: bool,
+        metadata: bool,
+    ) -> u16 {
+        let extended = !matches!(compression, None | Some(CompressionCodec::Bzip2)) || checksum || metadata;
+        if extended {
+            EXTENDED_HEADER_SAVEFILE_LIB_VERSION
+        } else {
+            CURRENT_SAVEFILE_LIB_VERSION
+        }
+    }
+
+    /// Read the header. The metadata is skipped unless `read_metadata` is true.
+    pub(crate) fn read(reader: &mut impl Read, read_metadata: bool) -> Result<PayloadHeader, SavefileError> {
+        let compression_byte = reader.read_u8()?;
+        let compression = match compression_byte & !(CHECKSUM_FLAG | METADATA_FLAG) {
+            0 => None,
+            byte => Some(CompressionCodec::from_header_byte(byte)?),
+        };
+        let checksum = if compression_byte & CHECKSUM_FLAG != 0 {
+            Some(ChecksumAlgorithm::from_header_byte(reader.read_u8()?)?)
+        } else {
+            None
+        };
+        let metadata = if compression_byte & METADATA_FLAG != 0 {
+            let len = reader.read_u64::<LittleEndian>()?;
+            let mut data = Vec::new();
+            let mut metadata = reader.take(len);
+            let count = if read_metadata {
+                metadata.read_to_end(&mut data)? as u64
+            } else {
+                std::io::copy(&mut metadata, &mut std::io::sink())?
+            };
+            if count != len {
+                return Err(SavefileError::ShortRead);
+            }
+            read_metadata.then_some(data)
+        } else {
+            None
+        };
+        Ok(PayloadHeader {
+            compression,
+            checksum,
+            metadata,
+        })
+    }
+
+    pub(crate) fn write(&self, writer: &mut impl Write) -> Result<(), SavefileError> {
+        let mut compression_byte = self.compression.map(|x| x.header_byte()).unwrap_or(0);
+        if self.checksum.is_some() {
+            compression_byte |= CHECKSUM_FLAG;
+        }
+        if self.metadata.is_some() {
+            compression_byte |= METADATA_FLAG;
+        }
+        writer.write_u8(compression_byte)?;
+        if let Some(checksum) = self.checksum {
+            writer.write_u8(checksum.header_byte())?;
+        }
+        if let Some(metadata) = &self.metadata {
+            writer.write_u64::<LittleEndian>(metadata.len() as u64)?;
+            writer.write_all(metadata)?;
+        }
+        Ok(())
+    }
+}
+
+/// The header of a savefile, see [read_header]
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileHeader {
+    /// Version of the savefile file format
+    pub savefile_lib_version: u16,
+    /// Version of the data, as given when the file was saved
+    pub version: u32,
+    /// The compression codec, if the data is compressed
+    pub compression: Option<CompressionCodec>,
+    /// The checksum algorithm, if the file has a checksum
+    pub checksum: Option<ChecksumAlgorithm>,
+    /// The metadata, if the file has any
+    pub metadata: Option<FileMetadata>,
+}
+
+impl FileHeader {
+    /// True if the data is compressed
+    pub fn is_compressed(&self) -> bool {
+        self.compression.is_some()
+    }
+}
+
+/// Read the header of a savefile, including its [FileMetadata], if any.
+///
+/// Nothing following the header is read, so this is fast even for large files. The header
+/// can be read regardless of the version of the data. See [inspect] to read the schema as well.
+pub fn read_header(reader: &mut impl Read) -> Result<FileHeader, SavefileError> {
+    let (savefile_lib_version, version) = read_file_header(reader, u32::MAX)?;
+    let payload_header = PayloadHeader::read(reader, true)?;
+    let metadata = payload_header
+        .metadata
+        .map(|data| FileMetadata::from_bytes(&data, savefile_lib_version))
+        .transpose()?;
+    Ok(FileHeader {
+        savefile_lib_version,
+        version,
+        compression: payload_header.compression,
+        checksum: payload_header.checksum,
+        metadata,
+    })
+}
+
+/// The header and schema of a savefile, see [inspect]
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileInfo {
+    /// The header of the file
+    pub header: FileHeader,
+    /// The schema of the data in the file
+    pub schema: Schema,
+}
+
+struct SchemaLoader;
+
+impl PayloadLoader for SchemaLoader {
+    type Output = Schema;
+    fn reads_schema(&self) -> bool {
+        true
+    }
+    fn load_data(
+        self,
+        _deserializer: &mut Deserializer<impl Read>,
+        file_schema: Option<Schema>,
+    ) -> Result<Schema, SavefileError> {
+        Ok(file_schema.expect("SchemaLoader reads the schema"))
+    }
+}
+
+/// Read the header and the schema of a savefile, without reading the data.
+///
+/// The file must have been saved with a schema, which is the case unless one of the
+/// `*_noschema` functions was used. The checksum of the file is not verified, since
+/// that requires reading all of it.
+pub fn inspect(reader: &mut impl Read) -> Result<FileInfo, SavefileError> {
+    let header = read_header(reader)?;
+    let (lib_version, version) = (header.savefile_lib_version, header.version);
+    let schema = match header.compression {
+        Some(codec) => read_compressed(reader, codec, lib_version, version, SchemaLoader, None, false)?,
+        None => read_schema_and_data(reader, lib_version, version, SchemaLoader, None, false)?,
+    };
+    Ok(FileInfo { header, schema })
+}
+
+/// Like [inspect], but reads the given file.
+pub fn inspect_file<P: AsRef<Path>>(path: P) -> Result<FileInfo, SavefileError> {
+    let mut f = BufReader::new(File::open(path)?);
+    inspect(&mut f)
+}
@@ -0,0 +1,350 @@
+use crate::value::{is_map_entry, write_dynamic, SchemaPath, Step, CANARY1};
+use crate::{load_dynamic, Field, SavefileError, Schema, SchemaPrimitive, Value};
+use serde_json::{Map, Value as Json};
+use std::io::{Read, Write};
+
+/// JSON for the float `x`, with the shortest representation `text`.
+/// Non-finite floats cannot be JSON numbers, and are written as strings.
+fn float_to_json(x: f64, text: String) -> Json {
+    if x.is_finite() {
+        // Parse the shortest representation, so that f32 values are not written with f64 precision
+        text.parse::<f64>().map(Json::from).unwrap_or(Json::String(text))
+    } else {
+        Json::String(text)
+    }
+}
+
+impl Value {
+    /// Convert this value to JSON.
+    ///
+    /// * Structs become objects, with the fields in declaration order.
+    /// * Enum variants without fields become strings with the variant name. Other variants
+    ///   become an object with the variant name as only key, and an object with the fields as value.
+    /// * Vectors and arrays become arrays. Maps become arrays of objects with a `key` and a `value`.
+    /// * `None` and zero sized values become `null`. `Some(x)` becomes `x`, except if `x` itself
+    ///   is an option or zero sized, then it becomes `[x]`.
+    /// * `u128` and `i128` become strings, as do non-finite floats (`"NaN"`, `"inf"` and `"-inf"`).
+    ///
+    /// Use [Value::from_json] to convert back.
+    pub fn to_json(&self) -> Json {
+        match self {
+            Value::Unit => Json::Null,
+            Value::Bool(x) => Json::Bool(*x),
+            Value::U8(x) => Json::from(*x),
+            Value::I8(x) => Json::from(*x),
+            Value::U16(x) => Json::from(*x),
+            Value::I16(x) => Json::from(*x),
+            Value::U32(x) => Json::from(*x),
+            Value::I32(x) => Json::from(*x),
+            Value::U64(x) => Json::from(*x),
+            Value::I64(x) => Json::from(*x),
+            Value::U128(x) => Json::String(x.to_string()),
+            Value::I128(x) => Json::String(x.to_string()),
+            Value::F32(x) => float_to_json(*x as f64, x.to_string()),
+            Value::F64(x) => float_to_json(*x, x.to_string()),
+            Value::Char(x) => Json::String(x.to_string()),
+            Value::String(x) => Json::String(x.clone()),
+            Value::Option(None) => Json::Null,
+            Value::Option(Some(x)) => match **x {
+                Value::Option(_) | Value::Unit => Json::Array(vec![x.to_json()]),
+                _ => x.to_json(),
+            },
+            Value::Vector(items) | Value::Array(items) => Json::Array(items.iter().map(|x| x.to_json()).collect()),
+            Value::Struct { fields, .. } => fields_to_json(fields),
+            Value::Enum { variant, fields, .. } => {
+                if fields.is_empty() {
+                    Json::String(variant.clone())
+                } else {
+                    let mut object = Map::new();
+                    object.insert(variant.clone(), fields_to_json(fields));
+                    Json::Object(object)
+                }
+            }
+        }
+    }
+
+    /// Convert JSON, in the format produced by [Value::to_json], to a value with the given schema.
+    ///
+    /// Fails with [SavefileError::JsonMismatch] if the JSON does not fit the schema.
+    /// The error contains the path of the offending part of the JSON, such as `$.shapes[2].width`.
+    pub fn from_json(json: &Json, schema: &Schema) -> Result<Value, SavefileError> {
+        let mut reader = JsonReader {
+            path: SchemaPath::new(schema),
+            location: String::new(),
+        };
+        reader.read_value(json, schema)
+    }
+}
+
+fn fields_to_json(fields: &[(String, Value)]) -> Json {
+    Json::Object(
+        fields
+            .iter()
+            .map(|(name, value)| (name.clone(), value.to_json()))
+            .collect(),
+    )
+}
+
+/// Load the data from `reader`, without knowing its Rust type, and convert it to JSON.
+/// See [crate::load_dynamic] and [Value::to_json].
+pub fn load_as_json(reader: &mut impl Read) -> Result<Json, SavefileError> {
+    Ok(load_dynamic(reader)?.value.to_json())
+}
+
+/// Convert `json` to a value with the given schema, and write it to `writer`, preceded by
+/// the schema. The data is written as version `version`.
+/// See [Value::from_json] and [crate::save_dynamic].
+///
+/// Nothing is written if the JSON does not fit the schema.
+pub fn save_from_json(
+    writer: &mut impl Write,
+    version: u32,
+    schema: &Schema,
+    json: &Json,
+) -> Result<(), SavefileError> {
+    let value = Value::from_json(json, schema)?;
+    write_dynamic(writer, version, schema, &value)
+}
+
+fn json_kind(json: &Json) -> &'static str {
+    match json {
+        Json::Null => "null",
+        Json::Bool(_) => "a boolean",
+        Json::Number(_) => "a number",
+        Json::String(_) => "a string",
+        Json::Array(_) => "an array",
+        Json::Object(_) => "an object",
+    }
+}
+
+/// Converts JSON to values, keeping track of where in the JSON it is, for error messages.
+struct JsonReader<'s> {
+    path: SchemaPath<'s>,
+    /// Location in the JSON, in JSONPath syntax, without the leading `$`
+    location: String,
+}
+
+impl<'s> JsonReader<'s> {
+    fn error(&self, msg: String) -> SavefileError {
+        SavefileError::JsonMismatch {
+            path: format!("${}", self.location),
+            msg,
+        }
+    }
+    fn expected(&self, what: &str, json: &Json) -> SavefileError {
+        self.error(format!("expected {}, found {}", what, json_kind(json)))
+    }
+
+    fn read_child(
+        &mut self,
+        json: &Json,
+        schema: &'s Schema,
+        step: Step,
+        recursion_point: bool,
+        location: &str,
+    ) -> Result<Value, SavefileError> {
+        let prev_len = self.location.len();
+        self.location.push_str(location);
+        let value = if let Schema::Recursion(depth) = schema {
+            let (target, removed) = self.path.resolve(*depth, step)?;
+            let value = self.read_value(json, target)?;
+            self.path.restore(removed);
+            value
+        } else {
+            self.path.enter(schema, step, recursion_point, false);
+            let value = self.read_value(json, schema)?;
+            self.path.leave();
+            value
+        };
+        self.location.truncate(prev_len);
+        Ok(value)
+    }
+
+    fn read_fields(
+        &mut self,
+        json: &Json,
+        fields: &'s [Field],
+        variant: usize,
+        recursion_points: bool,
+    ) -> Result<Vec<(String, Value)>, SavefileError> {
+        let Json::Object(object) = json else {
+            return Err(self.expected("an object", json));
+        };
+        if let Some(unknown) = object.keys().find(|key| !fields.iter().any(|x| x.name == **key)) {
+            return Err(self.error(format!("unknown field '{}'", unknown)));
+        }
+        let mut values = Vec::with_capacity(fields.len());
+        for (index, field) in fields.iter().enumerate() {
+            let Some(json) = object.get(&field.name) else {
+                return Err(self.error(format!("missing field '{}'", field.name)));
+            };
+            let location = format!(".{}", field.name);
+            let value = self.read_child(json, &field.value, (variant, index), recursion_points, &location)?;
+            values.push((field.name.clone(), value));
+        }
+        Ok(values)
+    }
+
+    fn read_integer<T: TryFrom<i128>>(&self, json: &Json, name: &str) -> Result<T, SavefileError> {
+        let number = match json {
+            Json::Number(x) => x.as_i64().map(i128::from).or_else(|| x.as_u64().map(i128::from)),
+            Json::String(x) => x.parse::<i128>().ok(),
+            _ => None,
+        };
+        number
+            .and_then(|x| T::try_from(x).ok())
+            .ok_or_else(|| self.expected(name, json))
+    }
+
+    fn read_float(&self, json: &Json, name: &str) -> Result<f64, SavefileError> {
+        match json {
+            Json::Number(x) => x.as_f64(),
+            Json::String(x) => x.parse::<f64>().ok(),
+            _ => None,
+        }
+        .ok_or_else(|| self.expected(name, json))
+    }
+
+    fn read_primitive(&self, json: &Json, primitive: &SchemaPrimitive) -> Result<Value, SavefileError> {
+        let name = primitive.name();
+        Ok(match primitive {
+            SchemaPrimitive::schema_bool => Value::Bool(json.as_bool().ok_or_else(|| self.expected(name, json))?),
+            SchemaPrimitive::schema_u8 => Value::U8(self.read_integer(json, name)?),
+            SchemaPrimitive::schema_i8 => Value::I8(self.read_integer(json, name)?),
+            SchemaPrimitive::schema_u16 => Value::U16(self.read_integer(json, name)?),
+            SchemaPrimitive::schema_i16 => Value::I16(self.read_integer(json, name)?),
+            SchemaPrimitive::schema_u32 => Value::U32(self.read_integer(json, name)?),
+            SchemaPrimitive::schema_i32 => Value::I32(self.read_integer(json, name)?),
+            SchemaPrimitive::schema_u64 => Value::U64(self.read_integer(json, name)?),
+            SchemaPrimitive::schema_i64 => Value::I64(self.read_integer(json, name)?),
+            SchemaPrimitive::schema_i128 => Value::I128(self.read_integer(json, name)?),
+            SchemaPrimitive::schema_u128 => {
+                // Doesn't fit in i128, so parsed separately
+                let number = match json {
+                    Json::Number(x) => x.as_u64().map(u128::from),
+                    Json::String(x) => x.parse::<u128>().ok(),
+                    _ => None,
+                };
+                Value::U128(number.ok_or_else(|| self.expected(name, json))?)
+            }
+            SchemaPrimitive::schema_f32 => Value::F32(self.read_float(json, name)? as f32),
+            SchemaPrimitive::schema_f64 => Value::F64(self.read_float(json, name)?),
+            SchemaPrimitive::schema_char => {
+                let mut chars = json.as_str().unwrap_or_default().chars();
+                match (chars.next(), chars.next()) {
+                    (Some(x), None) => Value::Char(x),
+                    _ => return Err(self.expected("a string with a single char", json)),
+                }
+            }
+            SchemaPrimitive::schema_string(_) => Value::String(
+                json.as_str()
+                    .ok_or_else(|| self.expected("a string", json))?
+                    .to_string(),
+            ),
+            SchemaPrimitive::schema_canary1 => {
+                if json.as_u64() != Some(CANARY1 as u64) {
+                    return Err(self.expected(&format!("the canary value {}", CANARY1), json));
+                }
+                Value::U32(CANARY1)
+            }
+        })
+    }
+
+    fn read_value(&mut self, json: &Json, schema: &'s Schema) -> Result<Value, SavefileError> {
+        Ok(match schema {
+            Schema::Struct(s) => Value::Struct {
+                name: s.dbg_name.clone(),
+                fields: self.read_fields(json, &s.fields, 0, false)?,
+            },
+            Schema::Enum(e) => {
+                let (variant_name, fields_json) = match json {
+                    Json::String(name) => (name, None),
+                    Json::Object(object) if object.len() == 1 => {
+                        let (name, fields) = object.iter().next().expect("object has one entry");
+                        (name, Some(fields))
+                    }
+                    _ => return Err(self.expected("a variant name, or an object with a single variant", json)),
+                };
+                let Some(index) = e.variants.iter().position(|x| x.name == *variant_name) else {
+                    return Err(self.error(format!("unknown variant '{}' of enum {}", variant_name, e.dbg_name)));
+                };
+                let variant = &e.variants[index];
+                let fields = match fields_json {
+                    None if variant.fields.is_empty() => vec![],
+                    None => return Err(self.error(format!("variant '{}' has fields", variant_name))),
+                    Some(fields_json) => {
+                        let prev_len = self.location.len();
+                        self.location.push_str(&format!(".{}", variant_name));
+                        let fields = self.read_fields(fields_json, &variant.fields, index, false)?;
+                        self.location.truncate(prev_len);
+                        fields
+                    }
+                };
+                Value::Enum {
+                    name: e.dbg_name.clone(),
+                    variant: variant.name.clone(),
+                    fields,
+                }
+            }
+            Schema::Primitive(primitive) => self.read_primitive(json, primitive)?,
+            Schema::Vector(item, _) => {
+                let Json::Array(items) = json else {
+                    return Err(self.expected("an array", json));
+                };
+                let mut values = Vec::with_capacity(items.len());
+                for (index, json) in items.iter().enumerate() {
+                    let location = format!("[{}]", index);
+                    match &**item {
+                        Schema::Struct(entry) if is_map_entry(entry) => {
+                            let prev_len = self.location.len();
+                            self.location.push_str(&location);
+                            self.path.enter(item, (0, 0), false, true);
+                            values.push(Value::Struct {
+                                name: entry.dbg_name.clone(),
+                                fields: self.read_fields(json, &entry.fields, 0, true)?,
+                            });
+                            self.path.leave();
+                            self.location.truncate(prev_len);
+                        }
+                        _ => values.push(self.read_child(json, item, (0, 0), true, &location)?),
+                    }
+                }
+                Value::Vector(values)
+            }
+            Schema::Array(array) => {
+                let items = match json {
+                    Json::Array(items) if items.len() == array.count => items,
+                    _ => return Err(self.expected(&format!("an array of length {}", array.count), json)),
+                };
+                let mut values = Vec::with_capacity(items.len());
+                for (index, json) in items.iter().enumerate() {
+                    let location = format!("[{}]", index);
+                    values.push(self.read_child(json, &array.item_type, (0, 0), true, &location)?);
+                }
+                Value::Array(values)
+            }
+            Schema::SchemaOption(inner) => match (json, &**inner) {
+                (Json::Null, _) => Value::Option(None),
+                (json, Schema::SchemaOption(_) | Schema::ZeroSize) => {
+                    let item = match json {
+                        Json::Array(items) if items.len() == 1 => &items[0],
+                        _ => return Err(self.expected("null, or an array with a single item", json)),
+                    };
+                    Value::Option(Some(Box::new(self.read_child(item, inner, (0, 0), false, "[0]")?)))
+                }
+                (json, _) => Value::Option(Some(Box::new(self.read_child(json, inner, (0, 0), false, "")?))),
+            },
+            Schema::ZeroSize => match json {
+                Json::Null => Value::Unit,
+                _ => return Err(self.expected("null", json)),
+            },
+            Schema::Boxed(inner) => self.read_child(json, inner, (0, 0), false, "")?,
+            other => {
+                return Err(self.error(format!(
+                    "values of schema type {} are not supported",
+                    other.top_level_description()
+                )))
+            }
+        })
+    }
+}
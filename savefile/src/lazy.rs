@@ -0,0 +1,401 @@
+use crate::header::METADATA_FLAG;
+use crate::limits::load_payload_limited;
+use crate::{
+    check_file_schema, read_file_header, Deserialize, Deserializer, Field, Introspect, IntrospectItem, LoadLimits,
+    Packed, PayloadLoader, SavefileError, Schema, SchemaPrimitive, SchemaStruct, Serialize, Serializer, WithSchema,
+    WithSchemaContext,
+};
+use byteorder::ReadBytesExt;
+use std::any::TypeId;
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom, Write};
+use std::marker::PhantomData;
+use std::path::Path;
+use std::rc::Rc;
+
+#[derive(Debug, Clone)]
+enum LazyState<T> {
+    Loaded(T),
+    /// The serialized value, in the format of `file_version`
+    Bytes {
+        data: Vec<u8>,
+        file_version: u32,
+        /// The limits of the deserializer the value was loaded by
+        limits: Option<LoadLimits>,
+    },
+    /// The serialized value is `len` bytes at `offset` in the file it was loaded from
+    InFile {
+        offset: u64,
+        len: u64,
+        file_version: u32,
+        limits: Option<LoadLimits>,
+    },
+}
+
+/// A value which is not deserialized when the data containing it is loaded, but
+/// when it is first accessed.
+///
+/// This is useful for large parts of the data which are often not needed, such as the
+/// terrain of a level. The value is stored preceded by its length, so loading can skip it.
+///
+/// * When loaded using [load_lazy] or [load_file_lazy] from a file which is not compressed
+///   and has no checksum, the value is not read at all. Only its position in the file is
+///   remembered, and [Lazy::get_from] reads it from the file when it is needed.
+/// * Otherwise, the serialized value is kept in memory, and deserialized by [Lazy::get].
+///
+/// If the data containing the value was loaded with [crate::LoadLimits], the value is
+/// deserialized with the same limits. The allocations of each lazy value are counted
+/// separately, starting from zero.
+///
+/// The schema of `Lazy<T>` is a struct `Lazy` with the fields `length` (`u64`) and
+/// `value` (`T`), so files containing lazy values can be read by code which does not use `Lazy`.
+/// When a [crate::Value] is written, such as by [crate::load_lenient], the length of lazy
+/// values is computed from their value, ignoring the `length` field.
+#[derive(Debug, Clone)]
+pub struct Lazy<T> {
+    state: LazyState<T>,
+}
+
+impl<T> Lazy<T> {
+    /// A lazy value which has already been loaded
+    pub fn new(value: T) -> Lazy<T> {
+        Lazy {
+            state: LazyState::Loaded(value),
+        }
+    }
+    /// True if the value has been deserialized
+    pub fn is_loaded(&self) -> bool {
+        matches!(self.state, LazyState::Loaded(_))
+    }
+    /// The value, if it has been deserialized
+    pub fn get_loaded(&self) -> Option<&T> {
+        match &self.state {
+            LazyState::Loaded(value) => Some(value),
+            _ => None,
+        }
+    }
+}
+
+impl<T: Deserialize> Lazy<T> {
+    /// The value, deserializing it if needed.
+    ///
+    /// Fails if the value is still in the file it was loaded from, use [Lazy::get_from] then.
+    pub fn get(&mut self) -> Result<&mut T, SavefileError> {
+        if let LazyState::Bytes {
+            data,
+            file_version,
+            limits,
+        } = &self.state
+        {
+            let value = deserialize_bytes(data, *file_version, *limits)?;
+            self.state = LazyState::Loaded(value);
+        }
+        match &mut self.state {
+            LazyState::Loaded(value) => Ok(value),
+            _ => Err(SavefileError::GeneralError {
+                msg: "The lazy value has not been read from its file. Use Lazy::get_from.".into(),
+            }),
+        }
+    }
+
+    /// The value, deserializing it if needed. If the value is still in the file it was
+    /// loaded from, it is read from `reader`, which must read the same file, or buffer,
+    /// as was given to [load_lazy].
+    pub fn get_from(&mut self, reader: &mut (impl Read + Seek)) -> Result<&mut T, SavefileError> {
+        if let LazyState::InFile {
+            offset,
+            len,
+            file_version,
+            limits,
+        } = self.state
+        {
+            reader.seek(SeekFrom::Start(offset))?;
+            let mut data = Vec::new();
+            reader.take(len).read_to_end(&mut data)?;
+            if data.len() as u64 != len {
+                return Err(SavefileError::ShortRead);
+            }
+            self.state = LazyState::Loaded(deserialize_bytes(&data, file_version, limits)?);
+        }
+        self.get()
+    }
+
+    /// The value, deserializing it if needed. See [Lazy::get].
+    pub fn into_inner(mut self) -> Result<T, SavefileError> {
+        self.get()?;
+        match self.state {
+            LazyState::Loaded(value) => Ok(value),
+            _ => unreachable!("get succeeded, so the value is loaded"),
+        }
+    }
+}
+
+impl<T> From<T> for Lazy<T> {
+    fn from(value: T) -> Lazy<T> {
+        Lazy::new(value)
+    }
+}
+
+fn deserialize_bytes<T: Deserialize>(
+    data: &[u8],
+    file_version: u32,
+    limits: Option<LoadLimits>,
+) -> Result<T, SavefileError> {
+    let mut deserializer = Deserializer {
+        reader: &mut &data[..],
+        file_version,
+        ephemeral_state: HashMap::new(),
+        limit_state: None,
+    };
+    if let Some(limits) = limits {
+        deserializer.set_limits(limits);
+    }
+    T::deserialize(&mut deserializer)
+}
+
+impl<T: WithSchema> WithSchema for Lazy<T> {
+    fn schema(version: u32, context: &mut WithSchemaContext) -> Schema {
+        Schema::Struct(SchemaStruct::new(
+            "Lazy".to_string(),
+            vec![
+                Field::new(
+                    "length".to_string(),
+                    Box::new(Schema::Primitive(SchemaPrimitive::schema_u64)),
+                ),
+                Field::new("value".to_string(), Box::new(T::schema(version, context))),
+            ],
+        ))
+    }
+}
+
+impl<T> Packed for Lazy<T> {}
+
+impl<T: Serialize + Deserialize> Serialize for Lazy<T> {
+    fn serialize(&self, serializer: &mut Serializer<impl Write>) -> Result<(), SavefileError> {
+        let write = |serializer: &mut Serializer<_>, data: &[u8]| {
+            serializer.write_u64(data.len() as u64)?;
+            serializer.write_bytes(data)
+        };
+        let serialize = |value: &T| {
+            let mut data = Vec::new();
+            value.serialize(&mut Serializer {
+                writer: &mut data,
+                file_version: serializer.file_version,
+            })?;
+            Ok::<_, SavefileError>(data)
+        };
+        match &self.state {
+            LazyState::Loaded(value) => {
+                let data = serialize(value)?;
+                write(serializer, &data)
+            }
+            LazyState::Bytes { data, file_version, .. } if *file_version == serializer.file_version => {
+                write(serializer, data)
+            }
+            LazyState::Bytes {
+                data,
+                file_version,
+                limits,
+            } => {
+                // Stored in the format of another version, so it has to be converted
+                let data = serialize(&deserialize_bytes(data, *file_version, *limits)?)?;
+                write(serializer, &data)
+            }
+            LazyState::InFile { .. } => Err(SavefileError::GeneralError {
+                msg: "The lazy value has not been read from its file, so it cannot be saved. Use Lazy::get_from first."
+                    .into(),
+            }),
+        }
+    }
+}
+
+/// Where the reader of a file loaded by [load_lazy] is, shared between [SkippingReader]
+/// and the deserializer of [Lazy]
+#[derive(Clone)]
+struct LazySource {
+    /// Position in the file of the data read so far
+    position: Rc<Cell<u64>>,
+    /// Number of bytes to skip before reading more
+    pending_skip: Rc<Cell<u64>>,
+}
+
+impl<T: Deserialize> Deserialize for Lazy<T> {
+    fn deserialize(deserializer: &mut Deserializer<impl Read>) -> Result<Self, SavefileError> {
+        let len = deserializer.read_u64()?;
+        let file_version = deserializer.file_version;
+        let limits = deserializer.limits();
+        let source = deserializer
+            .ephemeral_state
+            .get(&TypeId::of::<LazySource>())
+            .and_then(|x| x.downcast_ref::<LazySource>());
+        if let Some(source) = source {
+            let offset = source.position.get() + source.pending_skip.get();
+            source.pending_skip.set(source.pending_skip.get() + len);
+            return Ok(Lazy {
+                state: LazyState::InFile {
+                    offset,
+                    len,
+                    file_version,
+                    limits,
+                },
+            });
+        }
+        deserializer.check_allocated_bytes(len)?;
+        // The length may be corrupt, don't trust it for the initial allocation
+        let mut data = Vec::with_capacity(len.min(4096) as usize);
+        (&mut *deserializer.reader).take(len).read_to_end(&mut data)?;
+        if data.len() as u64 != len {
+            return Err(SavefileError::ShortRead);
+        }
+        Ok(Lazy {
+            state: LazyState::Bytes {
+                data,
+                file_version,
+                limits,
+            },
+        })
+    }
+}
+
+impl<T: Introspect> Introspect for Lazy<T> {
+    fn introspect_value(&self) -> String {
+        match &self.state {
+            LazyState::Loaded(value) => value.introspect_value(),
+            _ => "Lazy (not loaded)".to_string(),
+        }
+    }
+
+    fn introspect_child(&self, index: usize) -> Option<Box<dyn IntrospectItem<'_> + '_>> {
+        match &self.state {
+            LazyState::Loaded(value) => value.introspect_child(index),
+            _ => None,
+        }
+    }
+
+    fn introspect_len(&self) -> usize {
+        match &self.state {
+            LazyState::Loaded(value) => value.introspect_len(),
+            _ => 0,
+        }
+    }
+}
+
+/// Reads from a seekable reader, seeking past the data of lazy values instead of reading it
+struct SkippingReader<'a, R> {
+    reader: &'a mut R,
+    source: LazySource,
+}
+
+impl<R: Read + Seek> Read for SkippingReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let skip = self.source.pending_skip.replace(0);
+        if skip > 0 {
+            let skip = i64::try_from(skip).map_err(|_| std::io::Error::from(std::io::ErrorKind::InvalidData))?;
+            self.reader.seek(SeekFrom::Current(skip))?;
+            self.source.position.set(self.source.position.get() + skip as u64);
+        }
+        let count = self.reader.read(buf)?;
+        self.source.position.set(self.source.position.get() + count as u64);
+        Ok(count)
+    }
+}
+
+/// Loads an instance of T, checking the schema like [crate::load], letting
+/// lazy values skip their data
+struct LazyLoader<T> {
+    source: Option<LazySource>,
+    phantom: PhantomData<T>,
+}
+
+impl<T: WithSchema + Deserialize> PayloadLoader for LazyLoader<T> {
+    type Output = T;
+    fn reads_schema(&self) -> bool {
+        true
+    }
+    fn load_data(
+        self,
+        deserializer: &mut Deserializer<impl Read>,
+        file_schema: Option<Schema>,
+    ) -> Result<T, SavefileError> {
+        if let Some(file_schema) = file_schema {
+            let file_ver = deserializer.file_version;
+            let memory_schema = T::schema(file_ver, &mut WithSchemaContext::new());
+            check_file_schema(&memory_schema, &file_schema, file_ver)?;
+        }
+        if let Some(source) = self.source {
+            deserializer
+                .ephemeral_state
+                .insert(TypeId::of::<LazySource>(), Box::new(source));
+        }
+        T::deserialize(deserializer)
+    }
+}
+
+/// Like [crate::load], but the data of [Lazy] values is skipped instead of being read,
+/// if the file is not compressed and has no checksum. Their data is read by [Lazy::get_from],
+/// which must be given the same `reader`, or one reading the same file or buffer.
+///
+/// Positions are relative to the start of the underlying file or buffer, not to the position
+/// of `reader` when this is called.
+pub fn load_lazy<T: WithSchema + Deserialize>(
+    reader: &mut (impl Read + Seek),
+    version: u32,
+) -> Result<T, SavefileError> {
+    load_lazy_impl(reader, version, None)
+}
+
+/// Like [load_lazy], but fails with [SavefileError::LimitExceeded] if the data exceeds the
+/// given limits. The limits also apply when the [Lazy] values are deserialized.
+pub fn load_lazy_with_limits<T: WithSchema + Deserialize>(
+    reader: &mut (impl Read + Seek),
+    version: u32,
+    limits: &LoadLimits,
+) -> Result<T, SavefileError> {
+    load_lazy_impl(reader, version, Some(*limits))
+}
+
+fn load_lazy_impl<T: WithSchema + Deserialize>(
+    reader: &mut (impl Read + Seek),
+    version: u32,
+    limits: Option<LoadLimits>,
+) -> Result<T, SavefileError> {
+    let (savefile_lib_version, file_ver) = read_file_header(reader, version)?;
+    // Only plain data can be skipped by seeking
+    let compression = reader.read_u8()?;
+    reader.seek(SeekFrom::Current(-1))?;
+    let source = (compression & !METADATA_FLAG == 0).then(|| LazySource {
+        position: Rc::new(Cell::new(0)),
+        pending_skip: Rc::new(Cell::new(0)),
+    });
+    let loader = LazyLoader {
+        source: source.clone(),
+        phantom: PhantomData,
+    };
+    match source {
+        Some(source) => {
+            source.position.set(reader.stream_position()?);
+            let mut reader = SkippingReader { reader, source };
+            load_payload_limited(&mut reader, savefile_lib_version, file_ver, loader, limits)
+        }
+        None => load_payload_limited(reader, savefile_lib_version, file_ver, loader, limits),
+    }
+}
+
+/// Like [load_lazy], but loads from the given file. Use [Lazy::get_from] with the
+/// same file, opened again, to read lazy values.
+pub fn load_file_lazy<T: WithSchema + Deserialize, P: AsRef<Path>>(path: P, version: u32) -> Result<T, SavefileError> {
+    let mut f = BufReader::new(File::open(path)?);
+    load_lazy(&mut f, version)
+}
+
+/// Like [load_lazy_with_limits], but loads from the given file.
+pub fn load_file_lazy_with_limits<T: WithSchema + Deserialize, P: AsRef<Path>>(
+    path: P,
+    version: u32,
+    limits: &LoadLimits,
+) -> Result<T, SavefileError> {
+    let mut f = BufReader::new(File::open(path)?);
+    load_lazy_with_limits(&mut f, version, limits)
+}
@@ -0,0 +1,467 @@
+use crate::value::{is_map_entry, read_value, SchemaPath, Step, CANARY1};
+use crate::{
+    check_file_schema, get_schema, read_file_header, Deserialize, Deserializer, Field, FieldDefault, PayloadLoader,
+    SavefileError, Schema, SchemaPrimitive, Serializer, Value, WithSchema,
+};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::marker::PhantomData;
+use std::path::Path;
+
+/// How the fields of a struct or enum variant in a file are matched to those in memory
+#[derive(Clone, Copy)]
+enum Matching {
+    /// By name, in any order. Missing fields are defaulted, see [load_lenient].
+    ByName,
+    /// The fields in memory must be the first fields in the file, see [load_forward_compatible].
+    Prefix,
+}
+
+/// Converts values read using the schema of a file to the schema in memory, keeping track of
+/// where in the data it is, for error messages.
+struct Remapper<'s> {
+    matching: Matching,
+    /// The version of the memory schema
+    version: u32,
+    path: SchemaPath<'s>,
+    /// Location in the data, such as `.inventory[2].name`
+    location: String,
+}
+
+impl<'s> Remapper<'s> {
+    fn error(&self, msg: String) -> SavefileError {
+        SavefileError::IncompatibleSchema {
+            message: format!("Cannot load leniently, at ${}: {}", self.location, msg),
+        }
+    }
+
+    /// Call `f` with the schema of the child `schema` of the current node, with any recursion resolved
+    fn child<R>(
+        &mut self,
+        schema: &'s Schema,
+        step: Step,
+        recursion_point: bool,
+        location: &str,
+        f: impl FnOnce(&mut Self, &'s Schema) -> Result<R, SavefileError>,
+    ) -> Result<R, SavefileError> {
+        let prev_len = self.location.len();
+        self.location.push_str(location);
+        let result = if let Schema::Recursion(depth) = schema {
+            let (target, removed) = self.path.resolve(*depth, step)?;
+            let result = f(self, target)?;
+            self.path.restore(removed);
+            result
+        } else {
+            self.path.enter(schema, step, recursion_point, false);
+            let result = f(self, schema)?;
+            self.path.leave();
+            result
+        };
+        self.location.truncate(prev_len);
+        Ok(result)
+    }
+
+    /// The fields of the memory schema, taken by name from `file_fields`. Fields which are not in
+    /// the file get a default value, fields which are not in memory are dropped.
+    /// With [Matching::Prefix], the fields must be in the same order, and none may be missing.
+    fn remap_fields(
+        &mut self,
+        mut file_fields: Vec<(String, Value)>,
+        fields: &'s [Field],
+        variant: usize,
+        recursion_points: bool,
+    ) -> Result<Vec<(String, Value)>, SavefileError> {
+        let mut values = Vec::with_capacity(fields.len());
+        for (index, field) in fields.iter().enumerate() {
+            let location = format!(".{}", field.name);
+            let step = (variant, index);
+            let position = match self.matching {
+                Matching::ByName => file_fields.iter().position(|(name, _)| *name == field.name),
+                Matching::Prefix => match file_fields.first() {
+                    Some((name, _)) if *name == field.name => Some(0),
+                    Some((name, _)) => {
+                        return Err(self.error(format!(
+                            "the file has field '{}' where memory has field '{}'",
+                            name, field.name
+                        )))
+                    }
+                    None => return Err(self.error(format!("the file has no field '{}'", field.name))),
+                },
+            };
+            let value = match position {
+                Some(position) => {
+                    let (_, value) = file_fields.remove(position);
+                    self.child(&field.value, step, recursion_points, &location, |this, schema| {
+                        this.remap(value, schema)
+                    })?
+                }
+                None => self.field_default(field, step, recursion_points, &location)?,
+            };
+            values.push((field.name.clone(), value));
+        }
+        Ok(values)
+    }
+
+    /// Convert `value` to the memory schema `schema`
+    fn remap(&mut self, value: Value, schema: &'s Schema) -> Result<Value, SavefileError> {
+        Ok(match (schema, value) {
+            (Schema::Struct(s), Value::Struct { fields, .. }) => Value::Struct {
+                name: s.dbg_name.clone(),
+                fields: self.remap_fields(fields, &s.fields, 0, false)?,
+            },
+            (Schema::Enum(e), Value::Enum { variant, fields, .. }) => {
+                let Some(index) = e.variants.iter().position(|x| x.name == variant) else {
+                    return Err(self.error(match self.matching {
+                        Matching::ByName => format!("enum {} no longer has variant {}", e.dbg_name, variant),
+                        // The variant was added by a newer version
+                        Matching::Prefix => format!("enum {} in memory has no variant {}", e.dbg_name, variant),
+                    }));
+                };
+                Value::Enum {
+                    name: e.dbg_name.clone(),
+                    variant,
+                    fields: self.remap_fields(fields, &e.variants[index].fields, index, false)?,
+                }
+            }
+            (Schema::Primitive(primitive), value) => {
+                if !primitive_matches(primitive, &value) {
+                    return Err(self.error(format!(
+                        "the file has {} where memory has {}",
+                        value.type_name(),
+                        primitive.name()
+                    )));
+                }
+                value
+            }
+            (Schema::Vector(item, _), Value::Vector(items)) => {
+                let mut values = Vec::with_capacity(items.len());
+                for (index, value) in items.into_iter().enumerate() {
+                    let location = format!("[{}]", index);
+                    match (&**item, value) {
+                        (Schema::Struct(entry), Value::Struct { fields, .. }) if is_map_entry(entry) => {
+                            let prev_len = self.location.len();
+                            self.location.push_str(&location);
+                            self.path.enter(item, (0, 0), false, true);
+                            values.push(Value::Struct {
+                                name: entry.dbg_name.clone(),
+                                fields: self.remap_fields(fields, &entry.fields, 0, true)?,
+                            });
+                            self.path.leave();
+                            self.location.truncate(prev_len);
+                        }
+                        (_, value) => values
+                            .push(self.child(item, (0, 0), true, &location, |this, schema| this.remap(value, schema))?),
+                    }
+                }
+                Value::Vector(values)
+            }
+            (Schema::Array(array), Value::Array(items)) => {
+                if items.len() != array.count {
+                    return Err(self.error(format!(
+                        "the file has an array of length {} where memory has length {}",
+                        items.len(),
+                        array.count
+                    )));
+                }
+                let mut values = Vec::with_capacity(items.len());
+                for (index, value) in items.into_iter().enumerate() {
+                    let location = format!("[{}]", index);
+                    values.push(self.child(&array.item_type, (0, 0), true, &location, |this, schema| {
+                        this.remap(value, schema)
+                    })?);
+                }
+                Value::Array(values)
+            }
+            (Schema::SchemaOption(inner), Value::Option(value)) => match value {
+                Some(value) => Value::Option(Some(Box::new(self.child(
+                    inner,
+                    (0, 0),
+                    false,
+                    "",
+                    |this, schema| this.remap(*value, schema),
+                )?))),
+                None => Value::Option(None),
+            },
+            (Schema::ZeroSize, Value::Unit) => Value::Unit,
+            (Schema::Boxed(inner), value) => {
+                self.child(inner, (0, 0), false, "", |this, schema| this.remap(value, schema))?
+            }
+            (schema, value) => {
+                return Err(self.error(format!(
+                    "the file has {} where memory has {}",
+                    value.type_name(),
+                    schema.top_level_description()
+                )))
+            }
+        })
+    }
+
+    /// The value of `field`, which is in memory but not in the file. This is the default declared
+    /// for the field, if any, and otherwise [Remapper::default_value].
+    fn field_default(
+        &mut self,
+        field: &'s Field,
+        step: Step,
+        recursion_point: bool,
+        location: &str,
+    ) -> Result<Value, SavefileError> {
+        self.child(&field.value, step, recursion_point, location, |this, schema| match field.default() {
+            Some(FieldDefault::Serialized(serialize_default)) => {
+                let data = serialize_default(this.version)?;
+                let mut deserializer = Deserializer {
+                    reader: &mut &data[..],
+                    file_version: this.version,
+                    ephemeral_state: HashMap::new(),
+                    limit_state: None,
+                };
+                read_value(&mut this.path, &mut deserializer, schema)
+            }
+            Some(FieldDefault::Unavailable) => Err(this.error(format!(
+                "the file has no value for it, and its declared default cannot be used, since the type of field '{}' is generic",
+                field.name
+            ))),
+            None => this.default_value(schema),
+        })
+    }
+
+    /// The value used for data which is in memory, but not in the file, and has no declared
+    /// default: zero, false, empty strings and vectors, None, and the first variant of enums.
+    fn default_value(&mut self, schema: &'s Schema) -> Result<Value, SavefileError> {
+        Ok(match schema {
+            Schema::Struct(s) => {
+                let mut fields = Vec::with_capacity(s.fields.len());
+                for (index, field) in s.fields.iter().enumerate() {
+                    let location = format!(".{}", field.name);
+                    let value = self.field_default(field, (0, index), false, &location)?;
+                    fields.push((field.name.clone(), value));
+                }
+                Value::Struct {
+                    name: s.dbg_name.clone(),
+                    fields,
+                }
+            }
+            Schema::Enum(e) => {
+                let Some(variant) = e.variants.first() else {
+                    return Err(self.error(format!("enum {} has no variants, so there is no default", e.dbg_name)));
+                };
+                let mut fields = Vec::with_capacity(variant.fields.len());
+                for (index, field) in variant.fields.iter().enumerate() {
+                    let location = format!(".{}", field.name);
+                    let value = self.field_default(field, (0, index), false, &location)?;
+                    fields.push((field.name.clone(), value));
+                }
+                Value::Enum {
+                    name: e.dbg_name.clone(),
+                    variant: variant.name.clone(),
+                    fields,
+                }
+            }
+            Schema::Primitive(primitive) => default_primitive(primitive),
+            Schema::Vector(..) => Value::Vector(vec![]),
+            Schema::Array(array) => {
+                let mut values = Vec::with_capacity(array.count);
+                for index in 0..array.count {
+                    let location = format!("[{}]", index);
+                    values.push(self.child(&array.item_type, (0, 0), true, &location, |this, schema| {
+                        this.default_value(schema)
+                    })?);
+                }
+                Value::Array(values)
+            }
+            Schema::SchemaOption(_) => Value::Option(None),
+            Schema::ZeroSize => Value::Unit,
+            Schema::Boxed(inner) => self.child(inner, (0, 0), false, "", |this, schema| this.default_value(schema))?,
+            other => {
+                return Err(self.error(format!(
+                    "the file has no value for it, and there is no default for {}",
+                    other.top_level_description()
+                )))
+            }
+        })
+    }
+}
+
+fn primitive_matches(primitive: &SchemaPrimitive, value: &Value) -> bool {
+    matches!(
+        (primitive, value),
+        (SchemaPrimitive::schema_bool, Value::Bool(_))
+            | (SchemaPrimitive::schema_u8, Value::U8(_))
+            | (SchemaPrimitive::schema_i8, Value::I8(_))
+            | (SchemaPrimitive::schema_u16, Value::U16(_))
+            | (SchemaPrimitive::schema_i16, Value::I16(_))
+            | (SchemaPrimitive::schema_u32, Value::U32(_))
+            | (SchemaPrimitive::schema_i32, Value::I32(_))
+            | (SchemaPrimitive::schema_u64, Value::U64(_))
+            | (SchemaPrimitive::schema_i64, Value::I64(_))
+            | (SchemaPrimitive::schema_u128, Value::U128(_))
+            | (SchemaPrimitive::schema_i128, Value::I128(_))
+            | (SchemaPrimitive::schema_f32, Value::F32(_))
+            | (SchemaPrimitive::schema_f64, Value::F64(_))
+            | (SchemaPrimitive::schema_char, Value::Char(_))
+            | (SchemaPrimitive::schema_string(_), Value::String(_))
+            | (SchemaPrimitive::schema_canary1, Value::U32(CANARY1))
+    )
+}
+
+fn default_primitive(primitive: &SchemaPrimitive) -> Value {
+    match primitive {
+        SchemaPrimitive::schema_bool => Value::Bool(false),
+        SchemaPrimitive::schema_u8 => Value::U8(0),
+        SchemaPrimitive::schema_i8 => Value::I8(0),
+        SchemaPrimitive::schema_u16 => Value::U16(0),
+        SchemaPrimitive::schema_i16 => Value::I16(0),
+        SchemaPrimitive::schema_u32 => Value::U32(0),
+        SchemaPrimitive::schema_i32 => Value::I32(0),
+        SchemaPrimitive::schema_u64 => Value::U64(0),
+        SchemaPrimitive::schema_i64 => Value::I64(0),
+        SchemaPrimitive::schema_u128 => Value::U128(0),
+        SchemaPrimitive::schema_i128 => Value::I128(0),
+        SchemaPrimitive::schema_f32 => Value::F32(0.0),
+        SchemaPrimitive::schema_f64 => Value::F64(0.0),
+        SchemaPrimitive::schema_char => Value::Char('\0'),
+        SchemaPrimitive::schema_string(_) => Value::String(String::new()),
+        SchemaPrimitive::schema_canary1 => Value::U32(CANARY1),
+    }
+}
+
+/// Loads an instance of T, matching the data in the file to T using the schema in the file
+struct RemappingLoader<T> {
+    matching: Matching,
+    version: u32,
+    phantom: PhantomData<T>,
+}
+
+impl<T: WithSchema + Deserialize + 'static> PayloadLoader for RemappingLoader<T> {
+    type Output = T;
+    fn reads_schema(&self) -> bool {
+        true
+    }
+    fn load_data(
+        self,
+        deserializer: &mut Deserializer<impl Read>,
+        file_schema: Option<Schema>,
+    ) -> Result<T, SavefileError> {
+        let file_schema = file_schema.ok_or_else(|| SavefileError::GeneralError {
+            msg: "File has no schema.".into(),
+        })?;
+        let file_ver = deserializer.file_version;
+        if let (Matching::Prefix, true) = (self.matching, file_ver <= self.version) {
+            // Not from a newer writer, so it can be loaded the normal way
+            check_file_schema(&get_schema::<T>(file_ver), &file_schema, file_ver)?;
+            return T::deserialize(deserializer);
+        }
+        let file_value = Value::deserialize_with_schema(deserializer, &file_schema)?;
+
+        let memory_schema = get_schema::<T>(self.version);
+        let mut remapper = Remapper {
+            matching: self.matching,
+            version: self.version,
+            path: SchemaPath::new(&memory_schema),
+            location: String::new(),
+        };
+        let value = remapper.remap(file_value, &memory_schema)?;
+
+        // Write the data in the format of the memory schema, and read it back as a T
+        let limits = deserializer.limits();
+        let mut data = Vec::new();
+        let mut serializer = Serializer {
+            writer: &mut data,
+            file_version: self.version,
+        };
+        value.serialize_with_schema(&mut serializer, &memory_schema)?;
+        let mut deserializer = Deserializer {
+            reader: &mut &data[..],
+            file_version: self.version,
+            ephemeral_state: HashMap::new(),
+            limit_state: None,
+        };
+        if let Some(limits) = limits {
+            deserializer.set_limits(limits);
+        }
+        T::deserialize(&mut deserializer)
+    }
+}
+
+fn load_remapped<T: WithSchema + Deserialize + 'static>(
+    reader: &mut impl Read,
+    version: u32,
+    matching: Matching,
+) -> Result<T, SavefileError> {
+    let (savefile_lib_version, file_ver) = read_file_header(reader, u32::MAX)?;
+    let loader = RemappingLoader {
+        matching,
+        version,
+        phantom: PhantomData,
+    };
+    Deserializer::<_>::load_payload_with(reader, savefile_lib_version, file_ver, loader)
+}
+
+/// Load an instance of `T` from `reader`, using the schema stored in the file to match
+/// the data to `T` by name, instead of requiring the data to have the schema of `T`.
+///
+/// * Struct fields, and fields of enum variants, are matched by name. Their order
+///   does not matter.
+/// * Fields which no longer exist in `T` are skipped.
+/// * Fields which are not in the file get the default value declared using the
+///   `savefile_default_val` or `savefile_default_fn` attributes. Loading fails if the declared
+///   default cannot be used, which is the case for fields whose type involves a type parameter.
+///   Fields without a declared default get zero, `false`, an empty string or collection,
+///   `None`, or the first variant of an enum.
+/// * Enum variants are matched by name. Loading fails if the file contains a variant which
+///   no longer exists.
+/// * Other types, such as the type of a field, must not have changed.
+///
+/// The version of the file is not checked, and `savefile_versions` annotations are not needed
+/// for this to work. `version` is the current version of `T`, the one the data is converted to.
+/// Loading this way is slower than [crate::load], since the data is converted on the way.
+///
+/// The file must have been saved with a schema, which is the case unless one of the
+/// `*_noschema` functions was used.
+pub fn load_lenient<T: WithSchema + Deserialize + 'static>(
+    reader: &mut impl Read,
+    version: u32,
+) -> Result<T, SavefileError> {
+    load_remapped(reader, version, Matching::ByName)
+}
+
+/// Like [load_lenient], but loads from the given file.
+pub fn load_file_lenient<T: WithSchema + Deserialize + 'static, P: AsRef<Path>>(
+    path: P,
+    version: u32,
+) -> Result<T, SavefileError> {
+    let mut f = BufReader::new(File::open(path)?);
+    load_lenient(&mut f, version)
+}
+
+/// Load an instance of `T` from `reader`, like [crate::load], except that files written
+/// by newer versions of the program, with a later version than `version`, can also be loaded.
+///
+/// This allows older programs to read data written by newer ones, provided that the newer
+/// versions only extend the data:
+///
+/// * Fields may be added at the end of structs and enum variants. They are skipped when loading.
+/// * Variants may be added at the end of enums. Loading only fails if one of them is
+///   actually present in the data.
+///
+/// Other changes, such as removing a field, cause loading of newer files to fail. The schema
+/// stored in the file describes what the newer version added, so the file must have been saved
+/// with a schema, which is the case unless one of the `*_noschema` functions was used.
+///
+/// Files which are not newer than `version` are loaded exactly like [crate::load] loads them.
+/// Newer files are converted on the way, which is slower.
+pub fn load_forward_compatible<T: WithSchema + Deserialize + 'static>(
+    reader: &mut impl Read,
+    version: u32,
+) -> Result<T, SavefileError> {
+    load_remapped(reader, version, Matching::Prefix)
+}
+
+/// Like [load_forward_compatible], but loads from the given file.
+pub fn load_file_forward_compatible<T: WithSchema + Deserialize + 'static, P: AsRef<Path>>(
+    path: P,
+    version: u32,
+) -> Result<T, SavefileError> {
+    let mut f = BufReader::new(File::open(path)?);
+    load_forward_compatible(&mut f, version)
+}
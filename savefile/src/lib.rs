@@ -74,13 +74,17 @@ fn main() {
 
 ```
 
+Besides [save_file] and [load_file], there are functions for saving with compression,
+checksums, metadata or encryption. These options can also be combined freely using
+[SaveOptions] and [LoadOptions], which save to and load from writers, files or memory.
+
 # Limitations of Savefile
 
 Savefile does make a few tradeoffs:
 
 1: It only supports the "savefile-format". It does not support any sort of pluggable
 architecture with different formats. This format is generally pretty 'raw', data is mostly
-formatted the same way as it is in RAM. There is support for bzip2, but this is just a simple
+formatted the same way as it is in RAM. There is support for compression (bzip2, zstd, lz4 and deflate), but this is just a simple
 post-processing step.
 
 2: It does not support serializing 'graphs'. I.e, it does not have a concept of object identity,
@@ -97,6 +101,11 @@ types may be contained in each node. In a tree, it will determine that children
 may be another node, which may itself have children of the same type, which may have children
 of the same type, and so on.
 
+4: A corrupt or malicious file can make loading allocate a lot of memory, by claiming to
+contain very large collections, or overflow the stack, by nesting values of recursive types
+very deeply. When loading files from untrusted sources, use [load_with_limits] with
+[LoadLimits] to bound the sizes and nesting depth accepted.
+
 # Handling old versions
 
 Let's expand the above example, by creating a 2nd version of the Player struct. Let's say
@@ -252,6 +261,16 @@ Rules for using the #\[savefile_versions] attribute:
  * You may not change the type of a field in your structs, except when using the savefile_versions_as-macro.
  * You may add enum variants in future versions, but you may not change the size of the discriminant.
 
+Mistakes in following these rules can be caught by a test calling [verify_schema_history], which
+records the schema of every released version in a directory, and fails if any of them later changes.
+Mistakes in conversions and default values can be caught using [verify_golden_files], which keeps
+a sample file for every released version, and checks that they all still load as they did.
+
+Data saved without following these rules can sometimes still be loaded using [load_lenient],
+which matches fields by name using the schema stored in the file.
+Programs which must open files written by newer versions of themselves can use
+[load_forward_compatible].
+
 
  ## The savefile_default_val attribute
 
@@ -930,6 +949,15 @@ extern crate savefile_derive;
 /// around is not supported.
 pub const CURRENT_SAVEFILE_LIB_VERSION: u16 = 1;
 
+/// The savefile version written to the header of files which use compression codecs
+/// other than bzip2, checksums or metadata. Savefile versions from before these were
+/// supported treat any nonzero compression byte as bzip2, and would fail with a misleading
+/// error. They do refuse to load files from later savefile versions, though.
+///
+/// Files not using any of these are still written with [CURRENT_SAVEFILE_LIB_VERSION],
+/// so that earlier savefile versions can load them.
+pub(crate) const EXTENDED_HEADER_SAVEFILE_LIB_VERSION: u16 = 2;
+
 /// This object represents an error in deserializing or serializing
 /// an item.
 #[derive(Debug)]
@@ -983,8 +1011,41 @@ pub enum SavefileError {
     },
     /// A poisoned mutex was encountered when traversing the object being saved
     PoisonedMutex,
-    /// File was compressed, or user asked for compression, but bzip2-library feature was not enabled.
-    CompressionSupportNotCompiledIn,
+    /// File was compressed, or user asked for compression, but the feature for the
+    /// compression codec was not enabled.
+    CompressionSupportNotCompiledIn {
+        /// The codec which is not supported
+        codec: CompressionCodec,
+    },
+    /// The password given when loading an encrypted file was not correct
+    WrongPassword,
+    /// The signature of signed data does not match. The data has been modified,
+    /// or was signed with a different key.
+    InvalidSignature,
+    /// The checksum stored in the file does not match the checksum of its contents.
+    /// The file is corrupt.
+    ChecksumMismatch {
+        /// The checksum stored in the file
+        expected: u64,
+        /// The checksum calculated from the contents of the file
+        actual: u64,
+    },
+    /// JSON being converted to savefile data does not match the schema
+    JsonMismatch {
+        /// Path of the offending part of the JSON, such as `$.shapes[2].width`
+        path: String,
+        /// Descriptive message
+        msg: String,
+    },
+    /// The data exceeds one of the [LoadLimits] it was loaded with
+    LimitExceeded {
+        /// The limit which was exceeded
+        limit: LimitKind,
+        /// The size or depth which exceeded the limit
+        value: u64,
+        /// The value of the limit
+        max: u64,
+    },
     /// Invalid char, i.e, a serialized value expected to be a char was encountered, but it had an invalid value.
     InvalidChar,
     /// This occurs for example when using the stable ABI-functionality to call into a library,
@@ -1062,8 +1123,35 @@ impl Display for SavefileError {
             SavefileError::PoisonedMutex => {
                 write!(f, "Poisoned mutex")
             }
-            SavefileError::CompressionSupportNotCompiledIn => {
-                write!(f, "Compression support missing - recompile with bzip2 feature enabled.")
+            SavefileError::CompressionSupportNotCompiledIn { codec } => {
+                write!(
+                    f,
+                    "Compression support missing - recompile with {} feature enabled.",
+                    codec
+                )
+            }
+            SavefileError::WrongPassword => {
+                write!(f, "Wrong password")
+            }
+            SavefileError::InvalidSignature => {
+                write!(f, "Invalid signature, data has been modified")
+            }
+            SavefileError::ChecksumMismatch { expected, actual } => {
+                write!(
+                    f,
+                    "Checksum mismatch, file is corrupt (expected {:#x}, got {:#x})",
+                    expected, actual
+                )
+            }
+            SavefileError::JsonMismatch { path, msg } => {
+                write!(f, "JSON does not match schema at {}: {}", path, msg)
+            }
+            SavefileError::LimitExceeded { limit, value, max } => {
+                write!(
+                    f,
+                    "Load limit exceeded: {} is {}, but the limit is {}",
+                    limit, value, max
+                )
             }
             SavefileError::InvalidChar => {
                 write!(f, "Invalid char value encountered.")
@@ -1131,6 +1219,9 @@ pub struct Deserializer<'a, R: Read> {
     /// This contains ephemeral state that can be used to implement de-duplication of
     /// strings or possibly other situations where it is desired to deserialize DAGs.
     pub ephemeral_state: HashMap<TypeId, Box<dyn Any>>,
+    /// The limits enforced while deserializing, see [Deserializer::set_limits].
+    /// `None` if nothing is limited.
+    pub limit_state: Option<LimitState>,
 }
 
 impl<'a, TR: Read> Deserializer<'a, TR> {
@@ -1365,13 +1456,16 @@ mod crypto {
     use ring::aead;
     use ring::aead::{BoundKey, Nonce, NonceSequence, OpeningKey, SealingKey, UnboundKey, AES_256_GCM};
     use ring::error::Unspecified;
-    use std::fs::File;
     use std::io::{Error, ErrorKind, Read, Write};
+    use std::num::NonZeroU32;
     use std::path::Path;
 
     extern crate rand;
 
-    use crate::{Deserialize, Deserializer, SavefileError, Serialize, Serializer, WithSchema};
+    use crate::{
+        write_file_atomic, CompressionOptions, Deserialize, Deserializer, LoadOptions, SaveOptions, SavefileError,
+        Serialize, Serializer, WithSchema,
+    };
     use byteorder::WriteBytesExt;
     use byteorder::{LittleEndian, ReadBytesExt};
     use rand::rngs::OsRng;
@@ -1433,6 +1527,7 @@ mod crypto {
         writer: &'a mut dyn Write,
         buf: Vec<u8>,
         sealkey: SealingKey<RandomNonceSequence>,
+        aad: Vec<u8>,
         failed: bool,
     }
 
@@ -1444,6 +1539,7 @@ mod crypto {
         buf: Vec<u8>,
         offset: usize,
         openingkey: OpeningKey<RandomNonceSequence>,
+        aad: Vec<u8>,
     }
 
     impl<'a> CryptoReader<'a> {
@@ -1451,7 +1547,16 @@ mod crypto {
         /// 32 byte cryptographic key.
         /// Crypto is 256 bit AES GCM
         pub fn new(reader: &'a mut dyn Read, key_bytes: [u8; 32]) -> Result<CryptoReader<'a>, SavefileError> {
-            let unboundkey = UnboundKey::new(&AES_256_GCM, &key_bytes).unwrap();
+            CryptoReader::with_aad(reader, key_bytes, &[])
+        }
+        /// Like [CryptoReader::new], except the given additional authenticated data must
+        /// match the data given to [CryptoWriter::with_aad] when the data was encrypted.
+        pub fn with_aad(
+            reader: &'a mut dyn Read,
+            key_bytes: [u8; 32],
+            aad: &[u8],
+        ) -> Result<CryptoReader<'a>, SavefileError> {
+            let unboundkey = UnboundKey::new(&AES_256_GCM, &key_bytes).map_err(|_| SavefileError::CryptographyError)?;
 
             let nonce_sequence = RandomNonceSequence::deserialize(reader)?;
             let openingkey = OpeningKey::new(unboundkey, nonce_sequence);
@@ -1461,6 +1566,7 @@ mod crypto {
                 offset: 0,
                 buf: Vec::new(),
                 openingkey,
+                aad: aad.to_vec(),
             })
         }
     }
@@ -1478,7 +1584,17 @@ mod crypto {
         /// 32 byte cryptographic key.
         /// Crypto is 256 bit AES GCM
         pub fn new(writer: &'a mut dyn Write, key_bytes: [u8; 32]) -> Result<CryptoWriter<'a>, SavefileError> {
-            let unboundkey = UnboundKey::new(&AES_256_GCM, &key_bytes).unwrap();
+            CryptoWriter::with_aad(writer, key_bytes, &[])
+        }
+        /// Like [CryptoWriter::new], except the given additional authenticated data is bound
+        /// into the authentication tag of each chunk. The data is not written to the output,
+        /// but the exact same data must be given to [CryptoReader::with_aad] when decrypting.
+        pub fn with_aad(
+            writer: &'a mut dyn Write,
+            key_bytes: [u8; 32],
+            aad: &[u8],
+        ) -> Result<CryptoWriter<'a>, SavefileError> {
+            let unboundkey = UnboundKey::new(&AES_256_GCM, &key_bytes).map_err(|_| SavefileError::CryptographyError)?;
             let nonce_sequence = RandomNonceSequence::new();
             nonce_sequence.serialize(writer)?;
             let sealkey = SealingKey::new(unboundkey, nonce_sequence);
@@ -1486,6 +1602,7 @@ mod crypto {
                 writer,
                 buf: Vec::new(),
                 sealkey,
+                aad: aad.to_vec(),
                 failed: false,
             })
         }
@@ -1556,7 +1673,7 @@ mod crypto {
 
                 match self
                     .openingkey
-                    .open_in_place(aead::Aad::empty(), &mut self.buf[orglen..])
+                    .open_in_place(aead::Aad::from(&self.aad[..]), &mut self.buf[orglen..])
                 {
                     Ok(_) => {}
                     Err(_) => {
@@ -1606,7 +1723,10 @@ mod crypto {
                 debug_assert!(expected_final_len <= CRYPTO_BUFSIZE as u64 + 16);
 
                 self.writer.write_u64::<LittleEndian>(expected_final_len)?; //16 for the tag
-                match self.sealkey.seal_in_place_append_tag(aead::Aad::empty(), curbuf) {
+                match self
+                    .sealkey
+                    .seal_in_place_append_tag(aead::Aad::from(&self.aad[..]), curbuf)
+                {
                     Ok(_) => {}
                     Err(_) => {
                         return Err(Error::new(ErrorKind::Other, "Cryptography error"));
@@ -1624,50 +1744,635 @@ mod crypto {
             Ok(())
         }
     }
-    /// Like [crate::save_file], except encrypts the data with AES256, using the SHA256 hash
-    /// of the password as key.
-    pub fn save_encrypted_file<T: WithSchema + Serialize, P: AsRef<Path>>(
-        filepath: P,
-        version: u32,
-        data: &T,
-        password: &str,
-    ) -> Result<(), SavefileError> {
+    /// Magic bytes starting encrypted files with a crypto header.
+    /// Files written by older versions of savefile start directly with the nonce.
+    const CRYPTO_HEADER_MAGIC: &[u8; 16] = b"savefile-crypto\0";
+    /// Version of the crypto header
+    const CRYPTO_HEADER_VERSION: u8 = 1;
+    /// Identifies a key given directly by the user, see [KeyProvider]
+    const KDF_NONE: u8 = 0;
+    /// Identifies PBKDF2-HMAC-SHA256 as the key derivation function
+    const KDF_PBKDF2_HMAC_SHA256: u8 = 1;
+    /// Identifies a random key, wrapped for one or more X25519 recipients
+    const KDF_X25519_RECIPIENTS: u8 = 2;
+    const SALT_LEN: usize = 16;
+    /// Size of a 32 byte key encrypted with AES-256-GCM, including the tag
+    const WRAPPED_KEY_LEN: usize = 48;
+
+    #[cfg(feature = "x25519")]
+    mod recipients;
+    #[cfg(feature = "x25519")]
+    pub use recipients::{
+        load_encrypted_as_recipient, save_encrypted_for_recipients, RecipientPublicKey, RecipientSecretKey,
+    };
+
+    /// Parameters for deriving the encryption key from a password, for use with
+    /// [crate::save_encrypted_file_with_kdf].
+    ///
+    /// The key is derived using PBKDF2-HMAC-SHA256, with a random salt. The parameters
+    /// and the salt are stored in the file, so only the password is needed to load it.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct KdfParams {
+        /// Number of PBKDF2 iterations. Higher values make brute forcing the password
+        /// slower, but also make saving and loading slower.
+        ///
+        /// At most [KdfParams::MAX_ITERATIONS].
+        pub iterations: u32,
+    }
+
+    impl KdfParams {
+        /// The largest supported number of iterations, 10 times the default.
+        ///
+        /// Files claiming more iterations are rejected without deriving the key, since
+        /// a crafted file could otherwise make loading take hours before failing.
+        pub const MAX_ITERATIONS: u32 = 6_000_000;
+    }
+
+    impl Default for KdfParams {
+        fn default() -> Self {
+            KdfParams { iterations: 600_000 }
+        }
+    }
+
+    /// Supplies the keys used by [crate::save_encrypted] and [crate::load_encrypted].
+    ///
+    /// This allows keys to be managed externally, for example by a key management service.
+    /// Each key has an id, which is stored unencrypted with the data, so that the right key
+    /// can be found when loading, even after the key used for saving has been rotated.
+    ///
+    /// A plain 32 byte key implements this trait, using an empty key id.
+    pub trait KeyProvider {
+        /// The key to encrypt with, and its id. The id may be at most 65535 bytes.
+        fn encryption_key(&self) -> Result<(Vec<u8>, [u8; 32]), SavefileError>;
+        /// The key with the given id
+        fn decryption_key(&self, key_id: &[u8]) -> Result<[u8; 32], SavefileError>;
+    }
+
+    impl KeyProvider for [u8; 32] {
+        fn encryption_key(&self) -> Result<(Vec<u8>, [u8; 32]), SavefileError> {
+            Ok((Vec::new(), *self))
+        }
+        fn decryption_key(&self, key_id: &[u8]) -> Result<[u8; 32], SavefileError> {
+            if !key_id.is_empty() {
+                return Err(SavefileError::GeneralError {
+                    msg: "Data was encrypted using a key with an id, but no id was given.".into(),
+                });
+            }
+            Ok(*self)
+        }
+    }
+
+    /// How the key of an encrypted file is obtained
+    enum KeySource {
+        /// Given by the user, see [KeyProvider]
+        Raw { key_id: Vec<u8> },
+        /// Derived from a password
+        Pbkdf2 {
+            iterations: NonZeroU32,
+            salt: [u8; SALT_LEN],
+        },
+        /// Random, wrapped for each recipient using a key agreed with an ephemeral X25519 key
+        Recipients {
+            ephemeral_public: [u8; 32],
+            wrapped_keys: Vec<[u8; WRAPPED_KEY_LEN]>,
+        },
+    }
+
+    impl KeySource {
+        /// Error for data which was encrypted differently than the caller expected
+        fn mismatch_error(&self) -> SavefileError {
+            let (how, function) = match self {
+                KeySource::Raw { .. } => ("a key", "load_encrypted"),
+                KeySource::Pbkdf2 { .. } => ("a password", "load_encrypted_file"),
+                KeySource::Recipients { .. } => ("public keys", "load_encrypted_as_recipient"),
+            };
+            SavefileError::GeneralError {
+                msg: format!("Data was encrypted using {}. Use {} to load it.", how, function),
+            }
+        }
+    }
+
+    /// The crypto header of an encrypted file
+    struct CryptoHeader {
+        key_source: KeySource,
+        /// Allows detecting a wrong password or key before decrypting anything
+        key_check: [u8; 32],
+    }
+
+    impl CryptoHeader {
+        fn serialize(&self, writer: &mut dyn Write) -> Result<(), SavefileError> {
+            writer.write_all(CRYPTO_HEADER_MAGIC)?;
+            writer.write_u8(CRYPTO_HEADER_VERSION)?;
+            match &self.key_source {
+                KeySource::Raw { key_id } => {
+                    let len = u16::try_from(key_id.len()).map_err(|_| SavefileError::GeneralError {
+                        msg: "Encryption key id is longer than 65535 bytes.".into(),
+                    })?;
+                    writer.write_u8(KDF_NONE)?;
+                    writer.write_u16::<LittleEndian>(len)?;
+                    writer.write_all(key_id)?;
+                }
+                KeySource::Pbkdf2 { iterations, salt } => {
+                    writer.write_u8(KDF_PBKDF2_HMAC_SHA256)?;
+                    writer.write_u32::<LittleEndian>(iterations.get())?;
+                    writer.write_all(salt)?;
+                }
+                KeySource::Recipients {
+                    ephemeral_public,
+                    wrapped_keys,
+                } => {
+                    let count = u16::try_from(wrapped_keys.len()).map_err(|_| SavefileError::GeneralError {
+                        msg: "Data cannot be encrypted for more than 65535 recipients.".into(),
+                    })?;
+                    writer.write_u8(KDF_X25519_RECIPIENTS)?;
+                    writer.write_all(ephemeral_public)?;
+                    writer.write_u16::<LittleEndian>(count)?;
+                    for wrapped_key in wrapped_keys {
+                        writer.write_all(wrapped_key)?;
+                    }
+                }
+            }
+            writer.write_all(&self.key_check)?;
+            Ok(())
+        }
+        /// Deserialize the header, which must follow immediately after the magic
+        fn deserialize_after_magic(reader: &mut dyn Read) -> Result<CryptoHeader, SavefileError> {
+            let version = reader.read_u8()?;
+            if version != CRYPTO_HEADER_VERSION {
+                return Err(SavefileError::GeneralError {
+                    msg: format!("Unsupported encrypted file header version {}.", version),
+                });
+            }
+            let key_source = match reader.read_u8()? {
+                KDF_NONE => {
+                    let len = reader.read_u16::<LittleEndian>()?;
+                    let mut key_id = vec![0u8; len as usize];
+                    reader.read_exact(&mut key_id)?;
+                    KeySource::Raw { key_id }
+                }
+                KDF_PBKDF2_HMAC_SHA256 => {
+                    let iterations = NonZeroU32::new(reader.read_u32::<LittleEndian>()?).ok_or_else(|| {
+                        SavefileError::GeneralError {
+                            msg: "Invalid key derivation iteration count 0.".into(),
+                        }
+                    })?;
+                    if iterations.get() > KdfParams::MAX_ITERATIONS {
+                        return Err(SavefileError::GeneralError {
+                            msg: format!(
+                                "Key derivation iteration count {} exceeds the maximum of {}.",
+                                iterations,
+                                KdfParams::MAX_ITERATIONS
+                            ),
+                        });
+                    }
+                    let mut salt = [0u8; SALT_LEN];
+                    reader.read_exact(&mut salt)?;
+                    KeySource::Pbkdf2 { iterations, salt }
+                }
+                KDF_X25519_RECIPIENTS => {
+                    let mut ephemeral_public = [0u8; 32];
+                    reader.read_exact(&mut ephemeral_public)?;
+                    let count = reader.read_u16::<LittleEndian>()?;
+                    let mut wrapped_keys = Vec::with_capacity(count as usize);
+                    for _ in 0..count {
+                        let mut wrapped_key = [0u8; WRAPPED_KEY_LEN];
+                        reader.read_exact(&mut wrapped_key)?;
+                        wrapped_keys.push(wrapped_key);
+                    }
+                    KeySource::Recipients {
+                        ephemeral_public,
+                        wrapped_keys,
+                    }
+                }
+                kdf => {
+                    return Err(SavefileError::GeneralError {
+                        msg: format!("Unsupported key derivation function {}.", kdf),
+                    });
+                }
+            };
+            let mut key_check = [0u8; 32];
+            reader.read_exact(&mut key_check)?;
+            Ok(CryptoHeader { key_source, key_check })
+        }
+        /// Check that `key` is the key the data was encrypted with
+        fn verify_key(&self, key: &[u8; 32]) -> Result<(), SavefileError> {
+            if key_check(key) != self.key_check {
+                return Err(SavefileError::WrongPassword);
+            }
+            Ok(())
+        }
+    }
+
+    /// Read the crypto header magic, if present. Returns the bytes read, which are
+    /// the start of the encrypted data if the magic was not found.
+    fn read_magic(reader: &mut dyn Read) -> Result<(bool, [u8; 16], usize), SavefileError> {
+        let mut magic = [0u8; CRYPTO_HEADER_MAGIC.len()];
+        let mut got = 0;
+        while got < magic.len() {
+            match reader.read(&mut magic[got..])? {
+                0 => break,
+                n => got += n,
+            }
+        }
+        Ok((&magic[..got] == CRYPTO_HEADER_MAGIC, magic, got))
+    }
+
+    fn derive_key(password: &str, salt: &[u8], iterations: NonZeroU32) -> [u8; 32] {
+        let mut key = [0u8; 32];
+        ring::pbkdf2::derive(
+            ring::pbkdf2::PBKDF2_HMAC_SHA256,
+            iterations,
+            salt,
+            password.as_bytes(),
+            &mut key,
+        );
+        key
+    }
+
+    fn key_check(key: &[u8; 32]) -> [u8; 32] {
+        use ring::digest;
+        let mut context = digest::Context::new(&digest::SHA256);
+        context.update(b"savefile key check");
+        context.update(key);
+        let mut check = [0u8; 32];
+        check.clone_from_slice(context.finish().as_ref());
+        check
+    }
+
+    /// The key derivation used by files written by older versions of savefile
+    fn legacy_key(password: &str) -> [u8; 32] {
         use ring::digest;
         let actual = digest::digest(&digest::SHA256, password.as_bytes());
         let mut key = [0u8; 32];
         let password_hash = actual.as_ref();
         assert_eq!(password_hash.len(), key.len(), "A SHA256 sum must be 32 bytes");
         key.clone_from_slice(password_hash);
+        key
+    }
+
+    /// Like [crate::save_file], except encrypts the data with AES256, using a key derived
+    /// from the password using PBKDF2, with default parameters. See [KdfParams].
+    pub fn save_encrypted_file<T: WithSchema + Serialize, P: AsRef<Path>>(
+        filepath: P,
+        version: u32,
+        data: &T,
+        password: &str,
+    ) -> Result<(), SavefileError> {
+        save_encrypted_file_with_kdf(filepath, version, data, password, KdfParams::default())
+    }
+
+    /// Like [crate::save_encrypted_file], except the key is derived using the given parameters.
+    pub fn save_encrypted_file_with_kdf<T: WithSchema + Serialize, P: AsRef<Path>>(
+        filepath: P,
+        version: u32,
+        data: &T,
+        password: &str,
+        kdf: KdfParams,
+    ) -> Result<(), SavefileError> {
+        SaveOptions::new(version)
+            .with_compression(CompressionOptions::default())
+            .with_password(password, kdf)
+            .save_file(filepath, data)
+    }
+
+    /// Like [crate::save_encrypted_file], except the file is replaced atomically.
+    /// See [crate::write_file_atomic].
+    /// If `keep_backup` is true, the previous file is kept as `<filepath>.bak`.
+    pub fn save_encrypted_file_atomic<T: WithSchema + Serialize, P: AsRef<Path>>(
+        filepath: P,
+        version: u32,
+        data: &T,
+        password: &str,
+        keep_backup: bool,
+    ) -> Result<(), SavefileError> {
+        write_file_atomic(filepath, keep_backup, |f| {
+            save_encrypted_with_password(f, version, data, password, KdfParams::default())
+        })
+    }
 
-        let mut f = File::create(filepath)?;
-        let mut writer = CryptoWriter::new(&mut f, key)?;
+    fn save_encrypted_with_password<T: WithSchema + Serialize>(
+        mut f: &mut dyn Write,
+        version: u32,
+        data: &T,
+        password: &str,
+        kdf: KdfParams,
+    ) -> Result<(), SavefileError> {
+        SaveOptions::new(version)
+            .with_compression(CompressionOptions::default())
+            .with_password(password, kdf)
+            .save(&mut f, data)
+    }
 
-        Serializer::<CryptoWriter>::save::<T>(&mut writer, version, data, true)?;
+    /// Write the crypto header for a key derived from `password`, and call `write` with
+    /// a writer encrypting the rest.
+    pub(crate) fn write_with_password(
+        f: &mut dyn Write,
+        password: &str,
+        kdf: KdfParams,
+        write: impl FnOnce(&mut dyn Write) -> Result<(), SavefileError>,
+    ) -> Result<(), SavefileError> {
+        let key = write_password_header(f, password, kdf)?;
+        let mut writer = CryptoWriter::new(f, key)?;
+        write(&mut writer)?;
         writer.flush()?;
         Ok(())
     }
 
+    /// Write the crypto header for a key derived from `password` with a random salt.
+    /// Returns the key.
+    fn write_password_header(f: &mut dyn Write, password: &str, kdf: KdfParams) -> Result<[u8; 32], SavefileError> {
+        let iterations = NonZeroU32::new(kdf.iterations).ok_or_else(|| SavefileError::GeneralError {
+            msg: "The key derivation iteration count must not be 0.".into(),
+        })?;
+        if kdf.iterations > KdfParams::MAX_ITERATIONS {
+            return Err(SavefileError::GeneralError {
+                msg: format!(
+                    "The key derivation iteration count must not exceed {}.",
+                    KdfParams::MAX_ITERATIONS
+                ),
+            });
+        }
+        let mut salt = [0u8; SALT_LEN];
+        OsRng.fill_bytes(&mut salt);
+        let key = derive_key(password, &salt, iterations);
+        CryptoHeader {
+            key_source: KeySource::Pbkdf2 { iterations, salt },
+            key_check: key_check(&key),
+        }
+        .serialize(f)?;
+        Ok(key)
+    }
+
     /// Like [crate::load_file], except it expects the file to be an encrypted file previously stored using
-    /// [crate::save_encrypted_file].
+    /// [crate::save_encrypted_file]. Files written by older versions of savefile, which did
+    /// not use a salted key derivation, can also be loaded.
+    ///
+    /// Returns [SavefileError::WrongPassword] if the password is not correct.
     pub fn load_encrypted_file<T: WithSchema + Deserialize, P: AsRef<Path>>(
         filepath: P,
         version: u32,
         password: &str,
     ) -> Result<T, SavefileError> {
-        use ring::digest;
-        let actual = digest::digest(&digest::SHA256, password.as_bytes());
-        let mut key = [0u8; 32];
-        let password_hash = actual.as_ref();
-        assert_eq!(password_hash.len(), key.len(), "A SHA256 sum must be 32 bytes");
-        key.clone_from_slice(password_hash);
+        LoadOptions::new(version).with_password(password).load_file(filepath)
+    }
+
+    /// Read the crypto header of data encrypted using a password, and call `read` with
+    /// a reader decrypting the rest. Also handles data written by older versions of savefile.
+    pub(crate) fn read_with_password<R>(
+        f: &mut dyn Read,
+        password: &str,
+        read: impl FnOnce(&mut dyn Read) -> Result<R, SavefileError>,
+    ) -> Result<R, SavefileError> {
+        let (has_header, magic, got) = read_magic(f)?;
+        if has_header {
+            let header = CryptoHeader::deserialize_after_magic(f)?;
+            let key = match &header.key_source {
+                KeySource::Pbkdf2 { iterations, salt } => derive_key(password, salt, *iterations),
+                key_source => return Err(key_source.mismatch_error()),
+            };
+            header.verify_key(&key)?;
+            let mut reader = CryptoReader::new(f, key)?;
+            read(&mut reader)
+        } else {
+            // Old file, without key derivation header
+            let mut f = (&magic[..got]).chain(f);
+            let mut reader = CryptoReader::new(&mut f, legacy_key(password))?;
+            // Old files have no key check value. Decrypting the first chunk with the wrong key
+            // fails, which is the best indication of a wrong password available.
+            let mut first = [0u8; 1];
+            let first_len = match reader.read(&mut first) {
+                Ok(first_len) => first_len,
+                Err(err) if err.kind() == ErrorKind::Other => return Err(SavefileError::WrongPassword),
+                Err(err) => return Err(err.into()),
+            };
+            let mut reader = (&first[..first_len]).chain(reader);
+            read(&mut reader)
+        }
+    }
+
+    /// Encrypt a complete savefile read from `reader`, writing it to `writer` in the format
+    /// used by [crate::save_encrypted_file_with_kdf]. The result can be loaded using
+    /// [crate::load_encrypted_file].
+    ///
+    /// This works for any savefile, without knowing the type of the data in it.
+    pub fn encrypt_savefile(
+        reader: &mut impl Read,
+        writer: &mut impl Write,
+        password: &str,
+        kdf: KdfParams,
+    ) -> Result<(), SavefileError> {
+        let mut head = [0u8; 9];
+        reader.read_exact(&mut head)?;
+        if &head != b"savefile\0" {
+            return Err(SavefileError::GeneralError {
+                msg: "File is not in new savefile-format.".into(),
+            });
+        }
+        let key = write_password_header(writer, password, kdf)?;
+        let mut writer = CryptoWriter::new(writer, key)?;
+        writer.write_all(&head)?;
+        std::io::copy(reader, &mut writer)?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Decrypt data previously encrypted using [crate::save_encrypted_file] or
+    /// [crate::encrypt_savefile], writing the plain savefile to `writer`.
+    ///
+    /// Returns [SavefileError::WrongPassword] if the password is not correct.
+    pub fn decrypt_savefile(
+        reader: &mut impl Read,
+        writer: &mut impl Write,
+        password: &str,
+    ) -> Result<(), SavefileError> {
+        read_with_password(reader, password, |reader| {
+            std::io::copy(reader, writer)?;
+            Ok(())
+        })
+    }
+
+    /// Write the given `data` to `writer`, encrypted with AES256 using the key from `keys`.
+    /// The current version of data must be `version`.
+    ///
+    /// `aad` is additional authenticated data, which is not stored, but which is bound into
+    /// the authentication of the encrypted data. Loading the data using [crate::load_encrypted]
+    /// requires the same `aad`. This can be used to tie the data to its context, for example
+    /// to the id of the user owning it. Use an empty slice if not needed.
+    ///
+    /// The data is not compressed.
+    pub fn save_encrypted<T: WithSchema + Serialize>(
+        writer: &mut impl Write,
+        version: u32,
+        data: &T,
+        keys: &impl KeyProvider,
+        aad: &[u8],
+    ) -> Result<(), SavefileError> {
+        let (key_id, key) = keys.encryption_key()?;
+        save_with_header(writer, KeySource::Raw { key_id }, key, aad, version, data)
+    }
+
+    /// Write the crypto header, followed by the encrypted, uncompressed, data
+    fn save_with_header<T: WithSchema + Serialize>(
+        writer: &mut dyn Write,
+        key_source: KeySource,
+        key: [u8; 32],
+        aad: &[u8],
+        version: u32,
+        data: &T,
+    ) -> Result<(), SavefileError> {
+        CryptoHeader {
+            key_source,
+            key_check: key_check(&key),
+        }
+        .serialize(writer)?;
+
+        let mut writer = CryptoWriter::with_aad(writer, key, aad)?;
+        Serializer::<CryptoWriter>::save::<T>(&mut writer, version, data, false)?;
+        writer.flush()?;
+        Ok(())
+    }
 
-        let mut f = File::open(filepath)?;
-        let mut reader = CryptoReader::new(&mut f, key).unwrap();
+    /// Read the crypto header. `key` is called to obtain the key from the header.
+    /// Then loads the encrypted data.
+    fn load_with_header<T: WithSchema + Deserialize>(
+        reader: &mut dyn Read,
+        version: u32,
+        aad: &[u8],
+        key: impl FnOnce(&KeySource) -> Result<[u8; 32], SavefileError>,
+    ) -> Result<T, SavefileError> {
+        let (has_header, _, _) = read_magic(reader)?;
+        if !has_header {
+            return Err(SavefileError::GeneralError {
+                msg: "Data is not encrypted using savefile.".into(),
+            });
+        }
+        let header = CryptoHeader::deserialize_after_magic(reader)?;
+        let key = key(&header.key_source)?;
+        header.verify_key(&key)?;
+        let mut reader = CryptoReader::with_aad(reader, key, aad)?;
         Deserializer::<CryptoReader>::load::<T>(&mut reader, version)
     }
+
+    /// Like [crate::save_encrypted], except the encrypted data is returned as a `Vec<u8>`.
+    pub fn save_encrypted_to_mem<T: WithSchema + Serialize>(
+        version: u32,
+        data: &T,
+        keys: &impl KeyProvider,
+        aad: &[u8],
+    ) -> Result<Vec<u8>, SavefileError> {
+        let mut retval = Vec::new();
+        save_encrypted(&mut retval, version, data, keys, aad)?;
+        Ok(retval)
+    }
+
+    /// Load an object of type T, previously saved using [crate::save_encrypted], from `reader`.
+    /// The current version of T in memory must be `version`.
+    ///
+    /// The key is obtained from `keys`, using the key id stored with the data. `aad` must be
+    /// the additional authenticated data given when saving.
+    ///
+    /// Returns [SavefileError::WrongPassword] if the key is not the one used when saving,
+    /// and [SavefileError::IOError] if the data (or `aad`) fails authentication.
+    pub fn load_encrypted<T: WithSchema + Deserialize>(
+        reader: &mut impl Read,
+        version: u32,
+        keys: &impl KeyProvider,
+        aad: &[u8],
+    ) -> Result<T, SavefileError> {
+        load_with_header(reader, version, aad, |key_source| match key_source {
+            KeySource::Raw { key_id } => keys.decryption_key(key_id),
+            key_source => Err(key_source.mismatch_error()),
+        })
+    }
+
+    /// Like [crate::load_encrypted], except the encrypted data is read from memory.
+    pub fn load_encrypted_from_mem<T: WithSchema + Deserialize>(
+        mut input: &[u8],
+        version: u32,
+        keys: &impl KeyProvider,
+        aad: &[u8],
+    ) -> Result<T, SavefileError> {
+        load_encrypted(&mut input, version, keys, aad)
+    }
 }
 #[cfg(feature = "ring")]
-pub use crypto::{load_encrypted_file, save_encrypted_file, CryptoReader, CryptoWriter};
+pub use crypto::{
+    decrypt_savefile, encrypt_savefile, load_encrypted, load_encrypted_file, load_encrypted_from_mem, save_encrypted,
+    save_encrypted_file, save_encrypted_file_atomic, save_encrypted_file_with_kdf, save_encrypted_to_mem, CryptoReader,
+    CryptoWriter, KdfParams, KeyProvider,
+};
+#[cfg(feature = "x25519")]
+pub use crypto::{load_encrypted_as_recipient, save_encrypted_for_recipients, RecipientPublicKey, RecipientSecretKey};
+
+mod stream;
+pub use stream::{StreamReader, StreamWriter};
+
+mod archive;
+pub use archive::{ArchiveEntry, ArchiveReader, ArchiveWriter};
+
+mod compression;
+use compression::{read_compressed, write_compressed};
+pub use compression::{recompress, CompressionCodec, CompressionOptions};
+
+mod checksum;
+pub use checksum::ChecksumAlgorithm;
+use checksum::{ChecksumReader, ChecksumWriter, CHECKSUM_FLAG};
+
+mod header;
+use header::PayloadHeader;
+pub use header::{inspect, inspect_file, read_header, FileHeader, FileInfo, FileMetadata};
+
+mod schema_diff;
+pub use schema_diff::{SchemaDiff, SchemaDifference, SchemaDifferenceKind, SchemaPathElement};
+
+mod schema_history;
+pub use schema_history::verify_schema_history;
+
+mod golden;
+pub use golden::{load_golden_files, verify_golden_files};
+
+mod atomic;
+pub use atomic::{save_file_atomic, save_file_compressed_atomic, write_file_atomic};
+
+mod value;
+pub use value::{load_dynamic, load_file_dynamic, save_dynamic, save_file_dynamic, DynamicFile, Value};
+
+mod lenient;
+pub use lenient::{load_file_forward_compatible, load_file_lenient, load_forward_compatible, load_lenient};
+
+mod projection;
+pub use projection::{
+    load_file_projection, load_file_projection_with_limits, load_projection, load_projection_with_limits,
+};
+
+mod lazy;
+pub use lazy::{load_file_lazy, load_file_lazy_with_limits, load_lazy, load_lazy_with_limits, Lazy};
+
+mod limits;
+pub use limits::{
+    load_file_with_limits, load_from_mem_with_limits, load_with_limits, LimitKind, LimitState, LoadLimits,
+};
+
+mod options;
+pub use options::{LoadOptions, SaveOptions};
+
+#[cfg(feature = "json")]
+mod json;
+#[cfg(feature = "json")]
+pub use json::{load_as_json, save_from_json};
+
+#[cfg(feature = "ring")]
+mod signing;
+#[cfg(feature = "ring")]
+pub use signing::{
+    load_file_signed, load_signed, save_file_signed, save_signed, save_signed_compressed_with, SigningKeyPair,
+    SigningPublicKey,
+};
+
+#[cfg(feature = "tokio")]
+mod async_io;
+#[cfg(feature = "tokio")]
+pub use async_io::{load_async, save_async, save_compressed_async};
 
 impl<'a, W: Write + 'a> Serializer<'a, W> {
     /// Writes a binary bool to the output
@@ -1831,13 +2536,15 @@ impl<'a, W: Write + 'a> Serializer<'a, W> {
             version,
             data,
             Some(T::schema(version, &mut WithSchemaContext::new())),
-            with_compression,
+            with_compression.then(CompressionOptions::default),
+            None,
+            None,
         )?)
     }
     /// Creata a new serializer.
     /// Don't use this function directly, use the [crate::save_noschema] function instead.
     pub fn save_noschema<T: Serialize>(writer: &mut W, version: u32, data: &T) -> Result<(), SavefileError> {
-        Ok(Self::save_impl(writer, version, data, None, false)?)
+        Ok(Self::save_impl(writer, version, data, None, None, None, None)?)
     }
 
     /// Serialize without any header. Using this means that bare_deserialize must be used to
@@ -1855,58 +2562,38 @@ impl<'a, W: Write + 'a> Serializer<'a, W> {
         version: u32,
         data: &T,
         with_schema: Option<Schema>,
-        with_compression: bool,
+        compression: Option<CompressionOptions>,
+        checksum: Option<ChecksumAlgorithm>,
+        metadata: Option<&FileMetadata>,
     ) -> Result<(), SavefileError> {
-        let header = "savefile\0".to_string().into_bytes();
-
-        writer.write_all(&header)?; //9
-
-        writer.write_u16::<LittleEndian>(CURRENT_SAVEFILE_LIB_VERSION /*savefile format version*/)?;
-        writer.write_u32::<LittleEndian>(version)?;
-        // 9 + 2 + 4 = 15
-        {
-            if with_compression {
-                writer.write_u8(1)?; //15 + 1 = 16
-
-                #[cfg(feature = "bzip2")]
-                {
-                    let mut compressed_writer = bzip2::write::BzEncoder::new(writer, Compression::best());
-                    if let Some(schema) = with_schema {
-                        let mut schema_serializer = Serializer::<bzip2::write::BzEncoder<W>>::new_raw(
-                            &mut compressed_writer,
-                            CURRENT_SAVEFILE_LIB_VERSION as u32,
-                        );
-                        schema.serialize(&mut schema_serializer)?;
-                    }
-
-                    let mut serializer = Serializer {
-                        writer: &mut compressed_writer,
-                        file_version: version,
-                    }; //Savefile always serializes most recent version. Only savefile-abi ever writes old formats.
-                    data.serialize(&mut serializer)?;
-                    compressed_writer.flush()?;
-                    return Ok(());
-                }
-                #[cfg(not(feature = "bzip2"))]
-                {
-                    return Err(SavefileError::CompressionSupportNotCompiledIn);
-                }
-            } else {
-                writer.write_u8(0)?;
-                if let Some(schema) = with_schema {
-                    let mut schema_serializer = Serializer::<W>::new_raw(writer, CURRENT_SAVEFILE_LIB_VERSION as u32);
-                    schema.serialize(&mut schema_serializer)?;
-                }
+        let savefile_lib_version = PayloadHeader::required_savefile_lib_version(
+            compression.map(|x| x.codec),
+            checksum.is_some(),
+            metadata.is_some(),
+        );
+        write_file_header(writer, savefile_lib_version, version)?;
+        Self::save_payload(writer, version, data, with_schema, compression, checksum, metadata)
+    }
 
-                let mut serializer = Serializer {
-                    writer,
-                    file_version: version,
-                };
-                data.serialize(&mut serializer)?;
-                writer.flush()?;
-                Ok(())
-            }
+    /// Write everything following the fixed header: the compression byte, the
+    /// checksum type (if any), the metadata (if any), the schema (if any), and the data itself.
+    pub(crate) fn save_payload<T: Serialize>(
+        writer: &mut W,
+        version: u32,
+        data: &T,
+        with_schema: Option<Schema>,
+        compression: Option<CompressionOptions>,
+        checksum: Option<ChecksumAlgorithm>,
+        metadata: Option<&FileMetadata>,
+    ) -> Result<(), SavefileError> {
+        PayloadHeader::new(compression.map(|x| x.codec), checksum, metadata)?.write(writer)?; //15 + 1 = 16
+        if let Some(compression) = compression {
+            write_compressed(writer, compression, version, data, with_schema, checksum)?;
+        } else {
+            write_schema_and_data(writer, version, data, with_schema, checksum)?;
         }
+        writer.flush()?;
+        Ok(())
     }
 
     /// Create a Serializer.
@@ -2036,6 +2723,7 @@ impl<'a, TR: Read> Deserializer<'a, TR> {
                 });
             }
         }
+        self.check_string_len(l)?;
         let mut v = vec![0; l];
         self.reader.read_exact(&mut v)?;
         Ok(String::from_utf8(v)?)
@@ -2043,6 +2731,7 @@ impl<'a, TR: Read> Deserializer<'a, TR> {
 
     /// Reads 'len' raw u8 bytes as a `Vec<u8>`
     pub fn read_bytes(&mut self, len: usize) -> Result<Vec<u8>, SavefileError> {
+        self.check_collection_len(len, 1)?;
         let mut v = vec![0; len];
         self.reader.read_exact(&mut v)?;
         Ok(v)
@@ -2079,6 +2768,7 @@ impl<'a, TR: Read> Deserializer<'a, TR> {
             reader,
             file_version,
             ephemeral_state: HashMap::new(),
+            limit_state: None,
         };
         Ok(T::deserialize(&mut deserializer)?)
     }
@@ -2089,85 +2779,291 @@ impl<'a, TR: Read> Deserializer<'a, TR> {
         version: u32,
         expected_schema: Option<impl FnOnce(u32) -> Schema>,
     ) -> Result<T, SavefileError> {
-        let mut head: [u8; 9] = [0u8; 9];
-        reader.read_exact(&mut head)?;
+        let (savefile_lib_version, file_ver) = read_file_header(reader, version)?;
+        Self::load_payload(reader, savefile_lib_version, file_ver, expected_schema)
+    }
 
-        if head[..] != ("savefile\0".to_string().into_bytes())[..] {
-            return Err(SavefileError::GeneralError {
-                msg: "File is not in new savefile-format.".into(),
-            });
+    /// Read everything following the fixed header: the compression byte, the
+    /// checksum type (if any), the schema (if any), and the data itself.
+    pub(crate) fn load_payload<T: Deserialize>(
+        reader: &mut TR,
+        savefile_lib_version: u16,
+        file_ver: u32,
+        expected_schema: Option<impl FnOnce(u32) -> Schema>,
+    ) -> Result<T, SavefileError> {
+        let loader = TypedLoader {
+            expected_schema,
+            phantom: PhantomData,
+        };
+        Self::load_payload_with(reader, savefile_lib_version, file_ver, loader)
+    }
+
+    /// Like [Deserializer::load_payload], but the schema and data are read by the given loader.
+    pub(crate) fn load_payload_with<L: PayloadLoader>(
+        reader: &mut TR,
+        savefile_lib_version: u16,
+        file_ver: u32,
+        loader: L,
+    ) -> Result<L::Output, SavefileError> {
+        Self::load_payload_from(reader, savefile_lib_version, file_ver, loader, false)
+    }
+
+    /// Like [Deserializer::load_payload_with]. `whole_input` is true if the data is known to
+    /// extend to the end of `reader`, as when loading a file or a buffer.
+    ///
+    /// If loading a file with a checksum fails, the rest of the input is only read to verify
+    /// the checksum if `whole_input` is true. Otherwise, the reader may be a socket which is
+    /// not closed, or have more data after the checksum, so the load error is returned as is.
+    pub(crate) fn load_payload_from<L: PayloadLoader>(
+        reader: &mut TR,
+        savefile_lib_version: u16,
+        file_ver: u32,
+        loader: L,
+        whole_input: bool,
+    ) -> Result<L::Output, SavefileError> {
+        let header = PayloadHeader::read(reader, false)?;
+        if let Some(codec) = header.compression {
+            read_compressed(
+                reader,
+                codec,
+                savefile_lib_version,
+                file_ver,
+                loader,
+                header.checksum,
+                whole_input,
+            )
+        } else {
+            read_schema_and_data(
+                reader,
+                savefile_lib_version,
+                file_ver,
+                loader,
+                header.checksum,
+                whole_input,
+            )
         }
+    }
+}
 
-        let savefile_lib_version = reader.read_u16::<LittleEndian>()?;
-        if savefile_lib_version > CURRENT_SAVEFILE_LIB_VERSION {
-            return Err(SavefileError::GeneralError {
-                msg: "This file has been created by a future, incompatible version of the savefile crate.".into(),
-            });
+impl<'a, 'de> Deserializer<'a, &'de [u8]> {
+    /// Reads 'len' raw u8 bytes, returning a slice borrowed from the input buffer.
+    pub fn read_borrowed_bytes(&mut self, len: usize) -> Result<&'de [u8], SavefileError> {
+        let input: &'de [u8] = self.reader;
+        if len > input.len() {
+            return Err(SavefileError::ShortRead);
         }
-        let file_ver = reader.read_u32::<LittleEndian>()?;
+        let (head, tail) = input.split_at(len);
+        *self.reader = tail;
+        Ok(head)
+    }
+    /// Reads a 64 bit length followed by an utf8 encoded string, returning
+    /// a string borrowed from the input buffer. Fails if data is not valid utf8
+    pub fn read_borrowed_str(&mut self) -> Result<&'de str, SavefileError> {
+        let l = self.read_usize()?;
+        Ok(std::str::from_utf8(self.read_borrowed_bytes(l)?)?)
+    }
+}
 
-        if file_ver > version {
-            return Err(SavefileError::WrongVersion {
-                msg: format!(
-                    "File has later version ({}) than structs in memory ({}).",
-                    file_ver, version
-                ),
-            });
+/// Write the fixed part of the savefile header: the magic "savefile\0",
+/// the savefile library version and the file version.
+/// The compression byte, schema and data follow.
+pub(crate) fn write_file_header(
+    writer: &mut impl Write,
+    savefile_lib_version: u16,
+    version: u32,
+) -> Result<(), SavefileError> {
+    let header = "savefile\0".to_string().into_bytes();
+
+    writer.write_all(&header)?; //9
+
+    writer.write_u16::<LittleEndian>(savefile_lib_version /*savefile format version*/)?;
+    writer.write_u32::<LittleEndian>(version)?;
+    // 9 + 2 + 4 = 15
+    Ok(())
+}
+
+/// Read and validate the fixed part of the savefile header.
+/// Returns the savefile library version and the file version.
+/// Fails if the file version is later than `version`.
+pub(crate) fn read_file_header(reader: &mut impl Read, version: u32) -> Result<(u16, u32), SavefileError> {
+    let mut head: [u8; 9] = [0u8; 9];
+    reader.read_exact(&mut head)?;
+
+    if head[..] != ("savefile\0".to_string().into_bytes())[..] {
+        return Err(SavefileError::GeneralError {
+            msg: "File is not in new savefile-format.".into(),
+        });
+    }
+
+    let savefile_lib_version = reader.read_u16::<LittleEndian>()?;
+    if savefile_lib_version > EXTENDED_HEADER_SAVEFILE_LIB_VERSION {
+        return Err(SavefileError::GeneralError {
+            msg: "This file has been created by a future, incompatible version of the savefile crate.".into(),
+        });
+    }
+    let file_ver = reader.read_u32::<LittleEndian>()?;
+
+    if file_ver > version {
+        return Err(SavefileError::WrongVersion {
+            msg: format!(
+                "File has later version ({}) than structs in memory ({}).",
+                file_ver, version
+            ),
+        });
+    }
+    Ok((savefile_lib_version, file_ver))
+}
+
+/// Fail with [SavefileError::IncompatibleSchema] if the schema read from a file
+/// does not match the in-memory schema for the file's version.
+pub(crate) fn check_file_schema(
+    memory_schema: &Schema,
+    file_schema: &Schema,
+    file_ver: u32,
+) -> Result<(), SavefileError> {
+    if let Some(err) = diff_schema(memory_schema, file_schema, ".".to_string()) {
+        return Err(SavefileError::IncompatibleSchema {
+            message: format!(
+                "Saved schema differs from in-memory schema for version {}. Error: {}",
+                file_ver, err
+            ),
+        });
+    }
+    Ok(())
+}
+
+/// Write the schema (if any) followed by the data and the checksum (if any), after the
+/// compression byte. The writer may be compressing.
+pub(crate) fn write_schema_and_data<T: Serialize>(
+    writer: &mut impl Write,
+    version: u32,
+    data: &T,
+    with_schema: Option<Schema>,
+    checksum: Option<ChecksumAlgorithm>,
+) -> Result<(), SavefileError> {
+    if let Some(checksum) = checksum {
+        let mut checksum_writer = ChecksumWriter::new(writer, checksum)?;
+        write_unchecked_schema_and_data(&mut checksum_writer, version, data, with_schema)?;
+        return checksum_writer.write_checksum();
+    }
+    write_unchecked_schema_and_data(writer, version, data, with_schema)
+}
+
+fn write_unchecked_schema_and_data<T: Serialize>(
+    writer: &mut impl Write,
+    version: u32,
+    data: &T,
+    with_schema: Option<Schema>,
+) -> Result<(), SavefileError> {
+    if let Some(schema) = with_schema {
+        let mut schema_serializer = Serializer {
+            writer: &mut *writer,
+            file_version: CURRENT_SAVEFILE_LIB_VERSION as u32,
+        };
+        schema.serialize(&mut schema_serializer)?;
+    }
+    let mut serializer = Serializer {
+        writer,
+        file_version: version,
+    }; //Savefile always serializes most recent version. Only savefile-abi ever writes old formats.
+    data.serialize(&mut serializer)
+}
+
+/// Reads what follows the compression byte (and checksum type) of a file: the schema,
+/// if the file has one, and the data.
+pub(crate) trait PayloadLoader {
+    /// The loaded data
+    type Output;
+    /// True if the file starts with a schema
+    fn reads_schema(&self) -> bool;
+    /// Limits to enforce when reading the schema and the data
+    fn limits(&self) -> Option<LoadLimits> {
+        None
+    }
+    /// Read the data. `file_schema` is the schema read from the file, if [PayloadLoader::reads_schema].
+    fn load_data(
+        self,
+        deserializer: &mut Deserializer<impl Read>,
+        file_schema: Option<Schema>,
+    ) -> Result<Self::Output, SavefileError>;
+}
+
+/// Loads an instance of T, checking the file schema against the in-memory schema (if expected)
+pub(crate) struct TypedLoader<T, F> {
+    expected_schema: Option<F>,
+    phantom: PhantomData<T>,
+}
+
+impl<T: Deserialize, F: FnOnce(u32) -> Schema> PayloadLoader for TypedLoader<T, F> {
+    type Output = T;
+    fn reads_schema(&self) -> bool {
+        self.expected_schema.is_some()
+    }
+    fn load_data(
+        self,
+        deserializer: &mut Deserializer<impl Read>,
+        file_schema: Option<Schema>,
+    ) -> Result<T, SavefileError> {
+        if let (Some(memory_schema), Some(file_schema)) = (self.expected_schema, file_schema) {
+            let file_ver = deserializer.file_version;
+            check_file_schema(&memory_schema(file_ver), &file_schema, file_ver)?;
         }
-        let with_compression = reader.read_u8()? != 0;
+        T::deserialize(deserializer)
+    }
+}
 
-        if with_compression {
-            #[cfg(feature = "bzip2")]
-            {
-                let mut compressed_reader = bzip2::read::BzDecoder::new(reader);
-                if let Some(memory_schema) = expected_schema {
-                    let mut schema_deserializer = new_schema_deserializer(&mut compressed_reader, savefile_lib_version);
-                    let memory_schema = memory_schema(file_ver);
-                    let file_schema = Schema::deserialize(&mut schema_deserializer)?;
-
-                    if let Some(err) = diff_schema(&memory_schema, &file_schema, ".".to_string()) {
-                        return Err(SavefileError::IncompatibleSchema {
-                            message: format!(
-                                "Saved schema differs from in-memory schema for version {}. Error: {}",
-                                file_ver, err
-                            ),
-                        });
-                    }
-                }
-                let mut deserializer = Deserializer {
-                    reader: &mut compressed_reader,
-                    file_version: file_ver,
-                    ephemeral_state: HashMap::new(),
-                };
-                Ok(T::deserialize(&mut deserializer)?)
-            }
-            #[cfg(not(feature = "bzip2"))]
-            {
-                return Err(SavefileError::CompressionSupportNotCompiledIn);
-            }
-        } else {
-            if let Some(memory_schema) = expected_schema {
-                let mut schema_deserializer = new_schema_deserializer(reader, savefile_lib_version);
-                let memory_schema = memory_schema(file_ver);
-                let file_schema = Schema::deserialize(&mut schema_deserializer)?;
-
-                if let Some(err) = diff_schema(&memory_schema, &file_schema, ".".to_string()) {
-                    return Err(SavefileError::IncompatibleSchema {
-                        message: format!(
-                            "Saved schema differs from in-memory schema for version {}. Error: {}",
-                            file_ver, err
-                        ),
-                    });
-                }
+/// Read the schema (if any), followed by the data, after the compression byte, using `loader`.
+/// If the file has a checksum, it is verified. The reader may be decompressing.
+///
+/// `whole_input` is true if the data extends to the end of `reader`, see
+/// [Deserializer::load_payload_from].
+pub(crate) fn read_schema_and_data<L: PayloadLoader>(
+    reader: &mut impl Read,
+    savefile_lib_version: u16,
+    file_ver: u32,
+    loader: L,
+    checksum: Option<ChecksumAlgorithm>,
+    whole_input: bool,
+) -> Result<L::Output, SavefileError> {
+    if let Some(checksum) = checksum {
+        let mut checksum_reader = ChecksumReader::new(reader, checksum, whole_input)?;
+        return match read_unchecked_schema_and_data(&mut checksum_reader, savefile_lib_version, file_ver, loader) {
+            Ok(data) => {
+                checksum_reader.verify()?;
+                Ok(data)
             }
-            let mut deserializer = Deserializer {
-                reader,
-                file_version: file_ver,
-                ephemeral_state: HashMap::new(),
-            };
-            Ok(T::deserialize(&mut deserializer)?)
+            Err(err) => Err(checksum_reader.verify_after_error(err)),
+        };
+    }
+    read_unchecked_schema_and_data(reader, savefile_lib_version, file_ver, loader)
+}
+
+fn read_unchecked_schema_and_data<L: PayloadLoader>(
+    reader: &mut impl Read,
+    savefile_lib_version: u16,
+    file_ver: u32,
+    loader: L,
+) -> Result<L::Output, SavefileError> {
+    let limits = loader.limits();
+    let file_schema = if loader.reads_schema() {
+        let mut schema_deserializer = new_schema_deserializer(reader, savefile_lib_version);
+        if let Some(limits) = limits {
+            schema_deserializer.set_limits(limits);
         }
+        Some(Schema::deserialize(&mut schema_deserializer)?)
+    } else {
+        None
+    };
+    let mut deserializer = Deserializer {
+        reader,
+        file_version: file_ver,
+        ephemeral_state: HashMap::new(),
+        limit_state: None,
+    };
+    if let Some(limits) = limits {
+        deserializer.set_limits(limits);
     }
+    loader.load_data(&mut deserializer, file_schema)
 }
 
 /// Create a Deserializer.
@@ -2178,72 +3074,194 @@ pub fn new_schema_deserializer(reader: &mut impl Read, file_schema_version: u16)
         reader,
         file_version: file_schema_version as u32,
         ephemeral_state: HashMap::new(),
+        limit_state: None,
     }
 }
 
-/// Deserialize an instance of type T from the given `reader` .
-/// The current type of T in memory must be equal to `version`.
-/// The deserializer will use the actual protocol version in the
-/// file to do the deserialization.
-pub fn load<T: WithSchema + Deserialize>(reader: &mut impl Read, version: u32) -> Result<T, SavefileError> {
-    Deserializer::<_>::load::<T>(reader, version)
+/// Deserialize an instance of type T from the given `reader` .
+/// The current type of T in memory must be equal to `version`.
+/// The deserializer will use the actual protocol version in the
+/// file to do the deserialization.
+pub fn load<T: WithSchema + Deserialize>(reader: &mut impl Read, version: u32) -> Result<T, SavefileError> {
+    LoadOptions::new(version).load(reader)
+}
+
+/// Deserialize an instance of type T from the given u8 slice .
+/// The current type of T in memory must be equal to `version`.
+/// The deserializer will use the actual protocol version in the
+/// file to do the deserialization.
+pub fn load_from_mem<T: WithSchema + Deserialize>(input: &[u8], version: u32) -> Result<T, SavefileError> {
+    LoadOptions::new(version).load_from_mem(input)
+}
+
+/// Like [crate::load_from_mem], except strings, byte slices and other types
+/// implementing [DeserializeBorrowed] may borrow from `input` instead of being copied.
+/// The input must not be compressed.
+pub fn load_from_mem_borrowed<'de, T: WithSchema + DeserializeBorrowed<'de>>(
+    input: &'de [u8],
+    version: u32,
+) -> Result<T, SavefileError> {
+    let mut input = input;
+    let (savefile_lib_version, file_ver) = read_file_header(&mut input, version)?;
+    let header = PayloadHeader::read(&mut input, false)?;
+    if header.compression.is_some() || header.checksum.is_some() {
+        return Err(SavefileError::GeneralError {
+            msg: "Compressed data cannot be deserialized while borrowing from the input.".into(),
+        });
+    }
+    {
+        let mut schema_deserializer = new_schema_deserializer(&mut input, savefile_lib_version);
+        let file_schema = Schema::deserialize(&mut schema_deserializer)?;
+        let memory_schema = T::schema(file_ver, &mut WithSchemaContext::new());
+        check_file_schema(&memory_schema, &file_schema, file_ver)?;
+    }
+    let mut deserializer = Deserializer {
+        reader: &mut input,
+        file_version: file_ver,
+        ephemeral_state: HashMap::new(),
+        limit_state: None,
+    };
+    T::deserialize_borrowed(&mut deserializer)
+}
+
+/// Write the given `data` to the `writer`.
+/// The current version of data must be `version`.
+pub fn save<T: WithSchema + Serialize>(writer: &mut impl Write, version: u32, data: &T) -> Result<(), SavefileError> {
+    SaveOptions::new(version).save(writer, data)
+}
+
+/// Write the given `data` to the `writer`. Compresses data using 'bzip2' compression format.
+/// The current version of data must be `version`.
+/// The resultant data can be loaded using the regular load-function (it autodetects if compressions was
+/// active or not).
+/// Note, this function will fail if the bzip2-feature is not enabled.
+pub fn save_compressed<T: WithSchema + Serialize>(
+    writer: &mut impl Write,
+    version: u32,
+    data: &T,
+) -> Result<(), SavefileError> {
+    SaveOptions::new(version)
+        .with_compression(CompressionOptions::default())
+        .save(writer, data)
+}
+
+/// Write the given `data` to the file. Compresses data using 'bzip2' compression format.
+///
+/// The current version of data must be `version`.
+/// The resultant data can be loaded using the regular load_file-function (it autodetects if compressions was
+/// active or not).
+/// Note, this function will fail if the bzip2-feature is not enabled.
+pub fn save_file_compressed<T: WithSchema + Serialize, P: AsRef<Path>>(
+    path: P,
+    version: u32,
+    data: &T,
+) -> Result<(), SavefileError> {
+    SaveOptions::new(version)
+        .with_compression(CompressionOptions::default())
+        .save_file(path, data)
+}
+
+/// Write the given `data` to the `writer`, compressed using the given codec and level.
+/// The current version of data must be `version`.
+/// The resultant data can be loaded using the regular load-function (it autodetects
+/// which codec, if any, was used).
+/// Note, this function will fail if the feature for the selected codec is not enabled.
+pub fn save_compressed_with<T: WithSchema + Serialize>(
+    writer: &mut impl Write,
+    version: u32,
+    data: &T,
+    compression: CompressionOptions,
+) -> Result<(), SavefileError> {
+    SaveOptions::new(version)
+        .with_compression(compression)
+        .save(writer, data)
+}
+
+/// Write the given `data` to the file, compressed using the given codec and level.
+///
+/// The current version of data must be `version`.
+/// The resultant data can be loaded using the regular load_file-function (it autodetects
+/// which codec, if any, was used).
+/// Note, this function will fail if the feature for the selected codec is not enabled.
+pub fn save_file_compressed_with<T: WithSchema + Serialize, P: AsRef<Path>>(
+    path: P,
+    version: u32,
+    data: &T,
+    compression: CompressionOptions,
+) -> Result<(), SavefileError> {
+    SaveOptions::new(version)
+        .with_compression(compression)
+        .save_file(path, data)
 }
 
-/// Deserialize an instance of type T from the given u8 slice .
-/// The current type of T in memory must be equal to `version`.
-/// The deserializer will use the actual protocol version in the
-/// file to do the deserialization.
-pub fn load_from_mem<T: WithSchema + Deserialize>(input: &[u8], version: u32) -> Result<T, SavefileError> {
-    let mut input = input;
-    Deserializer::load::<T>(&mut input, version)
+/// Write the given `data` to the `writer`, followed by a checksum of the contents.
+/// The current version of data must be `version`.
+/// The resultant data can be loaded using the regular load-function, which verifies
+/// the checksum, reporting [SavefileError::ChecksumMismatch] if the data is corrupt.
+pub fn save_with_checksum<T: WithSchema + Serialize>(
+    writer: &mut impl Write,
+    version: u32,
+    data: &T,
+    checksum: ChecksumAlgorithm,
+) -> Result<(), SavefileError> {
+    SaveOptions::new(version).with_checksum(checksum).save(writer, data)
 }
 
-/// Write the given `data` to the `writer`.
+/// Write the given `data` to the file, followed by a checksum of the contents.
+///
 /// The current version of data must be `version`.
-pub fn save<T: WithSchema + Serialize>(writer: &mut impl Write, version: u32, data: &T) -> Result<(), SavefileError> {
-    Serializer::save::<T>(writer, version, data, false)
+/// The resultant data can be loaded using the regular load_file-function, which verifies
+/// the checksum, reporting [SavefileError::ChecksumMismatch] if the file is corrupt.
+pub fn save_file_with_checksum<T: WithSchema + Serialize, P: AsRef<Path>>(
+    path: P,
+    version: u32,
+    data: &T,
+    checksum: ChecksumAlgorithm,
+) -> Result<(), SavefileError> {
+    SaveOptions::new(version).with_checksum(checksum).save_file(path, data)
 }
 
-/// Write the given `data` to the `writer`. Compresses data using 'bzip2' compression format.
+/// Write the given `data` to the `writer`, with the given metadata in the header.
 /// The current version of data must be `version`.
-/// The resultant data can be loaded using the regular load-function (it autodetects if compressions was
-/// active or not).
-/// Note, this function will fail if the bzip2-feature is not enabled.
-pub fn save_compressed<T: WithSchema + Serialize>(
+/// The metadata can be read using [read_header], without reading the data. The resultant
+/// data can be loaded using the regular load-function.
+pub fn save_with_metadata<T: WithSchema + Serialize>(
     writer: &mut impl Write,
     version: u32,
     data: &T,
+    metadata: &FileMetadata,
 ) -> Result<(), SavefileError> {
-    Serializer::save::<T>(writer, version, data, true)
+    SaveOptions::new(version)
+        .with_metadata(metadata.clone())
+        .save(writer, data)
 }
 
-/// Write the given `data` to the file. Compresses data using 'bzip2' compression format.
+/// Write the given `data` to the file, with the given metadata in the header.
 ///
 /// The current version of data must be `version`.
-/// The resultant data can be loaded using the regular load_file-function (it autodetects if compressions was
-/// active or not).
-/// Note, this function will fail if the bzip2-feature is not enabled.
-pub fn save_file_compressed<T: WithSchema + Serialize, P: AsRef<Path>>(
+/// The metadata can be read using [read_header], without reading the data.
+pub fn save_file_with_metadata<T: WithSchema + Serialize, P: AsRef<Path>>(
     path: P,
     version: u32,
     data: &T,
+    metadata: &FileMetadata,
 ) -> Result<(), SavefileError> {
-    let mut f = BufWriter::new(File::create(path)?);
-    Serializer::save::<T>(&mut f, version, data, true)
+    SaveOptions::new(version)
+        .with_metadata(metadata.clone())
+        .save_file(path, data)
 }
 
 /// Serialize the given data and return as a `Vec<u8>`
 /// The current version of data must be `version`.
 pub fn save_to_mem<T: WithSchema + Serialize>(version: u32, data: &T) -> Result<Vec<u8>, SavefileError> {
-    let mut retval = Vec::new();
-    Serializer::save::<T>(&mut retval, version, data, false)?;
-    Ok(retval)
+    SaveOptions::new(version).save_to_mem(data)
 }
 
 /// Like [crate::load] , but used to open files saved without schema,
 /// by one of the _noschema versions of the save functions.
 pub fn load_noschema<T: Deserialize>(reader: &mut impl Read, version: u32) -> Result<T, SavefileError> {
-    Deserializer::<_>::load_noschema::<T>(reader, version)
+    let no_schema: Option<fn(u32) -> Schema> = None;
+    LoadOptions::new(version).load_with_schema(reader, no_schema, false)
 }
 
 /// Write the given `data` to the `writer`.
@@ -2256,14 +3274,13 @@ pub fn load_noschema<T: Deserialize>(reader: &mut impl Read, version: u32) -> Re
 /// Serialize or Deserialize traits will cause hard-to-troubleshoot
 /// data corruption instead of a nice error message.
 pub fn save_noschema<T: Serialize>(writer: &mut impl Write, version: u32, data: &T) -> Result<(), SavefileError> {
-    Serializer::save_noschema::<T>(writer, version, data)
+    SaveOptions::new(version).save_with_schema(writer, data, None)
 }
 
 /// Like [crate::load] , except it deserializes from the given file in the filesystem.
 /// This is a pure convenience function.
 pub fn load_file<T: WithSchema + Deserialize, P: AsRef<Path>>(filepath: P, version: u32) -> Result<T, SavefileError> {
-    let mut f = BufReader::new(File::open(filepath)?);
-    Deserializer::load::<T>(&mut f, version)
+    LoadOptions::new(version).load_file(filepath)
 }
 
 /// Like [crate::save] , except it opens a file on the filesystem and writes
@@ -2273,15 +3290,14 @@ pub fn save_file<T: WithSchema + Serialize, P: AsRef<Path>>(
     version: u32,
     data: &T,
 ) -> Result<(), SavefileError> {
-    let mut f = BufWriter::new(File::create(filepath)?);
-    Serializer::save::<T>(&mut f, version, data, false)
+    SaveOptions::new(version).save_file(filepath, data)
 }
 
 /// Like [crate::load_noschema] , except it deserializes from the given file in the filesystem.
 /// This is a pure convenience function.
 pub fn load_file_noschema<T: Deserialize, P: AsRef<Path>>(filepath: P, version: u32) -> Result<T, SavefileError> {
     let mut f = BufReader::new(File::open(filepath)?);
-    Deserializer::load_noschema::<T>(&mut f, version)
+    load_noschema(&mut f, version)
 }
 
 /// Like [crate::save_noschema] , except it opens a file on the filesystem and writes
@@ -2292,7 +3308,9 @@ pub fn save_file_noschema<T: Serialize, P: AsRef<Path>>(
     data: &T,
 ) -> Result<(), SavefileError> {
     let mut f = BufWriter::new(File::create(filepath)?);
-    Serializer::save_noschema::<T>(&mut f, version, data)
+    save_noschema(&mut f, version, data)?;
+    f.flush()?;
+    Ok(())
 }
 
 /// Context object used to keep track of recursion.
@@ -2523,9 +3541,49 @@ pub trait Deserialize: WithSchema + Sized {
     fn deserialize(deserializer: &mut Deserializer<impl Read>) -> Result<Self, SavefileError>; //TODO: Do error handling
 }
 
+/// This trait is implemented by data structures which can be deserialized while
+/// borrowing from the input buffer, without copying. See [crate::load_from_mem_borrowed].
+///
+/// It is implemented for `&'de str`, `&'de [u8]`, `Cow<'de, str>` and `&'de [T]` for
+/// `Packed` T. Types deriving `Savefile` get an implementation if they have a single lifetime
+/// parameter. Fields which mention the lifetime are then deserialized using this trait,
+/// all other fields using the regular [Deserialize] trait.
+#[cfg_attr(feature = "rust1_78", diagnostic::on_unimplemented(
+    message = "`{Self}` cannot be deserialized by Savefile while borrowing from the input, since it doesn't implement the trait `savefile::DeserializeBorrowed`",
+    label = "This cannot be deserialized while borrowing from the input",
+    note = "You can implement it by adding `#[derive(Savefile)]` before the declaration of `{Self}`",
+    note = "Or you can manually implement the `savefile::DeserializeBorrowed` trait."
+))]
+pub trait DeserializeBorrowed<'de>: WithSchema + Sized {
+    /// Deserialize and return an instance of Self from the given deserializer.
+    /// The returned value may borrow from the buffer being deserialized.
+    fn deserialize_borrowed(deserializer: &mut Deserializer<&'de [u8]>) -> Result<Self, SavefileError>;
+}
+
+/// The default value of a field, declared using the `savefile_default_val` or
+/// `savefile_default_fn` attributes. Used by [crate::load_lenient] for fields which
+/// are missing from the file.
+#[derive(Clone, Copy)]
+pub enum FieldDefault {
+    /// Serializes the default value of the field, using the given version
+    Serialized(fn(u32) -> Result<Vec<u8>, SavefileError>),
+    /// The field has a default, but it cannot be obtained through the schema.
+    /// This is the case for fields whose type depends on a generic parameter.
+    Unavailable,
+}
+
+impl Debug for FieldDefault {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FieldDefault::Serialized(_) => write!(f, "Serialized"),
+            FieldDefault::Unavailable => write!(f, "Unavailable"),
+        }
+    }
+}
+
 /// A field is serialized according to its value.
 /// The name is just for diagnostics.
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, Clone)]
 #[cfg_attr(feature = "serde_derive", derive(Serialize, Deserialize))]
 pub struct Field {
     /// Field name
@@ -2542,6 +3600,16 @@ pub struct Field {
     /// is actually an instance of the type given by the schema in 'value'. Otherwise,
     /// layout compatibility calculations may fail, with catastrophic consequences.
     offset: Option<usize>,
+    /// The declared default value of the field, if any. This is not part of the
+    /// serialized schema, and is ignored when comparing fields.
+    #[cfg_attr(feature = "serde_derive", serde(skip))]
+    default: Option<FieldDefault>,
+}
+
+impl PartialEq for Field {
+    fn eq(&self, other: &Field) -> bool {
+        self.name == other.name && self.value == other.value && self.offset == other.offset
+    }
 }
 
 impl Field {
@@ -2551,6 +3619,7 @@ impl Field {
             name,
             value,
             offset: None,
+            default: None,
         }
     }
     /// Create a new instance of field, with the given name and type.
@@ -2559,7 +3628,29 @@ impl Field {
     /// # Safety
     /// The offset *must* be the correct offset of the field within its struct.
     pub unsafe fn unsafe_new(name: String, value: Box<Schema>, offset: Option<usize>) -> Field {
-        Field { name, value, offset }
+        Field {
+            name,
+            value,
+            offset,
+            default: None,
+        }
+    }
+    /// This field, with the given declared default value
+    pub fn with_default(self, default: FieldDefault) -> Field {
+        Field {
+            default: Some(default),
+            ..self
+        }
+    }
+    /// The declared default value of the field, if any. Only known for schemas
+    /// obtained from [WithSchema], never for schemas read from a file.
+    ///
+    /// The default is not serialized as part of the schema, and is ignored when comparing
+    /// fields with `==`. A schema in memory and the same schema read back from a file are
+    /// therefore equal, and neither [crate::diff_schema] nor [crate::SchemaDiff] report
+    /// changed defaults.
+    pub fn default(&self) -> Option<FieldDefault> {
+        self.default
     }
     /// Determine if the two fields are laid out identically in memory, in their parent objects.
     pub fn layout_compatible(&self, other: &Field) -> bool {
@@ -2931,21 +4022,6 @@ impl SchemaPrimitive {
     }
 }
 
-fn diff_primitive(a: SchemaPrimitive, b: SchemaPrimitive, path: &str) -> Option<String> {
-    if a != b {
-        if let (SchemaPrimitive::schema_string(_), SchemaPrimitive::schema_string(_)) = (&a, &b) {
-            return None; //Strings have the same schema, even if they're not memory-layout compatible
-        }
-        return Some(format!(
-            "At location [{}]: Application protocol has datatype {}, but disk format has {}",
-            path,
-            a.name(),
-            b.name()
-        ));
-    }
-    None
-}
-
 /// The actual layout in memory of a Vec-like datastructure.
 /// If this is 'Unknown', the memory format is unspecified.
 /// Otherwise, it is as given by the variant.
@@ -3328,6 +4404,7 @@ impl Schema {
                 name: "0".to_string(),
                 value: schema,
                 offset: Some(offset_of_tuple!((T1,), 0)),
+                default: None,
             }],
         })
     }
@@ -3343,11 +4420,13 @@ impl Schema {
                     name: "0".to_string(),
                     value: Box::new(T1::schema(version, context)),
                     offset: Some(offset_of_tuple!((T1, T2), 0)),
+                    default: None,
                 },
                 Field {
                     name: "1".to_string(),
                     value: Box::new(T2::schema(version, context)),
                     offset: Some(offset_of_tuple!((T1, T2), 1)),
+                    default: None,
                 },
             ],
         })
@@ -3366,16 +4445,19 @@ impl Schema {
                     name: "0".to_string(),
                     value: Box::new(T1::schema(version, context)),
                     offset: Some(offset_of_tuple!((T1, T2, T3), 0)),
+                    default: None,
                 },
                 Field {
                     name: "1".to_string(),
                     value: Box::new(T2::schema(version, context)),
                     offset: Some(offset_of_tuple!((T1, T2, T3), 1)),
+                    default: None,
                 },
                 Field {
                     name: "2".to_string(),
                     value: Box::new(T3::schema(version, context)),
                     offset: Some(offset_of_tuple!((T1, T2, T3), 2)),
+                    default: None,
                 },
             ],
         })
@@ -3394,21 +4476,25 @@ impl Schema {
                     name: "0".to_string(),
                     value: Box::new(T1::schema(version, context)),
                     offset: Some(offset_of_tuple!((T1, T2, T3, T4), 0)),
+                    default: None,
                 },
                 Field {
                     name: "1".to_string(),
                     value: Box::new(T2::schema(version, context)),
                     offset: Some(offset_of_tuple!((T1, T2, T3, T4), 1)),
+                    default: None,
                 },
                 Field {
                     name: "2".to_string(),
                     value: Box::new(T3::schema(version, context)),
                     offset: Some(offset_of_tuple!((T1, T2, T3, T4), 2)),
+                    default: None,
                 },
                 Field {
                     name: "3".to_string(),
                     value: Box::new(T4::schema(version, context)),
                     offset: Some(offset_of_tuple!((T1, T2, T3, T4), 3)),
+                    default: None,
                 },
             ],
         })
@@ -3436,213 +4522,18 @@ impl Schema {
     }
 }
 
-fn diff_vector(a: &Schema, b: &Schema, path: String) -> Option<String> {
-    diff_schema(a, b, path + "/*")
-}
-
-fn diff_array(a: &SchemaArray, b: &SchemaArray, path: String) -> Option<String> {
-    if a.count != b.count {
-        return Some(format!(
-            "At location [{}]: In memory array has length {}, but disk format length {}.",
-            path, a.count, b.count
-        ));
-    }
-
-    diff_schema(&a.item_type, &b.item_type, format!("{}/[{}]", path, a.count))
-}
-
-fn diff_option(a: &Schema, b: &Schema, path: String) -> Option<String> {
-    diff_schema(a, b, path + "/?")
-}
-
-fn diff_enum(a: &SchemaEnum, b: &SchemaEnum, path: String) -> Option<String> {
-    let path = (path + &b.dbg_name).to_string();
-    if a.variants.len() != b.variants.len() {
-        return Some(format!(
-            "At location [{}]: In memory enum has {} variants, but disk format has {} variants.",
-            path,
-            a.variants.len(),
-            b.variants.len()
-        ));
-    }
-    if a.discriminant_size != b.discriminant_size {
-        return Some(format!(
-            "At location [{}]: In memory enum has a representation with {} bytes for the discriminant, but disk format has {}.",
-            path,
-            a.discriminant_size,
-            b.discriminant_size
-        ));
-    }
-    for i in 0..a.variants.len() {
-        if a.variants[i].name != b.variants[i].name {
-            return Some(format!(
-                "At location [{}]: Enum variant #{} in memory is called {}, but in disk format it is called {}",
-                &path, i, a.variants[i].name, b.variants[i].name
-            ));
-        }
-        if a.variants[i].discriminant != b.variants[i].discriminant {
-            return Some(format!(
-                "At location [{}]: Enum variant #{} in memory has discriminant {}, but in disk format it has {}",
-                &path, i, a.variants[i].discriminant, b.variants[i].discriminant
-            ));
-        }
-        let r = diff_fields(
-            &a.variants[i].fields,
-            &b.variants[i].fields,
-            &(path.to_string() + "/" + &b.variants[i].name).to_string(),
-            "enum",
-            "",
-            "",
-        );
-        if let Some(err) = r {
-            return Some(err);
-        }
-    }
-    None
-}
-fn diff_struct(a: &SchemaStruct, b: &SchemaStruct, path: String) -> Option<String> {
-    diff_fields(
-        &a.fields,
-        &b.fields,
-        &(path + "/" + &b.dbg_name).to_string(),
-        "struct",
-        &(" (struct ".to_string() + &a.dbg_name + ")"),
-        &(" (struct ".to_string() + &b.dbg_name + ")"),
-    )
-}
-fn diff_fields(
-    a: &[Field],
-    b: &[Field],
-    path: &str,
-    structuretype: &str,
-    extra_a: &str,
-    extra_b: &str,
-) -> Option<String> {
-    if a.len() != b.len() {
-        return Some(format!(
-            "At location [{}]: In memory {}{} has {} fields, disk format{} has {} fields.",
-            path,
-            structuretype,
-            extra_a,
-            a.len(),
-            extra_b,
-            b.len()
-        ));
-    }
-    for i in 0..a.len() {
-        let r = diff_schema(
-            &a[i].value,
-            &b[i].value,
-            (path.to_string() + "/" + &b[i].name).to_string(),
-        );
-        if let Some(err) = r {
-            return Some(err);
-        }
-    }
-    None
-}
 /// Return a (kind of) human-readable description of the difference
 /// between the two schemas. The schema 'a' is assumed to be the current
 /// schema (used in memory).
 /// Returns None if both schemas are equivalent
 /// This does not care about memory layout, only serializability.
+///
+/// Only the first difference is described. Use [SchemaDiff] to get all of them.
 pub fn diff_schema(a: &Schema, b: &Schema, path: String) -> Option<String> {
-    let (atype, btype) = match (a, b) {
-        (Schema::Struct(a), Schema::Struct(b)) => return diff_struct(a, b, path),
-        (Schema::Enum(a), Schema::Enum(b)) => return diff_enum(a, b, path),
-        (Schema::Primitive(a1), Schema::Primitive(b1)) => return diff_primitive(*a1, *b1, &path),
-        (Schema::Vector(a1, _a2), Schema::Vector(b1, _b2)) => return diff_vector(a1, b1, path),
-        (Schema::SchemaOption(a), Schema::SchemaOption(b)) => {
-            return diff_option(a, b, path);
-        }
-        (Schema::Undefined, Schema::Undefined) => {
-            return Some(format!("At location [{}]: Undefined schema encountered.", path))
-        }
-        (Schema::ZeroSize, Schema::ZeroSize) => {
-            return None;
-        }
-        (Schema::Array(a), Schema::Array(b)) => return diff_array(a, b, path),
-        (Schema::Custom(a), Schema::Custom(b)) => {
-            if a != b {
-                return Some(format!(
-                    "At location [{}]: Application protocol has datatype Custom({}), but foreign format has Custom({})",
-                    path, a, b
-                ));
-            }
-            return None;
-        }
-        (Schema::Str, Schema::Str) => {
-            return None;
-        }
-        (Schema::Boxed(a), Schema::Boxed(b)) => {
-            return diff_schema(&**a, &**b, path);
-        }
-        (Schema::Reference(a), Schema::Reference(b)) => {
-            return diff_schema(&**a, &**b, path);
-        }
-        (Schema::Slice(a), Schema::Slice(b)) => {
-            return diff_schema(&**a, &**b, path);
-        }
-        (Schema::Trait(amut, a), Schema::Trait(bmut, b)) | (Schema::FnClosure(amut, a), Schema::FnClosure(bmut, b)) => {
-            if amut != bmut {
-                if *amut {
-                    return Some(format!(
-                        "At location [{}]: Application protocol uses FnMut, but foreign format has Fn.",
-                        path
-                    ));
-                }
-                if *bmut {
-                    return Some(format!(
-                        "At location [{}]: Application protocol uses Fn, but foreign format uses FnMut.",
-                        path
-                    ));
-                }
-            }
-            return diff_abi_def(a, b, path);
-        }
-        (Schema::Recursion(adepth), Schema::Recursion(bdepth)) => {
-            if adepth == bdepth {
-                return None; //Ok
-            } else {
-                return Some(format!(
-                    "At location [{}]: Application protocol uses recursion up {} levels, but foreign format uses {}.",
-                    path, adepth, bdepth
-                ));
-            }
-        }
-        (a, b) => (a.top_level_description(), b.top_level_description()),
-    };
-
-    Some(format!(
-        "At location [{}]: In memory schema: {}, file schema: {}",
-        path, atype, btype
-    ))
-}
-
-fn diff_abi_def(a: &AbiTraitDefinition, b: &AbiTraitDefinition, path: String) -> Option<String> {
-    for amet in a.methods.iter() {
-        if let Some(bmet) = b.methods.iter().find(|x| x.name == amet.name) {
-            if amet.info.arguments.len() != bmet.info.arguments.len() {
-                return Some(format!(
-                    "At location [{}]: Application protocol method {} has {} args, but foreign version has {}.",
-                    path,
-                    amet.name,
-                    amet.info.arguments.len(),
-                    bmet.info.arguments.len()
-                ));
-            }
-            for (arg_index, (a_arg, b_arg)) in amet.info.arguments.iter().zip(bmet.info.arguments.iter()).enumerate() {
-                if let Some(diff) = diff_schema(
-                    &a_arg.schema,
-                    &b_arg.schema,
-                    format!("{}(arg #{})", amet.name, arg_index),
-                ) {
-                    return Some(diff);
-                }
-            }
-        }
-    }
-    return None;
+    SchemaDiff::new(a, b)
+        .differences
+        .first()
+        .map(|difference| difference.message(&path))
 }
 
 impl WithSchema for Field {
@@ -3670,6 +4561,7 @@ impl Deserialize for Field {
             } else {
                 None
             },
+            default: None,
         })
     }
 }
@@ -3698,18 +4590,23 @@ impl Deserialize for Variant {
             discriminant: deserializer.read_u8()?,
             fields: {
                 let l = deserializer.read_usize()?;
+                deserializer.check_collection_len(l, std::mem::size_of::<Field>())?;
                 let mut ret = Vec::new();
-                for _ in 0..l {
-                    ret.push(Field {
-                        name: deserializer.read_string()?,
-                        value: Box::new(Schema::deserialize(deserializer)?),
-                        offset: if deserializer.file_version > 0 {
-                            Option::deserialize(deserializer)?
-                        } else {
-                            None
-                        },
-                    });
-                }
+                deserializer.nested(|deserializer| {
+                    for _ in 0..l {
+                        ret.push(Field {
+                            name: deserializer.read_string()?,
+                            value: Box::new(Schema::deserialize(deserializer)?),
+                            offset: if deserializer.file_version > 0 {
+                                Option::deserialize(deserializer)?
+                            } else {
+                                None
+                            },
+                            default: None,
+                        });
+                    }
+                    Ok(())
+                })?;
                 ret
             },
         })
@@ -3726,7 +4623,7 @@ impl Packed for SchemaArray {}
 impl Deserialize for SchemaArray {
     fn deserialize(deserializer: &mut Deserializer<impl Read>) -> Result<Self, SavefileError> {
         let count = deserializer.read_usize()?;
-        let item_type = Box::new(Schema::deserialize(deserializer)?);
+        let item_type = Box::new(deserializer.nested(Schema::deserialize)?);
         Ok(SchemaArray { count, item_type })
     }
 }
@@ -3758,6 +4655,7 @@ impl Deserialize for SchemaStruct {
     fn deserialize(deserializer: &mut Deserializer<impl Read>) -> Result<Self, SavefileError> {
         let dbg_name = deserializer.read_string()?;
         let l = deserializer.read_usize()?;
+        deserializer.check_collection_len(l, std::mem::size_of::<Field>())?;
         Ok(SchemaStruct {
             dbg_name,
             size: if deserializer.file_version > 0 {
@@ -3772,9 +4670,12 @@ impl Deserialize for SchemaStruct {
             },
             fields: {
                 let mut ret = Vec::new();
-                for _ in 0..l {
-                    ret.push(Field::deserialize(deserializer)?)
-                }
+                deserializer.nested(|deserializer| {
+                    for _ in 0..l {
+                        ret.push(Field::deserialize(deserializer)?)
+                    }
+                    Ok(())
+                })?;
                 ret
             },
         })
@@ -3899,6 +4800,7 @@ impl Deserialize for SchemaEnum {
     fn deserialize(deserializer: &mut Deserializer<impl Read>) -> Result<Self, SavefileError> {
         let dbg_name = deserializer.read_string()?;
         let l = deserializer.read_usize()?;
+        deserializer.check_collection_len(l, std::mem::size_of::<Variant>())?;
         let mut ret = Vec::new();
         for _ in 0..l {
             ret.push(Variant::deserialize(deserializer)?);
@@ -3976,6 +4878,7 @@ impl Arbitrary for Field {
             name: g.choose(&["", "test"]).unwrap().to_string(),
             value: <_ as Arbitrary>::arbitrary(g),
             offset: <_ as Arbitrary>::arbitrary(g),
+            default: None,
         }
     }
 }
@@ -4148,7 +5051,7 @@ impl Deserialize for Schema {
             2 => Schema::Enum(SchemaEnum::deserialize(deserializer)?),
             3 => Schema::Primitive(SchemaPrimitive::deserialize(deserializer)?),
             4 => Schema::Vector(
-                Box::new(Schema::deserialize(deserializer)?),
+                Box::new(deserializer.nested(Schema::deserialize)?),
                 if deserializer.file_version > 0 {
                     VecOrStringLayout::deserialize(deserializer)?
                 } else {
@@ -4157,17 +5060,17 @@ impl Deserialize for Schema {
             ),
             5 => Schema::Undefined,
             6 => Schema::ZeroSize,
-            7 => Schema::SchemaOption(Box::new(Schema::deserialize(deserializer)?)),
+            7 => Schema::SchemaOption(Box::new(deserializer.nested(Schema::deserialize)?)),
             8 => Schema::Array(SchemaArray::deserialize(deserializer)?),
             9 => Schema::Custom(String::deserialize(deserializer)?),
-            10 => Schema::Boxed(<_ as Deserialize>::deserialize(deserializer)?),
+            10 => Schema::Boxed(Box::new(deserializer.nested(Schema::deserialize)?)),
             11 => Schema::FnClosure(
                 <_ as Deserialize>::deserialize(deserializer)?,
                 <_ as Deserialize>::deserialize(deserializer)?,
             ),
-            12 => Schema::Slice(Box::new(<_ as Deserialize>::deserialize(deserializer)?)),
+            12 => Schema::Slice(Box::new(deserializer.nested(Schema::deserialize)?)),
             13 => Schema::Str,
-            14 => Schema::Reference(Box::new(<_ as Deserialize>::deserialize(deserializer)?)),
+            14 => Schema::Reference(Box::new(deserializer.nested(Schema::deserialize)?)),
             15 => Schema::Trait(
                 <_ as Deserialize>::deserialize(deserializer)?,
                 <_ as Deserialize>::deserialize(deserializer)?,
@@ -4639,11 +5542,13 @@ impl<K: WithSchema + 'static, V: WithSchema + 'static> WithSchema for BTreeMap<K
                         name: "key".to_string(),
                         value: Box::new(context.possible_recursion::<K>(|context| K::schema(version, context))),
                         offset: None,
+                        default: None,
                     },
                     Field {
                         name: "value".to_string(),
                         value: Box::new(context.possible_recursion::<V>(|context| V::schema(version, context))),
                         offset: None,
+                        default: None,
                     },
                 ],
             })),
@@ -4666,13 +5571,16 @@ impl<K: Deserialize + Ord + 'static, V: Deserialize + 'static> Deserialize for B
     fn deserialize(deserializer: &mut Deserializer<impl Read>) -> Result<Self, SavefileError> {
         let mut ret = BTreeMap::new();
         let count = <usize as Deserialize>::deserialize(deserializer)?;
-        for _ in 0..count {
-            ret.insert(
-                <_ as Deserialize>::deserialize(deserializer)?,
-                <_ as Deserialize>::deserialize(deserializer)?,
-            );
-        }
-        Ok(ret)
+        deserializer.check_collection_len(count, std::mem::size_of::<(K, V)>())?;
+        deserializer.nested(|deserializer| {
+            for _ in 0..count {
+                ret.insert(
+                    <_ as Deserialize>::deserialize(deserializer)?,
+                    <_ as Deserialize>::deserialize(deserializer)?,
+                );
+            }
+            Ok(ret)
+        })
     }
 }
 
@@ -4698,11 +5606,14 @@ impl<K: Serialize + 'static> Serialize for BTreeSet<K> {
 impl<K: Deserialize+'static+Ord> Deserialize for BTreeSet<K> {
     fn deserialize(deserializer: &mut Deserializer<impl Read>) -> Result<Self, SavefileError> {
         let cnt = deserializer.read_usize()?;
+        deserializer.check_collection_len(cnt, std::mem::size_of::<K>())?;
         let mut ret = BTreeSet::new();
-        for _ in 0..cnt {
-            ret.insert(<_ as Deserialize>::deserialize(deserializer)?);
-        }
-        Ok(ret)
+        deserializer.nested(|deserializer| {
+            for _ in 0..cnt {
+                ret.insert(<_ as Deserialize>::deserialize(deserializer)?);
+            }
+            Ok(ret)
+        })
     }
 }
 
@@ -4730,11 +5641,14 @@ impl<K: Serialize + 'static, S: ::std::hash::BuildHasher> Serialize for HashSet<
 impl<K: Deserialize + Eq + Hash + 'static, S: ::std::hash::BuildHasher + Default> Deserialize for HashSet<K, S> {
     fn deserialize(deserializer: &mut Deserializer<impl Read>) -> Result<Self, SavefileError> {
         let cnt = deserializer.read_usize()?;
+        deserializer.check_collection_len(cnt, std::mem::size_of::<K>())?;
         let mut ret = HashSet::with_capacity_and_hasher(cnt, S::default());
-        for _ in 0..cnt {
-            ret.insert(<_ as Deserialize>::deserialize(deserializer)?);
-        }
-        Ok(ret)
+        deserializer.nested(|deserializer| {
+            for _ in 0..cnt {
+                ret.insert(<_ as Deserialize>::deserialize(deserializer)?);
+            }
+            Ok(ret)
+        })
     }
 }
 
@@ -4752,11 +5666,13 @@ impl<K: WithSchema + Eq + Hash + 'static, V: WithSchema + 'static, S: ::std::has
                         name: "key".to_string(),
                         value: Box::new(context.possible_recursion::<K>(|context| K::schema(version, context))),
                         offset: None,
+                        default: None,
                     },
                     Field {
                         name: "value".to_string(),
                         value: Box::new(context.possible_recursion::<K>(|context| V::schema(version, context))),
                         offset: None,
+                        default: None,
                     },
                 ],
             })),
@@ -4783,11 +5699,14 @@ impl<K: Deserialize + Eq + Hash + 'static, V: Deserialize + 'static, S: ::std::h
 {
     fn deserialize(deserializer: &mut Deserializer<impl Read>) -> Result<Self, SavefileError> {
         let l = deserializer.read_usize()?;
+        deserializer.check_collection_len(l, std::mem::size_of::<(K, V)>())?;
         let mut ret: Self = HashMap::with_capacity_and_hasher(l, Default::default());
-        for _ in 0..l {
-            ret.insert(K::deserialize(deserializer)?, V::deserialize(deserializer)?);
-        }
-        Ok(ret)
+        deserializer.nested(|deserializer| {
+            for _ in 0..l {
+                ret.insert(K::deserialize(deserializer)?, V::deserialize(deserializer)?);
+            }
+            Ok(ret)
+        })
     }
 }
 
@@ -4806,11 +5725,13 @@ impl<K: WithSchema + Eq + Hash + 'static, V: WithSchema + 'static, S: ::std::has
                         name: "key".to_string(),
                         value: Box::new(context.possible_recursion::<K>(|context| K::schema(version, context))),
                         offset: None,
+                        default: None,
                     },
                     Field {
                         name: "value".to_string(),
                         value: Box::new(context.possible_recursion::<K>(|context| V::schema(version, context))),
                         offset: None,
+                        default: None,
                     },
                 ],
             })),
@@ -4923,11 +5844,14 @@ impl<K: Serialize + Eq + Hash + 'static, V: Serialize + 'static, S: ::std::hash:
 impl<K: Deserialize + Eq + Hash + 'static, V: Deserialize + 'static> Deserialize for IndexMap<K, V> {
     fn deserialize(deserializer: &mut Deserializer<impl Read>) -> Result<Self, SavefileError> {
         let l = deserializer.read_usize()?;
+        deserializer.check_collection_len(l, std::mem::size_of::<(K, V)>())?;
         let mut ret = IndexMap::with_capacity(l);
-        for _ in 0..l {
-            ret.insert(K::deserialize(deserializer)?, V::deserialize(deserializer)?);
-        }
-        Ok(ret)
+        deserializer.nested(|deserializer| {
+            for _ in 0..l {
+                ret.insert(K::deserialize(deserializer)?, V::deserialize(deserializer)?);
+            }
+            Ok(ret)
+        })
     }
 }
 
@@ -4965,6 +5889,7 @@ impl<K: WithSchema + Eq + Hash + 'static, S: ::std::hash::BuildHasher> WithSchem
                     name: "key".to_string(),
                     value: Box::new(context.possible_recursion::<K>(|context| K::schema(version, context))),
                     offset: None,
+                    default: None,
                 }],
             })),
             VecOrStringLayout::Unknown,
@@ -4987,11 +5912,14 @@ impl<K: Serialize + Eq + Hash + 'static, S: ::std::hash::BuildHasher> Serialize
 impl<K: Deserialize + Eq + Hash + 'static> Deserialize for IndexSet<K> {
     fn deserialize(deserializer: &mut Deserializer<impl Read>) -> Result<Self, SavefileError> {
         let l = deserializer.read_usize()?;
+        deserializer.check_collection_len(l, std::mem::size_of::<K>())?;
         let mut ret = IndexSet::with_capacity(l);
-        for _ in 0..l {
-            ret.insert(K::deserialize(deserializer)?);
-        }
-        Ok(ret)
+        deserializer.nested(|deserializer| {
+            for _ in 0..l {
+                ret.insert(K::deserialize(deserializer)?);
+            }
+            Ok(ret)
+        })
     }
 }
 
@@ -5301,6 +6229,7 @@ impl<T: WithSchema, R: WithSchema> WithSchema for Result<T, R> {
                         name: "ok".to_string(),
                         value: Box::new(T::schema(version, context)),
                         offset: None,
+                        default: None,
                     }],
                 },
                 Variant {
@@ -5310,6 +6239,7 @@ impl<T: WithSchema, R: WithSchema> WithSchema for Result<T, R> {
                         name: "err".to_string(),
                         value: Box::new(R::schema(version, context)),
                         offset: None,
+                        default: None,
                     }],
                 },
             ],
@@ -5360,11 +6290,13 @@ impl WithSchema for bit_vec::BitVec {
                     name: "num_bits".to_string(),
                     value: Box::new(usize::schema(version, context)),
                     offset: None,
+                    default: None,
                 },
                 Field {
                     name: "num_bytes".to_string(),
                     value: Box::new(usize::schema(version, context)),
                     offset: None,
+                    default: None,
                 },
                 Field {
                     name: "buffer".to_string(),
@@ -5373,6 +6305,7 @@ impl WithSchema for bit_vec::BitVec {
                         VecOrStringLayout::Unknown,
                     )),
                     offset: None,
+                    default: None,
                 },
             ],
         })
@@ -5423,6 +6356,7 @@ impl Deserialize for bit_vec::BitVec<u32> {
         if numbytes & (1 << 63) != 0 {
             //New format
             numbytes &= !(1 << 63);
+            deserializer.check_collection_len(numbytes, 1)?;
             let mut ret = bit_vec::BitVec::with_capacity(numbytes * 8);
             unsafe {
                 let num_words = numbytes / 4;
@@ -5455,11 +6389,13 @@ impl WithSchema for bit_set::BitSet {
                     name: "num_bits".to_string(),
                     value: Box::new(usize::schema(version, context)),
                     offset: None,
+                    default: None,
                 },
                 Field {
                     name: "num_bytes".to_string(),
                     value: Box::new(usize::schema(version, context)),
                     offset: None,
+                    default: None,
                 },
                 Field {
                     name: "buffer".to_string(),
@@ -5468,6 +6404,7 @@ impl WithSchema for bit_set::BitSet {
                         VecOrStringLayout::Unknown,
                     )),
                     offset: None,
+                    default: None,
                 },
             ],
         })
@@ -5530,11 +6467,13 @@ impl WithSchema for bit_vec08::BitVec {
                     name: "num_bits".to_string(),
                     value: Box::new(usize::schema(version, context)),
                     offset: None,
+                    default: None,
                 },
                 Field {
                     name: "num_bytes".to_string(),
                     value: Box::new(usize::schema(version, context)),
                     offset: None,
+                    default: None,
                 },
                 Field {
                     name: "buffer".to_string(),
@@ -5543,6 +6482,7 @@ impl WithSchema for bit_vec08::BitVec {
                         VecOrStringLayout::Unknown,
                     )),
                     offset: None,
+                    default: None,
                 },
             ],
         })
@@ -5593,6 +6533,7 @@ impl Deserialize for bit_vec08::BitVec<u32> {
         if numbytes & (1 << 63) != 0 {
             //New format
             numbytes &= !(1 << 63);
+            deserializer.check_collection_len(numbytes, 1)?;
             let mut ret = bit_vec08::BitVec::with_capacity(numbytes * 8);
             unsafe {
                 let num_words = numbytes / 4;
@@ -5625,11 +6566,13 @@ impl WithSchema for bit_set08::BitSet {
                     name: "num_bits".to_string(),
                     value: Box::new(usize::schema(version, context)),
                     offset: None,
+                    default: None,
                 },
                 Field {
                     name: "num_bytes".to_string(),
                     value: Box::new(usize::schema(version, context)),
                     offset: None,
+                    default: None,
                 },
                 Field {
                     name: "buffer".to_string(),
@@ -5638,6 +6581,7 @@ impl WithSchema for bit_set08::BitSet {
                         VecOrStringLayout::Unknown,
                     )),
                     offset: None,
+                    default: None,
                 },
             ],
         })
@@ -5727,11 +6671,14 @@ impl<T: Serialize + Ord + 'static> Serialize for BinaryHeap<T> {
 impl<T: Deserialize + Ord + 'static> Deserialize for BinaryHeap<T> {
     fn deserialize(deserializer: &mut Deserializer<impl Read>) -> Result<Self, SavefileError> {
         let l = deserializer.read_usize()?;
+        deserializer.check_collection_len(l, std::mem::size_of::<T>())?;
         let mut ret = BinaryHeap::with_capacity(l);
-        for _ in 0..l {
-            ret.push(T::deserialize(deserializer)?);
-        }
-        Ok(ret)
+        deserializer.nested(|deserializer| {
+            for _ in 0..l {
+                ret.push(T::deserialize(deserializer)?);
+            }
+            Ok(ret)
+        })
     }
 }
 
@@ -5793,11 +6740,14 @@ where
 {
     fn deserialize(deserializer: &mut Deserializer<impl Read>) -> Result<Self, SavefileError> {
         let l = deserializer.read_usize()?;
+        deserializer.check_collection_len(l, std::mem::size_of::<T::Item>())?;
         let mut ret = Self::with_capacity(l);
-        for _ in 0..l {
-            ret.push(T::Item::deserialize(deserializer)?);
-        }
-        Ok(ret)
+        deserializer.nested(|deserializer| {
+            for _ in 0..l {
+                ret.push(T::Item::deserialize(deserializer)?);
+            }
+            Ok(ret)
+        })
     }
 }
 
@@ -6007,6 +6957,86 @@ impl<'a, T: Serialize + Packed + 'static> Serialize for &'a [T] {
     }
 }
 
+impl Packed for &str {}
+impl<T> Packed for &[T] {}
+
+impl Introspect for &str {
+    fn introspect_value(&self) -> String {
+        self.to_string()
+    }
+
+    fn introspect_child(&self, _index: usize) -> Option<Box<dyn IntrospectItem<'_> + '_>> {
+        None
+    }
+}
+impl<T: Introspect> Introspect for &[T] {
+    fn introspect_value(&self) -> String {
+        "[]".to_string()
+    }
+
+    fn introspect_child(&self, index: usize) -> Option<Box<dyn IntrospectItem<'_> + '_>> {
+        if index >= self.len() {
+            return None;
+        }
+        Some(introspect_item(index.to_string(), &self[index]))
+    }
+    fn introspect_len(&self) -> usize {
+        self.len()
+    }
+}
+
+impl<'de> DeserializeBorrowed<'de> for &'de str {
+    fn deserialize_borrowed(deserializer: &mut Deserializer<&'de [u8]>) -> Result<Self, SavefileError> {
+        deserializer.read_borrowed_str()
+    }
+}
+impl<'de> DeserializeBorrowed<'de> for Cow<'de, str> {
+    fn deserialize_borrowed(deserializer: &mut Deserializer<&'de [u8]>) -> Result<Self, SavefileError> {
+        Ok(Cow::Borrowed(deserializer.read_borrowed_str()?))
+    }
+}
+impl<'de, T: Deserialize + Packed + 'static> DeserializeBorrowed<'de> for &'de [T] {
+    fn deserialize_borrowed(deserializer: &mut Deserializer<&'de [u8]>) -> Result<Self, SavefileError> {
+        if unsafe { T::repr_c_optimization_safe(deserializer.file_version) }.is_false() {
+            return Err(SavefileError::GeneralError {
+                msg: format!(
+                    "Slices of {} cannot be borrowed from the input, since the type is not packed.",
+                    std::any::type_name::<T>()
+                ),
+            });
+        }
+        let num_elems = deserializer.read_usize()?;
+        let num_bytes = num_elems
+            .checked_mul(std::mem::size_of::<T>())
+            .ok_or(SavefileError::SizeOverflow)?;
+        let bytes = deserializer.read_borrowed_bytes(num_bytes)?;
+        if std::mem::size_of::<T>() == 0 {
+            return Ok(unsafe { std::slice::from_raw_parts(NonNull::<T>::dangling().as_ptr(), num_elems) });
+        }
+        if bytes.as_ptr().align_offset(std::mem::align_of::<T>()) != 0 {
+            return Err(SavefileError::GeneralError {
+                msg: format!(
+                    "Slice of {} cannot be borrowed from the input, since it is not correctly aligned in the buffer.",
+                    std::any::type_name::<T>()
+                ),
+            });
+        }
+        // Safety: T is packed, so any bit pattern read from the input is its in-memory
+        // representation, and we have checked the size and alignment above.
+        Ok(unsafe { std::slice::from_raw_parts(bytes.as_ptr() as *const T, num_elems) })
+    }
+}
+impl<'de, T: DeserializeBorrowed<'de>> DeserializeBorrowed<'de> for Option<T> {
+    fn deserialize_borrowed(deserializer: &mut Deserializer<&'de [u8]>) -> Result<Self, SavefileError> {
+        let issome = deserializer.read_bool()?;
+        if issome {
+            Ok(Some(T::deserialize_borrowed(deserializer)?))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
 /// Deserialize a slice into a Vec
 /// Unsized slices cannot be deserialized into unsized slices.
 pub fn deserialize_slice_as_vec<R: Read, T: Deserialize + Packed + 'static>(
@@ -6157,11 +7187,14 @@ fn regular_deserialize_vec<T: Deserialize>(
             });
         }
     }
+    deserializer.check_collection_len(l, std::mem::size_of::<T>())?;
     let mut ret = Vec::with_capacity(l);
-    for _ in 0..l {
-        ret.push(T::deserialize(deserializer)?);
-    }
-    Ok(ret)
+    deserializer.nested(|deserializer| {
+        for _ in 0..l {
+            ret.push(T::deserialize(deserializer)?);
+        }
+        Ok(ret)
+    })
 }
 
 impl<T: Deserialize + Packed + 'static> Deserialize for Vec<T> {
@@ -6174,6 +7207,7 @@ impl<T: Deserialize + Packed + 'static> Deserialize for Vec<T> {
             let align = mem::align_of::<T>();
             let elem_size = mem::size_of::<T>();
             let num_elems = deserializer.read_usize()?;
+            deserializer.check_collection_len(num_elems, elem_size)?;
 
             if num_elems == 0 {
                 return Ok(Vec::new());
@@ -6270,11 +7304,14 @@ fn regular_deserialize_vecdeque<T: Deserialize>(
     deserializer: &mut Deserializer<impl Read>,
 ) -> Result<VecDeque<T>, SavefileError> {
     let l = deserializer.read_usize()?;
+    deserializer.check_collection_len(l, std::mem::size_of::<T>())?;
     let mut ret = VecDeque::with_capacity(l);
-    for _ in 0..l {
-        ret.push_back(T::deserialize(deserializer)?);
-    }
-    Ok(ret)
+    deserializer.nested(|deserializer| {
+        for _ in 0..l {
+            ret.push_back(T::deserialize(deserializer)?);
+        }
+        Ok(ret)
+    })
 }
 
 impl Packed for bool {
@@ -6773,6 +7810,7 @@ impl<const C: usize> Serialize for arrayvec::ArrayString<C> {
 impl<const C: usize> Deserialize for arrayvec::ArrayString<C> {
     fn deserialize(deserializer: &mut Deserializer<impl Read>) -> Result<Self, SavefileError> {
         let l = deserializer.read_usize()?;
+        deserializer.check_string_len(l)?;
         if l > C {
             return Err(SavefileError::ArrayvecCapacityError {
                 msg: format!("Deserialized data had length {}, but ArrayString capacity is {}", l, C),
@@ -6857,15 +7895,19 @@ impl<V: Deserialize + Packed, const C: usize> Deserialize for arrayvec::ArrayVec
     fn deserialize(deserializer: &mut Deserializer<impl Read>) -> Result<arrayvec::ArrayVec<V, C>, SavefileError> {
         let mut ret = arrayvec::ArrayVec::new();
         let l = deserializer.read_usize()?;
+        deserializer.check_collection_len(l, std::mem::size_of::<V>())?;
         if l > ret.capacity() {
             return Err(SavefileError::ArrayvecCapacityError {
                 msg: format!("ArrayVec with capacity {} can't hold {} items", ret.capacity(), l),
             });
         }
         if unsafe { V::repr_c_optimization_safe(deserializer.file_version) }.is_false() {
-            for _ in 0..l {
-                ret.push(V::deserialize(deserializer)?);
-            }
+            deserializer.nested(|deserializer| {
+                for _ in 0..l {
+                    ret.push(V::deserialize(deserializer)?);
+                }
+                Ok(())
+            })?;
         } else {
             unsafe {
                 let bytebuf = std::slice::from_raw_parts_mut(ret.as_mut_ptr() as *mut u8, std::mem::size_of::<V>() * l);
@@ -6891,7 +7933,7 @@ impl<T: Serialize + 'static> Serialize for Box<T> {
 }
 impl<T: Deserialize + 'static> Deserialize for Box<T> {
     fn deserialize(deserializer: &mut Deserializer<impl Read>) -> Result<Self, SavefileError> {
-        Ok(Box::new(T::deserialize(deserializer)?))
+        Ok(Box::new(deserializer.nested(T::deserialize)?))
     }
 }
 
@@ -6910,7 +7952,7 @@ impl<T: Serialize + 'static> Serialize for Rc<T> {
 }
 impl<T: Deserialize + 'static> Deserialize for Rc<T> {
     fn deserialize(deserializer: &mut Deserializer<impl Read>) -> Result<Self, SavefileError> {
-        Ok(Rc::new(T::deserialize(deserializer)?))
+        Ok(Rc::new(deserializer.nested(T::deserialize)?))
     }
 }
 
@@ -6927,11 +7969,9 @@ impl<T: Serialize + 'static> Serialize for Arc<T> {
 }
 impl<T: Deserialize + 'static> Deserialize for Arc<T> {
     fn deserialize(deserializer: &mut Deserializer<impl Read>) -> Result<Self, SavefileError> {
-        Ok(Arc::new(T::deserialize(deserializer)?))
+        Ok(Arc::new(deserializer.nested(T::deserialize)?))
     }
 }
-#[cfg(feature = "bzip2")]
-use bzip2::Compression;
 use std::any::Any;
 use std::cell::Cell;
 use std::cell::RefCell;
@@ -7808,6 +8848,7 @@ impl WithSchema for Duration {
                     name: "Duration".to_string(),
                     value: Box::new(Schema::Primitive(SchemaPrimitive::schema_u128)),
                     offset: None,
+                    default: None,
                 }
             ],
         })
@@ -7865,6 +8906,7 @@ impl WithSchema for SystemTime {
                     name: "SystemTimeDuration".to_string(),
                     value: Box::new(Schema::Primitive(SchemaPrimitive::schema_u128)),
                     offset: None,
+                    default: None,
                 }
             ],
         })
@@ -0,0 +1,261 @@
+use crate::{Deserialize, Deserializer, LoadOptions, PayloadLoader, SavefileError, Schema, WithSchema};
+use std::fmt::{Display, Formatter};
+use std::io::Read;
+use std::path::Path;
+
+/// Limits on the resources used when loading data, for loading files from untrusted sources.
+///
+/// A corrupt or hostile file can claim to contain a vector of billions of elements, or
+/// nest values of recursive types deep enough to overflow the stack. When a limit is exceeded,
+/// loading fails with [SavefileError::LimitExceeded] instead. All limits are disabled by default.
+///
+/// Use with [load_with_limits], [load_file_with_limits] or [Deserializer::set_limits].
+/// [crate::load_lazy_with_limits] and [crate::load_projection_with_limits] take limits as well.
+///
+/// The limits apply to strings, collections (`Vec`, `HashMap`, `VecDeque` etc.), `Box`,
+/// `Rc` and `Arc`, and to the schema stored in the file. Custom
+/// [Deserialize] implementations can take part using [Deserializer::check_collection_len]
+/// and [Deserializer::nested].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct LoadLimits {
+    /// Maximum total size in bytes of the elements of all strings and collections loaded
+    pub max_allocated_bytes: Option<u64>,
+    /// Maximum number of elements of a collection
+    pub max_collection_len: Option<usize>,
+    /// Maximum length of a string, in bytes
+    pub max_string_len: Option<usize>,
+    /// Maximum nesting depth. Each `Box`, `Rc`, `Arc` and collection containing a value
+    /// adds one level. Values can only be nested deeper than their type through these.
+    pub max_depth: Option<usize>,
+}
+
+impl LoadLimits {
+    /// No limits
+    pub fn new() -> LoadLimits {
+        LoadLimits::default()
+    }
+    /// Limit the total size of all strings and collections
+    pub fn with_max_allocated_bytes(self, max_allocated_bytes: u64) -> LoadLimits {
+        LoadLimits {
+            max_allocated_bytes: Some(max_allocated_bytes),
+            ..self
+        }
+    }
+    /// Limit the number of elements of each collection
+    pub fn with_max_collection_len(self, max_collection_len: usize) -> LoadLimits {
+        LoadLimits {
+            max_collection_len: Some(max_collection_len),
+            ..self
+        }
+    }
+    /// Limit the length of each string
+    pub fn with_max_string_len(self, max_string_len: usize) -> LoadLimits {
+        LoadLimits {
+            max_string_len: Some(max_string_len),
+            ..self
+        }
+    }
+    /// Limit how deeply values may be nested
+    pub fn with_max_depth(self, max_depth: usize) -> LoadLimits {
+        LoadLimits {
+            max_depth: Some(max_depth),
+            ..self
+        }
+    }
+}
+
+/// The limit which was exceeded, see [SavefileError::LimitExceeded]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LimitKind {
+    /// [LoadLimits::max_allocated_bytes]
+    AllocatedBytes,
+    /// [LoadLimits::max_collection_len]
+    CollectionLen,
+    /// [LoadLimits::max_string_len]
+    StringLen,
+    /// [LoadLimits::max_depth]
+    Depth,
+}
+
+impl Display for LimitKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LimitKind::AllocatedBytes => write!(f, "allocated bytes"),
+            LimitKind::CollectionLen => write!(f, "collection length"),
+            LimitKind::StringLen => write!(f, "string length"),
+            LimitKind::Depth => write!(f, "nesting depth"),
+        }
+    }
+}
+
+/// The limits of a deserializer, and how much of them has been used.
+/// Kept in [Deserializer::limit_state], and set using [Deserializer::set_limits].
+#[derive(Debug, Clone, Copy)]
+pub struct LimitState {
+    limits: LoadLimits,
+    allocated_bytes: u64,
+    depth: usize,
+}
+
+fn check(limit: LimitKind, value: u64, max: Option<u64>) -> Result<(), SavefileError> {
+    match max {
+        Some(max) if value > max => Err(SavefileError::LimitExceeded { limit, value, max }),
+        _ => Ok(()),
+    }
+}
+
+impl<TR: Read> Deserializer<'_, TR> {
+    /// Enforce the given limits for the rest of the deserialization
+    pub fn set_limits(&mut self, limits: LoadLimits) {
+        self.limit_state = Some(LimitState {
+            limits,
+            allocated_bytes: 0,
+            depth: 0,
+        });
+    }
+
+    /// The limits set by [Deserializer::set_limits], if any
+    pub fn limits(&self) -> Option<LoadLimits> {
+        self.limit_state.map(|state| state.limits)
+    }
+
+    fn allocate(state: &mut LimitState, bytes: u64) -> Result<(), SavefileError> {
+        state.allocated_bytes = state.allocated_bytes.saturating_add(bytes);
+        check(
+            LimitKind::AllocatedBytes,
+            state.allocated_bytes,
+            state.limits.max_allocated_bytes,
+        )
+    }
+
+    /// Check that a collection of `len` elements of `element_size` bytes each, read from
+    /// the file, is within the limits. Call this before allocating the collection.
+    pub fn check_collection_len(&mut self, len: usize, element_size: usize) -> Result<(), SavefileError> {
+        if let Some(state) = self.limit_state.as_mut() {
+            check(
+                LimitKind::CollectionLen,
+                len as u64,
+                state.limits.max_collection_len.map(|x| x as u64),
+            )?;
+            Self::allocate(state, (len as u64).saturating_mul(element_size as u64))?;
+        }
+        Ok(())
+    }
+
+    /// Check that `bytes` bytes of raw data, read from the file, are within the limits
+    pub(crate) fn check_allocated_bytes(&mut self, bytes: u64) -> Result<(), SavefileError> {
+        if let Some(state) = self.limit_state.as_mut() {
+            Self::allocate(state, bytes)?;
+        }
+        Ok(())
+    }
+
+    /// Check that a string of `len` bytes, read from the file, is within the limits.
+    /// Call this before allocating the string.
+    pub fn check_string_len(&mut self, len: usize) -> Result<(), SavefileError> {
+        if let Some(state) = self.limit_state.as_mut() {
+            check(
+                LimitKind::StringLen,
+                len as u64,
+                state.limits.max_string_len.map(|x| x as u64),
+            )?;
+            Self::allocate(state, len as u64)?;
+        }
+        Ok(())
+    }
+
+    /// Run `deserialize` one level deeper, failing if that exceeds [LoadLimits::max_depth].
+    /// Collections, `Box`, `Rc` and `Arc` deserialize their contents using this, so recursive
+    /// types can't overflow the stack when a depth limit is set.
+    pub fn nested<T>(
+        &mut self,
+        deserialize: impl FnOnce(&mut Self) -> Result<T, SavefileError>,
+    ) -> Result<T, SavefileError> {
+        match self.limit_state.as_mut() {
+            Some(state) if state.limits.max_depth.is_some() => {
+                check(
+                    LimitKind::Depth,
+                    state.depth as u64 + 1,
+                    state.limits.max_depth.map(|x| x as u64),
+                )?;
+                state.depth += 1;
+                let result = deserialize(self);
+                if let Some(state) = self.limit_state.as_mut() {
+                    state.depth -= 1;
+                }
+                result
+            }
+            _ => deserialize(self),
+        }
+    }
+}
+
+/// Loads using another loader, enforcing limits on both the schema and the data
+pub(crate) struct LimitedLoader<L> {
+    pub(crate) loader: L,
+    pub(crate) limits: LoadLimits,
+}
+
+impl<L: PayloadLoader> PayloadLoader for LimitedLoader<L> {
+    type Output = L::Output;
+    fn reads_schema(&self) -> bool {
+        self.loader.reads_schema()
+    }
+    fn limits(&self) -> Option<LoadLimits> {
+        Some(self.limits)
+    }
+    fn load_data(
+        self,
+        deserializer: &mut Deserializer<impl Read>,
+        file_schema: Option<Schema>,
+    ) -> Result<L::Output, SavefileError> {
+        self.loader.load_data(deserializer, file_schema)
+    }
+}
+
+/// Load the payload of a file using `loader`, enforcing `limits`, if given
+pub(crate) fn load_payload_limited<L: PayloadLoader>(
+    reader: &mut impl Read,
+    savefile_lib_version: u16,
+    file_ver: u32,
+    loader: L,
+    limits: Option<LoadLimits>,
+) -> Result<L::Output, SavefileError> {
+    match limits {
+        Some(limits) => Deserializer::<_>::load_payload_with(
+            reader,
+            savefile_lib_version,
+            file_ver,
+            LimitedLoader { loader, limits },
+        ),
+        None => Deserializer::<_>::load_payload_with(reader, savefile_lib_version, file_ver, loader),
+    }
+}
+
+/// Like [crate::load], but fails with [SavefileError::LimitExceeded] if the data
+/// exceeds the given limits. Use this when loading files from untrusted sources.
+pub fn load_with_limits<T: WithSchema + Deserialize>(
+    reader: &mut impl Read,
+    version: u32,
+    limits: &LoadLimits,
+) -> Result<T, SavefileError> {
+    LoadOptions::new(version).with_limits(*limits).load(reader)
+}
+
+/// Like [load_with_limits], but loads from the given file.
+pub fn load_file_with_limits<T: WithSchema + Deserialize, P: AsRef<Path>>(
+    path: P,
+    version: u32,
+    limits: &LoadLimits,
+) -> Result<T, SavefileError> {
+    LoadOptions::new(version).with_limits(*limits).load_file(path)
+}
+
+/// Like [load_with_limits], but loads from the given buffer.
+pub fn load_from_mem_with_limits<T: WithSchema + Deserialize>(
+    input: &[u8],
+    version: u32,
+    limits: &LoadLimits,
+) -> Result<T, SavefileError> {
+    LoadOptions::new(version).with_limits(*limits).load_from_mem(input)
+}
@@ -0,0 +1,297 @@
+use crate::limits::LimitedLoader;
+#[cfg(feature = "ring")]
+use crate::KdfParams;
+use crate::{
+    read_file_header, ChecksumAlgorithm, CompressionOptions, Deserialize, Deserializer, FileMetadata, LoadLimits,
+    SavefileError, Schema, Serialize, Serializer, TypedLoader, WithSchema, WithSchemaContext,
+};
+use std::fmt::{Debug, Formatter};
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::marker::PhantomData;
+use std::path::Path;
+
+/// How to save data: the version, whether to include the schema, and the compression,
+/// checksum, metadata and encryption to use. The options can be combined freely.
+///
+/// The data can be saved to a writer, a file or a `Vec<u8>`, and loaded using [LoadOptions]
+/// with matching options for the schema and the encryption. The other options are
+/// detected when loading.
+///
+/// ```
+/// use savefile::{ChecksumAlgorithm, LoadOptions, SaveOptions};
+/// let data = vec![1u32, 2, 3];
+/// let saved = SaveOptions::new(0)
+///     .without_schema()
+///     .with_checksum(ChecksumAlgorithm::Crc32c)
+///     .save_to_mem(&data)
+///     .unwrap();
+/// let loaded: Vec<u32> = LoadOptions::new(0).without_schema().load_from_mem(&saved).unwrap();
+/// assert_eq!(loaded, data);
+/// ```
+#[derive(Clone)]
+pub struct SaveOptions {
+    version: u32,
+    with_schema: bool,
+    compression: Option<CompressionOptions>,
+    checksum: Option<ChecksumAlgorithm>,
+    metadata: Option<FileMetadata>,
+    #[cfg(feature = "ring")]
+    password: Option<(String, KdfParams)>,
+}
+
+impl SaveOptions {
+    /// Save data whose current version is `version`, with a schema, and nothing else
+    pub fn new(version: u32) -> SaveOptions {
+        SaveOptions {
+            version,
+            with_schema: true,
+            compression: None,
+            checksum: None,
+            metadata: None,
+            #[cfg(feature = "ring")]
+            password: None,
+        }
+    }
+    /// Don't save the schema. See [crate::save_noschema] for the consequences.
+    pub fn without_schema(self) -> SaveOptions {
+        SaveOptions {
+            with_schema: false,
+            ..self
+        }
+    }
+    /// Compress the schema and data
+    pub fn with_compression(self, compression: CompressionOptions) -> SaveOptions {
+        SaveOptions {
+            compression: Some(compression),
+            ..self
+        }
+    }
+    /// Add a checksum of the contents, which is verified when loading
+    pub fn with_checksum(self, checksum: ChecksumAlgorithm) -> SaveOptions {
+        SaveOptions {
+            checksum: Some(checksum),
+            ..self
+        }
+    }
+    /// Store metadata in the header, see [crate::read_header]
+    pub fn with_metadata(self, metadata: FileMetadata) -> SaveOptions {
+        SaveOptions {
+            metadata: Some(metadata),
+            ..self
+        }
+    }
+    /// Encrypt everything, using a key derived from `password`. The result is in the format
+    /// written by [crate::save_encrypted_file_with_kdf]. Load it using [LoadOptions::with_password].
+    #[cfg(feature = "ring")]
+    pub fn with_password(self, password: &str, kdf: KdfParams) -> SaveOptions {
+        SaveOptions {
+            password: Some((password.to_string(), kdf)),
+            ..self
+        }
+    }
+
+    /// Write `data` to `writer`
+    pub fn save<T: WithSchema + Serialize>(&self, writer: &mut impl Write, data: &T) -> Result<(), SavefileError> {
+        let schema = self
+            .with_schema
+            .then(|| T::schema(self.version, &mut WithSchemaContext::new()));
+        self.save_with_schema(writer, data, schema)
+    }
+
+    /// Write `data` to the file at `path`, replacing it if it exists
+    pub fn save_file<T: WithSchema + Serialize, P: AsRef<Path>>(&self, path: P, data: &T) -> Result<(), SavefileError> {
+        let mut f = BufWriter::new(File::create(path)?);
+        self.save(&mut f, data)?;
+        f.flush()?;
+        Ok(())
+    }
+
+    /// Write `data` to a new `Vec<u8>`
+    pub fn save_to_mem<T: WithSchema + Serialize>(&self, data: &T) -> Result<Vec<u8>, SavefileError> {
+        let mut retval = Vec::new();
+        self.save(&mut retval, data)?;
+        Ok(retval)
+    }
+
+    /// Write `data` with the given schema, which is `None` if no schema should be saved
+    pub(crate) fn save_with_schema<T: Serialize>(
+        &self,
+        writer: &mut impl Write,
+        data: &T,
+        schema: Option<Schema>,
+    ) -> Result<(), SavefileError> {
+        #[cfg(feature = "ring")]
+        if let Some((password, kdf)) = &self.password {
+            return crate::crypto::write_with_password(writer, password, *kdf, |mut writer| {
+                self.save_unencrypted(&mut writer, data, schema)
+            });
+        }
+        self.save_unencrypted(writer, data, schema)
+    }
+
+    fn save_unencrypted<T: Serialize>(
+        &self,
+        writer: &mut impl Write,
+        data: &T,
+        schema: Option<Schema>,
+    ) -> Result<(), SavefileError> {
+        Serializer::save_impl(
+            writer,
+            self.version,
+            data,
+            schema,
+            self.compression,
+            self.checksum,
+            self.metadata.as_ref(),
+        )
+    }
+}
+
+impl Debug for SaveOptions {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let mut debug = f.debug_struct("SaveOptions");
+        debug
+            .field("version", &self.version)
+            .field("with_schema", &self.with_schema)
+            .field("compression", &self.compression)
+            .field("checksum", &self.checksum)
+            .field("metadata", &self.metadata);
+        #[cfg(feature = "ring")]
+        debug.field("encrypted", &self.password.is_some());
+        debug.finish()
+    }
+}
+
+/// How to load data: the version in memory, whether the data has a schema, its
+/// encryption, and the [LoadLimits] to enforce. See [SaveOptions].
+///
+/// Compression, checksums and metadata are detected automatically.
+#[derive(Clone)]
+pub struct LoadOptions {
+    version: u32,
+    with_schema: bool,
+    limits: Option<LoadLimits>,
+    #[cfg(feature = "ring")]
+    password: Option<String>,
+}
+
+impl LoadOptions {
+    /// Load data whose current version in memory is `version`, saved with a schema
+    pub fn new(version: u32) -> LoadOptions {
+        LoadOptions {
+            version,
+            with_schema: true,
+            limits: None,
+            #[cfg(feature = "ring")]
+            password: None,
+        }
+    }
+    /// Load data saved without a schema
+    pub fn without_schema(self) -> LoadOptions {
+        LoadOptions {
+            with_schema: false,
+            ..self
+        }
+    }
+    /// Fail with [SavefileError::LimitExceeded] if the data exceeds the given limits
+    pub fn with_limits(self, limits: LoadLimits) -> LoadOptions {
+        LoadOptions {
+            limits: Some(limits),
+            ..self
+        }
+    }
+    /// Decrypt data saved using [SaveOptions::with_password] or [crate::save_encrypted_file]
+    #[cfg(feature = "ring")]
+    pub fn with_password(self, password: &str) -> LoadOptions {
+        LoadOptions {
+            password: Some(password.to_string()),
+            ..self
+        }
+    }
+
+    /// Load an instance of T from `reader`
+    ///
+    /// The reader may contain more data after the saved data, which is not read.
+    pub fn load<T: WithSchema + Deserialize>(&self, reader: &mut impl Read) -> Result<T, SavefileError> {
+        self.load_input(reader, false)
+    }
+
+    /// Load an instance of T from the file at `path`
+    pub fn load_file<T: WithSchema + Deserialize, P: AsRef<Path>>(&self, path: P) -> Result<T, SavefileError> {
+        let mut f = BufReader::new(File::open(path)?);
+        self.load_input(&mut f, true)
+    }
+
+    /// Load an instance of T from `input`
+    pub fn load_from_mem<T: WithSchema + Deserialize>(&self, input: &[u8]) -> Result<T, SavefileError> {
+        let mut input = input;
+        self.load_input(&mut input, true)
+    }
+
+    /// Load an instance of T. `whole_input` is true if the data extends to the end of
+    /// `reader`, see [Deserializer::load_payload_from].
+    fn load_input<T: WithSchema + Deserialize>(
+        &self,
+        reader: &mut impl Read,
+        whole_input: bool,
+    ) -> Result<T, SavefileError> {
+        let expected_schema = self
+            .with_schema
+            .then_some(|version| T::schema(version, &mut WithSchemaContext::new()));
+        self.load_with_schema(reader, expected_schema, whole_input)
+    }
+
+    /// Load an instance of T, checking the schema of the file against `expected_schema`,
+    /// if given. If it is `None`, the file must not have a schema.
+    pub(crate) fn load_with_schema<T: Deserialize>(
+        &self,
+        reader: &mut impl Read,
+        expected_schema: Option<impl FnOnce(u32) -> Schema>,
+        whole_input: bool,
+    ) -> Result<T, SavefileError> {
+        #[cfg(feature = "ring")]
+        if let Some(password) = &self.password {
+            return crate::crypto::read_with_password(reader, password, |mut reader| {
+                self.load_unencrypted(&mut reader, expected_schema, whole_input)
+            });
+        }
+        self.load_unencrypted(reader, expected_schema, whole_input)
+    }
+
+    fn load_unencrypted<T: Deserialize>(
+        &self,
+        reader: &mut impl Read,
+        expected_schema: Option<impl FnOnce(u32) -> Schema>,
+        whole_input: bool,
+    ) -> Result<T, SavefileError> {
+        let (savefile_lib_version, file_ver) = read_file_header(reader, self.version)?;
+        let loader = TypedLoader::<T, _> {
+            expected_schema,
+            phantom: PhantomData,
+        };
+        match self.limits {
+            Some(limits) => Deserializer::<_>::load_payload_from(
+                reader,
+                savefile_lib_version,
+                file_ver,
+                LimitedLoader { loader, limits },
+                whole_input,
+            ),
+            None => Deserializer::<_>::load_payload_from(reader, savefile_lib_version, file_ver, loader, whole_input),
+        }
+    }
+}
+
+impl Debug for LoadOptions {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let mut debug = f.debug_struct("LoadOptions");
+        debug
+            .field("version", &self.version)
+            .field("with_schema", &self.with_schema)
+            .field("limits", &self.limits);
+        #[cfg(feature = "ring")]
+        debug.field("encrypted", &self.password.is_some());
+        debug.finish()
+    }
+}
@@ -1,12 +1,15 @@
 pub use {
     super::deserialize_slice_as_vec, super::get_schema, super::introspect_item, super::load, super::load_file,
-    super::load_file_noschema, super::load_from_mem, super::load_noschema, super::save, super::save_file,
-    super::save_file_noschema, super::save_noschema, super::save_to_mem, super::AbiRemoved, super::Canary1,
-    super::Deserialize, super::Deserializer, super::Field, super::Introspect, super::IntrospectItem,
+    super::load_file_noschema, super::load_from_mem, super::load_from_mem_borrowed, super::load_noschema, super::save,
+    super::save_file, super::save_file_atomic, super::save_file_noschema, super::save_noschema, super::save_to_mem,
+    super::AbiRemoved, super::ArchiveReader, super::ArchiveWriter, super::Canary1, super::ChecksumAlgorithm,
+    super::CompressionCodec, super::CompressionOptions, super::Deserialize, super::DeserializeBorrowed,
+    super::Deserializer, super::Field, super::FieldDefault, super::Introspect, super::IntrospectItem,
     super::IntrospectedElementKey, super::IntrospectionResult, super::Introspector, super::IntrospectorNavCommand,
-    super::IsPacked, super::Packed, super::Removed, super::SavefileError, super::Schema, super::SchemaEnum,
-    super::SchemaPrimitive, super::SchemaStruct, super::Serialize, super::Serializer, super::Variant,
-    super::WithSchema, super::WithSchemaContext,
+    super::IsPacked, super::Lazy, super::LoadOptions, super::Packed, super::Removed, super::SaveOptions,
+    super::SavefileError, super::Schema, super::SchemaEnum, super::SchemaPrimitive, super::SchemaStruct,
+    super::Serialize, super::Serializer, super::StreamReader, super::StreamWriter, super::Variant, super::WithSchema,
+    super::WithSchemaContext,
 };
 
 pub use byteorder::{LittleEndian, ReadBytesExt};
@@ -16,7 +19,20 @@ pub use memoffset::span_of;
 pub use {super::AbiMethod, super::AbiMethodArgument, super::AbiMethodInfo, super::AbiTraitDefinition};
 
 #[cfg(feature = "ring")]
-pub use super::{load_encrypted_file, save_encrypted_file, CryptoReader, CryptoWriter};
+pub use super::{
+    load_encrypted, load_encrypted_file, load_encrypted_from_mem, save_encrypted, save_encrypted_file,
+    save_encrypted_file_atomic, save_encrypted_file_with_kdf, save_encrypted_to_mem, CryptoReader, CryptoWriter,
+    KdfParams, KeyProvider,
+};
+
+#[cfg(feature = "ring")]
+pub use super::{load_file_signed, load_signed, save_file_signed, save_signed, SigningKeyPair, SigningPublicKey};
+
+#[cfg(feature = "x25519")]
+pub use super::{load_encrypted_as_recipient, save_encrypted_for_recipients, RecipientPublicKey, RecipientSecretKey};
+
+#[cfg(feature = "tokio")]
+pub use super::{load_async, save_async, save_compressed_async};
 
 #[cfg(feature = "derive")]
 pub use savefile_derive::Packed;
@@ -0,0 +1,219 @@
+use crate::limits::load_payload_limited;
+use crate::value::{may_recurse_to_root, skip_child, SchemaPath};
+use crate::{
+    get_schema, read_file_header, Deserialize, Deserializer, LimitKind, LoadLimits, PayloadLoader, SavefileError,
+    Schema, SchemaDiff, WithSchema,
+};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::marker::PhantomData;
+use std::path::Path;
+
+/// Reads from a reader, keeping a copy of the data read
+struct RecordingReader<'a, R> {
+    reader: &'a mut R,
+    data: Vec<u8>,
+    /// The most data which may be kept, see [LoadLimits::max_allocated_bytes]
+    max_len: Option<u64>,
+    /// The length the data would have had, if reading failed since it exceeded `max_len`
+    exceeded: Option<u64>,
+}
+
+impl<R: Read> Read for RecordingReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let count = self.reader.read(buf)?;
+        if let Some(max_len) = self.max_len {
+            let len = (self.data.len() + count) as u64;
+            if len > max_len {
+                self.exceeded = Some(len);
+                return Err(std::io::Error::other("The wanted fields exceed the allocation limit"));
+            }
+        }
+        self.data.extend_from_slice(&buf[..count]);
+        Ok(count)
+    }
+}
+
+fn struct_fields<'s>(schema: &'s Schema, what: &str) -> Result<&'s [crate::Field], SavefileError> {
+    match schema {
+        Schema::Struct(s) => Ok(&s.fields),
+        other => Err(SavefileError::IncompatibleSchema {
+            message: format!(
+                "Only structs can be partially loaded, but the {} is {}.",
+                what,
+                other.top_level_description()
+            ),
+        }),
+    }
+}
+
+/// Loads some of the fields of a struct, see [load_projection]
+struct ProjectionLoader<T> {
+    phantom: PhantomData<T>,
+}
+
+impl<T: WithSchema + Deserialize + 'static> PayloadLoader for ProjectionLoader<T> {
+    type Output = T;
+    fn reads_schema(&self) -> bool {
+        true
+    }
+    fn load_data(
+        self,
+        deserializer: &mut Deserializer<impl Read>,
+        file_schema: Option<Schema>,
+    ) -> Result<T, SavefileError> {
+        let file_schema = file_schema.ok_or_else(|| SavefileError::GeneralError {
+            msg: "File has no schema.".into(),
+        })?;
+        let file_ver = deserializer.file_version;
+        let memory_schema = get_schema::<T>(file_ver);
+        let file_fields = struct_fields(&file_schema, "data in the file")?;
+        let memory_fields = struct_fields(&memory_schema, "type in memory")?;
+
+        // For each field in the file, the index of the same field in memory, if it is wanted
+        let mut wanted = vec![None; file_fields.len()];
+        for (memory_index, field) in memory_fields.iter().enumerate() {
+            let Some(file_index) = file_fields.iter().position(|x| x.name == field.name) else {
+                return Err(SavefileError::IncompatibleSchema {
+                    message: format!("The data in the file has no field '{}'.", field.name),
+                });
+            };
+            let diff = SchemaDiff::new(&field.value, &file_fields[file_index].value);
+            if !diff.is_empty() {
+                return Err(SavefileError::IncompatibleSchema {
+                    message: format!(
+                        "Field '{}' differs from the field in the file, for version {}:\n{}",
+                        field.name, file_ver, diff
+                    ),
+                });
+            }
+            // The field is compared on its own, so a recursion to the struct itself would be
+            // compared as equal, even though the structs differ.
+            let mut file_path = SchemaPath::new(&file_schema);
+            let mut memory_path = SchemaPath::new(&memory_schema);
+            if may_recurse_to_root(&mut file_path, &file_fields[file_index].value, (0, file_index), false)
+                || may_recurse_to_root(&mut memory_path, &field.value, (0, memory_index), false)
+            {
+                return Err(SavefileError::IncompatibleSchema {
+                    message: format!(
+                        "Field '{}' contains the struct itself, so it cannot be partially loaded.",
+                        field.name
+                    ),
+                });
+            }
+            wanted[file_index] = Some(memory_index);
+        }
+
+        // The wanted fields are kept in memory, so they count towards the allocation limit
+        let limits = deserializer.limits();
+        let max_allocated_bytes = limits.and_then(|x| x.max_allocated_bytes);
+        let mut recorded = 0u64;
+        let mut path = SchemaPath::new(&file_schema);
+        let mut field_data = vec![Vec::new(); memory_fields.len()];
+        for (index, field) in file_fields.iter().enumerate() {
+            match wanted[index] {
+                Some(memory_index) => {
+                    let mut recorder = RecordingReader {
+                        reader: &mut *deserializer.reader,
+                        data: Vec::new(),
+                        max_len: max_allocated_bytes.map(|max| max - recorded),
+                        exceeded: None,
+                    };
+                    let mut recording_deserializer = Deserializer {
+                        reader: &mut recorder,
+                        file_version: file_ver,
+                        ephemeral_state: HashMap::new(),
+                        limit_state: None,
+                    };
+                    let result = skip_child(&mut path, &mut recording_deserializer, &field.value, (0, index), false);
+                    if let (Some(len), Some(max)) = (recorder.exceeded, max_allocated_bytes) {
+                        return Err(SavefileError::LimitExceeded {
+                            limit: LimitKind::AllocatedBytes,
+                            value: recorded + len,
+                            max,
+                        });
+                    }
+                    result?;
+                    recorded += recorder.data.len() as u64;
+                    field_data[memory_index] = recorder.data;
+                }
+                None => skip_child(&mut path, deserializer, &field.value, (0, index), false)?,
+            }
+        }
+
+        // The wanted fields, in the order of T
+        let data = field_data.concat();
+        let mut deserializer = Deserializer {
+            reader: &mut &data[..],
+            file_version: file_ver,
+            ephemeral_state: HashMap::new(),
+            limit_state: None,
+        };
+        if let Some(limits) = limits {
+            deserializer.set_limits(limits);
+        }
+        T::deserialize(&mut deserializer)
+    }
+}
+
+/// Load some of the fields of the struct stored in a file, skipping the others.
+///
+/// `T` must be a struct with a subset of the fields of the struct in the file, with the same
+/// names and types, but possibly in a different order. Only those fields are deserialized.
+/// The other fields are skipped using the schema stored in the file, see
+/// [Deserializer::skip_value], which is much faster than loading them, and does not allocate
+/// memory for them.
+///
+/// This is useful for reading a small part of a large file, such as the name and level of a
+/// saved game, without loading the world it contains.
+///
+/// `version` and `savefile_versions` attributes work like for [crate::load]. The fields of `T`
+/// for the version of the file must be in the file. The file must have been saved with a schema,
+/// which is the case unless one of the `*_noschema` functions was used.
+pub fn load_projection<T: WithSchema + Deserialize + 'static>(
+    reader: &mut impl Read,
+    version: u32,
+) -> Result<T, SavefileError> {
+    load_projection_impl(reader, version, None)
+}
+
+/// Like [load_projection], but fails with [SavefileError::LimitExceeded] if the data exceeds
+/// the given limits. The serialized data of the wanted fields, which is kept in memory until
+/// they are deserialized, counts towards [LoadLimits::max_allocated_bytes].
+pub fn load_projection_with_limits<T: WithSchema + Deserialize + 'static>(
+    reader: &mut impl Read,
+    version: u32,
+    limits: &LoadLimits,
+) -> Result<T, SavefileError> {
+    load_projection_impl(reader, version, Some(*limits))
+}
+
+fn load_projection_impl<T: WithSchema + Deserialize + 'static>(
+    reader: &mut impl Read,
+    version: u32,
+    limits: Option<LoadLimits>,
+) -> Result<T, SavefileError> {
+    let (savefile_lib_version, file_ver) = read_file_header(reader, version)?;
+    let loader = ProjectionLoader { phantom: PhantomData };
+    load_payload_limited(reader, savefile_lib_version, file_ver, loader, limits)
+}
+
+/// Like [load_projection], but loads from the given file.
+pub fn load_file_projection<T: WithSchema + Deserialize + 'static, P: AsRef<Path>>(
+    path: P,
+    version: u32,
+) -> Result<T, SavefileError> {
+    let mut f = BufReader::new(File::open(path)?);
+    load_projection(&mut f, version)
+}
+
+/// Like [load_projection_with_limits], but loads from the given file.
+pub fn load_file_projection_with_limits<T: WithSchema + Deserialize + 'static, P: AsRef<Path>>(
+    path: P,
+    version: u32,
+    limits: &LoadLimits,
+) -> Result<T, SavefileError> {
+    let mut f = BufReader::new(File::open(path)?);
+    load_projection_with_limits(&mut f, version, limits)
+}
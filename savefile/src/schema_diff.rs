@@ -0,0 +1,498 @@
+use crate::{AbiTraitDefinition, Field, Schema, SchemaArray, SchemaEnum, SchemaPrimitive, SchemaStruct};
+use std::fmt::{Display, Formatter};
+
+/// One step of the path from the root of a schema to a [SchemaDifference].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum SchemaPathElement {
+    /// A struct, with the name it has in the file schema
+    Struct(String),
+    /// An enum, with the name it has in the file schema
+    Enum(String),
+    /// A variant of the enclosing enum, with the name it has in the file schema
+    Variant(String),
+    /// A field of the enclosing struct or enum variant, with the name it has in the file schema
+    Field(String),
+    /// The elements of a vector, or other collection
+    Element,
+    /// The elements of an array with the given length (in memory)
+    ArrayElement(usize),
+    /// The value of an Option
+    OptionValue,
+    /// An argument of a method of a trait. Paths within arguments start at this element.
+    MethodArgument {
+        /// The method name
+        method: String,
+        /// The index of the argument
+        index: usize,
+    },
+}
+
+/// The kind of a [SchemaDifference]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum SchemaDifferenceKind {
+    /// The schemas describe different types. For example a struct and a vector,
+    /// two different primitives, or recursion to different levels.
+    TypeChange,
+    /// A struct or enum variant has a different number of fields
+    FieldCountChange {
+        /// Number of fields in memory
+        memory: usize,
+        /// Number of fields in the file
+        file: usize,
+    },
+    /// A field exists in memory, but not in the file
+    FieldAdded(String),
+    /// A field exists in the file, but not in memory
+    FieldRemoved(String),
+    /// An array has a different length
+    ArrayLengthChange {
+        /// Length in memory
+        memory: usize,
+        /// Length in the file
+        file: usize,
+    },
+    /// An enum uses a different number of bytes for its discriminant
+    DiscriminantSizeChange {
+        /// Discriminant size in memory
+        memory: u8,
+        /// Discriminant size in the file
+        file: u8,
+    },
+    /// An enum has a different number of variants
+    VariantCountChange {
+        /// Number of variants in memory
+        memory: usize,
+        /// Number of variants in the file
+        file: usize,
+    },
+    /// The variant at the given position has a different name
+    VariantRenamed {
+        /// Position of the variant
+        index: usize,
+        /// Name in memory
+        memory: String,
+        /// Name in the file
+        file: String,
+    },
+    /// A variant has a different discriminant
+    VariantDiscriminantChange {
+        /// Position of the variant in memory
+        index: usize,
+        /// Discriminant in memory
+        memory: u8,
+        /// Discriminant in the file
+        file: u8,
+    },
+    /// A variant exists in memory, but not in the file
+    VariantAdded(String),
+    /// A variant exists in the file, but not in memory
+    VariantRemoved(String),
+    /// A trait method has a different number of arguments
+    ArgumentCountChange {
+        /// The method name
+        method: String,
+        /// Number of arguments in memory
+        memory: usize,
+        /// Number of arguments in the file
+        file: usize,
+    },
+    /// Both schemas are [Schema::Undefined], which cannot be compared
+    Undefined,
+}
+
+/// A single difference between two schemas.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SchemaDifference {
+    /// Where the difference is, starting from the root of the schemas
+    pub path: Vec<SchemaPathElement>,
+    /// What differs
+    pub kind: SchemaDifferenceKind,
+    /// The in-memory schema node at `path`. For differences in fields or variants, this is
+    /// the enclosing struct or enum.
+    pub memory: Schema,
+    /// The file schema node at `path`
+    pub file: Schema,
+}
+
+impl SchemaDifference {
+    /// The location of the difference as a string, like "./Level/tiles/*".
+    /// `root` is the string for the root of the schemas.
+    pub fn path_string(&self, root: &str) -> String {
+        let mut path = root.to_string();
+        for element in &self.path {
+            match element {
+                SchemaPathElement::Struct(name) | SchemaPathElement::Variant(name) | SchemaPathElement::Field(name) => {
+                    path.push('/');
+                    path.push_str(name);
+                }
+                SchemaPathElement::Enum(name) => path.push_str(name),
+                SchemaPathElement::Element => path.push_str("/*"),
+                SchemaPathElement::ArrayElement(count) => path.push_str(&format!("/[{}]", count)),
+                SchemaPathElement::OptionValue => path.push_str("/?"),
+                SchemaPathElement::MethodArgument { method, index } => {
+                    path = format!("{}(arg #{})", method, index);
+                }
+            }
+        }
+        path
+    }
+
+    /// A human-readable description of the difference, with the location given
+    /// by [SchemaDifference::path_string].
+    pub fn message(&self, root: &str) -> String {
+        let path = self.path_string(root);
+        let description = match &self.kind {
+            SchemaDifferenceKind::TypeChange => match (&self.memory, &self.file) {
+                (Schema::Primitive(a), Schema::Primitive(b)) => format!(
+                    "Application protocol has datatype {}, but disk format has {}",
+                    a.name(),
+                    b.name()
+                ),
+                (Schema::Custom(a), Schema::Custom(b)) => format!(
+                    "Application protocol has datatype Custom({}), but foreign format has Custom({})",
+                    a, b
+                ),
+                (Schema::Trait(true, _), Schema::Trait(false, _))
+                | (Schema::FnClosure(true, _), Schema::FnClosure(false, _)) => {
+                    "Application protocol uses FnMut, but foreign format has Fn.".to_string()
+                }
+                (Schema::Trait(false, _), Schema::Trait(true, _))
+                | (Schema::FnClosure(false, _), Schema::FnClosure(true, _)) => {
+                    "Application protocol uses Fn, but foreign format uses FnMut.".to_string()
+                }
+                (Schema::Recursion(a), Schema::Recursion(b)) => format!(
+                    "Application protocol uses recursion up {} levels, but foreign format uses {}.",
+                    a, b
+                ),
+                (a, b) => format!(
+                    "In memory schema: {}, file schema: {}",
+                    a.top_level_description(),
+                    b.top_level_description()
+                ),
+            },
+            SchemaDifferenceKind::FieldCountChange { memory, file } => {
+                let (structure, extra_memory, extra_file) = match (&self.memory, &self.file) {
+                    (Schema::Struct(a), Schema::Struct(b)) => (
+                        "struct",
+                        format!(" (struct {})", a.dbg_name),
+                        format!(" (struct {})", b.dbg_name),
+                    ),
+                    _ => ("enum", String::new(), String::new()),
+                };
+                format!(
+                    "In memory {}{} has {} fields, disk format{} has {} fields.",
+                    structure, extra_memory, memory, extra_file, file
+                )
+            }
+            SchemaDifferenceKind::FieldAdded(name) => {
+                format!("Field {} exists in memory, but not in disk format.", name)
+            }
+            SchemaDifferenceKind::FieldRemoved(name) => {
+                format!("Field {} exists in disk format, but not in memory.", name)
+            }
+            SchemaDifferenceKind::ArrayLengthChange { memory, file } => format!(
+                "In memory array has length {}, but disk format length {}.",
+                memory, file
+            ),
+            SchemaDifferenceKind::DiscriminantSizeChange { memory, file } => format!(
+                "In memory enum has a representation with {} bytes for the discriminant, but disk format has {}.",
+                memory, file
+            ),
+            SchemaDifferenceKind::VariantCountChange { memory, file } => format!(
+                "In memory enum has {} variants, but disk format has {} variants.",
+                memory, file
+            ),
+            SchemaDifferenceKind::VariantRenamed { index, memory, file } => format!(
+                "Enum variant #{} in memory is called {}, but in disk format it is called {}",
+                index, memory, file
+            ),
+            SchemaDifferenceKind::VariantDiscriminantChange { index, memory, file } => format!(
+                "Enum variant #{} in memory has discriminant {}, but in disk format it has {}",
+                index, memory, file
+            ),
+            SchemaDifferenceKind::VariantAdded(name) => {
+                format!("Enum variant {} exists in memory, but not in disk format.", name)
+            }
+            SchemaDifferenceKind::VariantRemoved(name) => {
+                format!("Enum variant {} exists in disk format, but not in memory.", name)
+            }
+            SchemaDifferenceKind::ArgumentCountChange { method, memory, file } => format!(
+                "Application protocol method {} has {} args, but foreign version has {}.",
+                method, memory, file
+            ),
+            SchemaDifferenceKind::Undefined => "Undefined schema encountered.".to_string(),
+        };
+        format!("At location [{}]: {}", path, description)
+    }
+}
+
+impl Display for SchemaDifference {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message("."))
+    }
+}
+
+/// All differences between an in-memory schema and a file (or foreign) schema.
+///
+/// Unlike [crate::diff_schema], which only describes the first difference, this lists
+/// every difference found. Where the structure differs too much to compare further
+/// (for example a struct in memory and a vector in the file), the nodes below are not compared.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct SchemaDiff {
+    /// The differences, in the order they were found
+    pub differences: Vec<SchemaDifference>,
+}
+
+impl SchemaDiff {
+    /// Compare the schema `memory`, used in memory, with `file`.
+    /// This does not care about memory layout, only serializability.
+    pub fn new(memory: &Schema, file: &Schema) -> SchemaDiff {
+        let mut differ = Differ {
+            path: Vec::new(),
+            differences: Vec::new(),
+        };
+        differ.diff(memory, file);
+        SchemaDiff {
+            differences: differ.differences,
+        }
+    }
+
+    /// True if the schemas are equivalent
+    pub fn is_empty(&self) -> bool {
+        self.differences.is_empty()
+    }
+}
+
+impl Display for SchemaDiff {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for difference in &self.differences {
+            writeln!(f, "{}", difference)?;
+        }
+        Ok(())
+    }
+}
+
+struct Differ {
+    path: Vec<SchemaPathElement>,
+    differences: Vec<SchemaDifference>,
+}
+
+impl Differ {
+    fn report(&mut self, kind: SchemaDifferenceKind, memory: &Schema, file: &Schema) {
+        self.differences.push(SchemaDifference {
+            path: self.path.clone(),
+            kind,
+            memory: memory.clone(),
+            file: file.clone(),
+        });
+    }
+
+    fn within(&mut self, element: SchemaPathElement, diff: impl FnOnce(&mut Differ)) {
+        self.path.push(element);
+        diff(self);
+        self.path.pop();
+    }
+
+    fn diff(&mut self, a: &Schema, b: &Schema) {
+        match (a, b) {
+            (Schema::Struct(a1), Schema::Struct(b1)) => self.diff_struct(a, b, a1, b1),
+            (Schema::Enum(a1), Schema::Enum(b1)) => self.diff_enum(a, b, a1, b1),
+            (Schema::Primitive(a1), Schema::Primitive(b1)) => {
+                //Strings have the same schema, even if they're not memory-layout compatible
+                let both_strings = matches!(
+                    (a1, b1),
+                    (SchemaPrimitive::schema_string(_), SchemaPrimitive::schema_string(_))
+                );
+                if a1 != b1 && !both_strings {
+                    self.report(SchemaDifferenceKind::TypeChange, a, b);
+                }
+            }
+            (Schema::Vector(a1, _), Schema::Vector(b1, _)) => {
+                self.within(SchemaPathElement::Element, |differ| differ.diff(a1, b1))
+            }
+            (Schema::SchemaOption(a1), Schema::SchemaOption(b1)) => {
+                self.within(SchemaPathElement::OptionValue, |differ| differ.diff(a1, b1))
+            }
+            (Schema::Undefined, Schema::Undefined) => self.report(SchemaDifferenceKind::Undefined, a, b),
+            (Schema::ZeroSize, Schema::ZeroSize) | (Schema::Str, Schema::Str) => {}
+            (Schema::Array(a1), Schema::Array(b1)) => self.diff_array(a, b, a1, b1),
+            (Schema::Custom(a1), Schema::Custom(b1)) => {
+                if a1 != b1 {
+                    self.report(SchemaDifferenceKind::TypeChange, a, b);
+                }
+            }
+            (Schema::Boxed(a1), Schema::Boxed(b1))
+            | (Schema::Reference(a1), Schema::Reference(b1))
+            | (Schema::Slice(a1), Schema::Slice(b1)) => self.diff(a1, b1),
+            (Schema::Trait(amut, a1), Schema::Trait(bmut, b1))
+            | (Schema::FnClosure(amut, a1), Schema::FnClosure(bmut, b1)) => {
+                if amut != bmut {
+                    self.report(SchemaDifferenceKind::TypeChange, a, b);
+                }
+                self.diff_abi_def(a, b, a1, b1);
+            }
+            (Schema::Recursion(adepth), Schema::Recursion(bdepth)) => {
+                if adepth != bdepth {
+                    self.report(SchemaDifferenceKind::TypeChange, a, b);
+                }
+            }
+            _ => self.report(SchemaDifferenceKind::TypeChange, a, b),
+        }
+    }
+
+    fn diff_array(&mut self, a: &Schema, b: &Schema, a1: &SchemaArray, b1: &SchemaArray) {
+        if a1.count != b1.count {
+            self.report(
+                SchemaDifferenceKind::ArrayLengthChange {
+                    memory: a1.count,
+                    file: b1.count,
+                },
+                a,
+                b,
+            );
+        }
+        self.within(SchemaPathElement::ArrayElement(a1.count), |differ| {
+            differ.diff(&a1.item_type, &b1.item_type)
+        });
+    }
+
+    fn diff_struct(&mut self, a: &Schema, b: &Schema, a1: &SchemaStruct, b1: &SchemaStruct) {
+        self.within(SchemaPathElement::Struct(b1.dbg_name.clone()), |differ| {
+            differ.diff_fields(a, b, &a1.fields, &b1.fields)
+        });
+    }
+
+    fn diff_enum(&mut self, a: &Schema, b: &Schema, a1: &SchemaEnum, b1: &SchemaEnum) {
+        self.path.push(SchemaPathElement::Enum(b1.dbg_name.clone()));
+        let same_count = a1.variants.len() == b1.variants.len();
+        if !same_count {
+            self.report(
+                SchemaDifferenceKind::VariantCountChange {
+                    memory: a1.variants.len(),
+                    file: b1.variants.len(),
+                },
+                a,
+                b,
+            );
+        }
+        if a1.discriminant_size != b1.discriminant_size {
+            self.report(
+                SchemaDifferenceKind::DiscriminantSizeChange {
+                    memory: a1.discriminant_size,
+                    file: b1.discriminant_size,
+                },
+                a,
+                b,
+            );
+        }
+        for (index, avar) in a1.variants.iter().enumerate() {
+            // Variants are identified by position. If variants were added or removed,
+            // the best guess is that the remaining ones kept their names.
+            let bvar = if same_count {
+                &b1.variants[index]
+            } else if let Some(bvar) = b1.variants.iter().find(|bvar| bvar.name == avar.name) {
+                bvar
+            } else {
+                self.report(SchemaDifferenceKind::VariantAdded(avar.name.clone()), a, b);
+                continue;
+            };
+            if avar.name != bvar.name {
+                self.report(
+                    SchemaDifferenceKind::VariantRenamed {
+                        index,
+                        memory: avar.name.clone(),
+                        file: bvar.name.clone(),
+                    },
+                    a,
+                    b,
+                );
+            }
+            if avar.discriminant != bvar.discriminant {
+                self.report(
+                    SchemaDifferenceKind::VariantDiscriminantChange {
+                        index,
+                        memory: avar.discriminant,
+                        file: bvar.discriminant,
+                    },
+                    a,
+                    b,
+                );
+            }
+            self.within(SchemaPathElement::Variant(bvar.name.clone()), |differ| {
+                differ.diff_fields(a, b, &avar.fields, &bvar.fields)
+            });
+        }
+        if !same_count {
+            for bvar in &b1.variants {
+                if !a1.variants.iter().any(|avar| avar.name == bvar.name) {
+                    self.report(SchemaDifferenceKind::VariantRemoved(bvar.name.clone()), a, b);
+                }
+            }
+        }
+        self.path.pop();
+    }
+
+    /// Compare the fields of a struct or enum variant. `a` and `b` are the struct or enum.
+    fn diff_fields(&mut self, a: &Schema, b: &Schema, afields: &[Field], bfields: &[Field]) {
+        if afields.len() == bfields.len() {
+            // Fields are identified by position, so renaming a field is fine
+            for (afield, bfield) in afields.iter().zip(bfields) {
+                self.within(SchemaPathElement::Field(bfield.name.clone()), |differ| {
+                    differ.diff(&afield.value, &bfield.value)
+                });
+            }
+            return;
+        }
+        self.report(
+            SchemaDifferenceKind::FieldCountChange {
+                memory: afields.len(),
+                file: bfields.len(),
+            },
+            a,
+            b,
+        );
+        for afield in afields {
+            if let Some(bfield) = bfields.iter().find(|bfield| bfield.name == afield.name) {
+                self.within(SchemaPathElement::Field(bfield.name.clone()), |differ| {
+                    differ.diff(&afield.value, &bfield.value)
+                });
+            } else {
+                self.report(SchemaDifferenceKind::FieldAdded(afield.name.clone()), a, b);
+            }
+        }
+        for bfield in bfields {
+            if !afields.iter().any(|afield| afield.name == bfield.name) {
+                self.report(SchemaDifferenceKind::FieldRemoved(bfield.name.clone()), a, b);
+            }
+        }
+    }
+
+    fn diff_abi_def(&mut self, a: &Schema, b: &Schema, a1: &AbiTraitDefinition, b1: &AbiTraitDefinition) {
+        for amet in a1.methods.iter() {
+            let Some(bmet) = b1.methods.iter().find(|x| x.name == amet.name) else {
+                continue;
+            };
+            if amet.info.arguments.len() != bmet.info.arguments.len() {
+                self.report(
+                    SchemaDifferenceKind::ArgumentCountChange {
+                        method: amet.name.clone(),
+                        memory: amet.info.arguments.len(),
+                        file: bmet.info.arguments.len(),
+                    },
+                    a,
+                    b,
+                );
+                continue;
+            }
+            for (index, (a_arg, b_arg)) in amet.info.arguments.iter().zip(bmet.info.arguments.iter()).enumerate() {
+                let element = SchemaPathElement::MethodArgument {
+                    method: amet.name.clone(),
+                    index,
+                };
+                self.within(element, |differ| differ.diff(&a_arg.schema, &b_arg.schema));
+            }
+        }
+    }
+}
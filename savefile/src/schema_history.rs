@@ -0,0 +1,72 @@
+use crate::{
+    get_schema, load_file_noschema, save_file_noschema, SavefileError, Schema, SchemaDiff, WithSchema,
+    CURRENT_SAVEFILE_LIB_VERSION,
+};
+use std::path::Path;
+
+/// Verify that the schema of `T` has not changed for any version up to and including
+/// `version`, which should be the current version of the data.
+///
+/// The schema of each version is stored in a file `savefile_{name}_{version}.schema` in the
+/// directory `path`, which is created if needed. If the file for a version does not exist,
+/// it is written. If it exists, the schema it contains is compared to
+/// [crate::get_schema] for that version, and [SavefileError::IncompatibleSchema], listing
+/// every difference, is returned if they are not compatible.
+///
+/// This detects changes which would make files saved by earlier versions impossible to load,
+/// such as changing the type of a field without using `savefile_versions_as`. Call it from
+/// a test, and check the files in to source control. If the check fails for a version which has
+/// not yet been released, just remove its file.
+///
+/// This is the equivalent of `savefile_abi::verify_compatiblity`, for types which are saved to files.
+pub fn verify_schema_history<T: WithSchema + 'static>(
+    path: impl AsRef<Path>,
+    version: u32,
+) -> Result<(), SavefileError> {
+    let path = path.as_ref();
+    std::fs::create_dir_all(path)?;
+    let name = schema_name::<T>();
+    // The schema format depends on the savefile version, so this is what the files have as version
+    let schema_format_version = CURRENT_SAVEFILE_LIB_VERSION as u32;
+    for version in 0..=version {
+        let schema = get_schema::<T>(version);
+        let schema_file_name = path.join(format!("savefile_{}_{}.schema", name, version));
+        if std::fs::metadata(&schema_file_name).is_ok() {
+            let recorded: Schema = load_file_noschema(&schema_file_name, schema_format_version)?;
+            let diff = SchemaDiff::new(&schema, &recorded);
+            if !diff.is_empty() {
+                return Err(SavefileError::IncompatibleSchema {
+                    message: format!(
+                        "Schema for version {} differs from the one recorded in {}:\n{}",
+                        version,
+                        schema_file_name.display(),
+                        diff
+                    ),
+                });
+            }
+        } else {
+            save_file_noschema(&schema_file_name, schema_format_version, &schema)?;
+        }
+    }
+    Ok(())
+}
+
+/// A name for the schema files of `T`, usable in file names. This is the type name
+/// without module paths, so moving the type to another module does not change it.
+pub(crate) fn schema_name<T: ?Sized>() -> String {
+    let mut name = String::new();
+    let mut segment_start = 0;
+    let mut chars = std::any::type_name::<T>().chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == ':' && chars.peek() == Some(&':') {
+            chars.next();
+            name.truncate(segment_start);
+        } else if c.is_ascii_alphanumeric() || c == '_' {
+            name.push(c);
+        } else {
+            name.push('_');
+            segment_start = name.len();
+        }
+    }
+    name.trim_matches('_').to_string()
+}
@@ -0,0 +1,187 @@
+use crate::{
+    CompressionOptions, Deserialize, LoadOptions, SavefileError, Serialize, Serializer, WithSchema, WithSchemaContext,
+};
+use ring::digest;
+use ring::rand::{SecureRandom, SystemRandom};
+use ring::signature::{Ed25519KeyPair, KeyPair, UnparsedPublicKey, ED25519};
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+/// Length of an Ed25519 signature
+const SIGNATURE_LEN: usize = 64;
+
+/// Ed25519 key pair, used to sign data using [save_signed].
+pub struct SigningKeyPair {
+    seed: [u8; 32],
+    key_pair: Ed25519KeyPair,
+}
+
+impl SigningKeyPair {
+    /// Generate a new, random, key pair
+    pub fn generate() -> Result<SigningKeyPair, SavefileError> {
+        let mut seed = [0u8; 32];
+        SystemRandom::new()
+            .fill(&mut seed)
+            .map_err(|_| SavefileError::CryptographyError)?;
+        SigningKeyPair::from_seed(seed)
+    }
+    /// The key pair with the given 32 byte secret seed
+    pub fn from_seed(seed: [u8; 32]) -> Result<SigningKeyPair, SavefileError> {
+        let key_pair = Ed25519KeyPair::from_seed_unchecked(&seed).map_err(|_| SavefileError::CryptographyError)?;
+        Ok(SigningKeyPair { seed, key_pair })
+    }
+    /// The secret seed of the key pair. Keep this secret.
+    pub fn seed(&self) -> [u8; 32] {
+        self.seed
+    }
+    /// The public key, used to verify signatures
+    pub fn public_key(&self) -> SigningPublicKey {
+        let mut bytes = [0u8; 32];
+        bytes.copy_from_slice(self.key_pair.public_key().as_ref());
+        SigningPublicKey(bytes)
+    }
+}
+
+/// Ed25519 public key, used to verify signed data using [load_signed].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SigningPublicKey([u8; 32]);
+
+impl SigningPublicKey {
+    /// The public key with the given bytes
+    pub fn from_bytes(bytes: [u8; 32]) -> SigningPublicKey {
+        SigningPublicKey(bytes)
+    }
+    /// The bytes of the public key
+    pub fn to_bytes(&self) -> [u8; 32] {
+        self.0
+    }
+}
+
+/// The message actually signed, given the SHA-512 digest of the signed data
+fn signed_message(digest: digest::Digest) -> Vec<u8> {
+    let mut message = b"savefile signature v1\0".to_vec();
+    message.extend_from_slice(digest.as_ref());
+    message
+}
+
+/// Writer which hashes everything written through it
+struct HashingWriter<'a, W: Write> {
+    writer: &'a mut W,
+    digest: digest::Context,
+}
+
+impl<W: Write> Write for HashingWriter<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let written = self.writer.write(buf)?;
+        self.digest.update(&buf[..written]);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+fn save_signed_impl<T: WithSchema + Serialize>(
+    writer: &mut impl Write,
+    version: u32,
+    data: &T,
+    key: &SigningKeyPair,
+    compression: Option<CompressionOptions>,
+) -> Result<(), SavefileError> {
+    let mut hashing = HashingWriter {
+        writer,
+        digest: digest::Context::new(&digest::SHA512),
+    };
+    Serializer::save_impl(
+        &mut hashing,
+        version,
+        data,
+        Some(T::schema(version, &mut WithSchemaContext::new())),
+        compression,
+        None,
+        None,
+    )?;
+    let signature = key.key_pair.sign(&signed_message(hashing.digest.finish()));
+    writer.write_all(signature.as_ref())?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Write the given `data` to the `writer`, followed by an Ed25519 signature of everything
+/// written (header, schema and data). The current version of data must be `version`.
+///
+/// The data is not encrypted. Use [load_signed] to load the data, verifying the signature.
+/// The regular [crate::load] can also load the data, but then the signature is not checked.
+pub fn save_signed<T: WithSchema + Serialize>(
+    writer: &mut impl Write,
+    version: u32,
+    data: &T,
+    key: &SigningKeyPair,
+) -> Result<(), SavefileError> {
+    save_signed_impl(writer, version, data, key, None)
+}
+
+/// Like [save_signed], but compresses the data using the given codec and level.
+/// The signature covers the compressed data.
+/// Note, this function will fail if the feature for the selected codec is not enabled.
+pub fn save_signed_compressed_with<T: WithSchema + Serialize>(
+    writer: &mut impl Write,
+    version: u32,
+    data: &T,
+    key: &SigningKeyPair,
+    compression: CompressionOptions,
+) -> Result<(), SavefileError> {
+    save_signed_impl(writer, version, data, key, Some(compression))
+}
+
+/// Like [save_signed], but writes to the given file.
+pub fn save_file_signed<T: WithSchema + Serialize, P: AsRef<Path>>(
+    path: P,
+    version: u32,
+    data: &T,
+    key: &SigningKeyPair,
+) -> Result<(), SavefileError> {
+    let mut f = BufWriter::new(File::create(path)?);
+    save_signed(&mut f, version, data, key)
+}
+
+/// Load an object of type T, previously saved using [save_signed], from `reader`.
+/// The current version of T in memory must be `version`.
+///
+/// The signature is verified against `public_key`. If it does not match, because the data
+/// has been modified or was signed with another key, [SavefileError::InvalidSignature] is returned.
+///
+/// The signature is stored at the end of the data, so the reader is read to its end.
+/// The reader should thus not contain anything after the saved object.
+///
+/// Nothing is deserialized before the signature has been verified, so data which has not
+/// been signed is never parsed. This means the whole file is read into memory first.
+pub fn load_signed<T: WithSchema + Deserialize>(
+    reader: &mut impl Read,
+    version: u32,
+    public_key: &SigningPublicKey,
+) -> Result<T, SavefileError> {
+    let mut data = Vec::new();
+    reader.read_to_end(&mut data)?;
+    if data.len() < SIGNATURE_LEN {
+        return Err(SavefileError::InvalidSignature);
+    }
+    let (signed, signature) = data.split_at(data.len() - SIGNATURE_LEN);
+    let message = signed_message(digest::digest(&digest::SHA512, signed));
+    UnparsedPublicKey::new(&ED25519, &public_key.0)
+        .verify(&message, signature)
+        .map_err(|_| SavefileError::InvalidSignature)?;
+    LoadOptions::new(version).load_from_mem(signed)
+}
+
+/// Like [load_signed], but reads from the given file.
+pub fn load_file_signed<T: WithSchema + Deserialize, P: AsRef<Path>>(
+    path: P,
+    version: u32,
+    public_key: &SigningPublicKey,
+) -> Result<T, SavefileError> {
+    let mut f = BufReader::new(File::open(path)?);
+    load_signed(&mut f, version, public_key)
+}
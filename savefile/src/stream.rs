@@ -0,0 +1,179 @@
+use crate::{
+    check_file_schema, new_schema_deserializer, read_file_header, write_file_header, Deserialize, Deserializer,
+    SavefileError, Schema, Serialize, Serializer, WithSchema, WithSchemaContext, CURRENT_SAVEFILE_LIB_VERSION,
+};
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use std::collections::HashMap;
+use std::io::{ErrorKind, Read, Write};
+use std::marker::PhantomData;
+
+/// Writes a stream of records of type T, all sharing a single savefile header and schema.
+///
+/// The header and the schema of T are written once, when the StreamWriter is created.
+/// Each record is then appended using [StreamWriter::write_record]. Records are
+/// length-prefixed, so that a reader can detect a record which was only partially
+/// written (for example because the writing process crashed).
+///
+/// The stream can be read back using [StreamReader]. It cannot be read using
+/// [crate::load].
+pub struct StreamWriter<W: Write, T> {
+    writer: W,
+    version: u32,
+    buf: Vec<u8>,
+    phantom: PhantomData<fn(&T)>,
+}
+
+impl<W: Write, T: WithSchema + Serialize> StreamWriter<W, T> {
+    /// Create a new StreamWriter, immediately writing the savefile header and the schema
+    /// of T for the given version to `writer`.
+    pub fn new(mut writer: W, version: u32) -> Result<StreamWriter<W, T>, SavefileError> {
+        write_file_header(&mut writer, CURRENT_SAVEFILE_LIB_VERSION, version)?;
+        writer.write_u8(0)?; //Streams are never compressed
+        let schema = T::schema(version, &mut WithSchemaContext::new());
+        let mut schema_serializer = Serializer::<W>::new_raw(&mut writer, CURRENT_SAVEFILE_LIB_VERSION as u32);
+        schema.serialize(&mut schema_serializer)?;
+        Ok(StreamWriter {
+            writer,
+            version,
+            buf: Vec::new(),
+            phantom: PhantomData,
+        })
+    }
+
+    /// Append a single record to the stream.
+    /// The record is serialized in full before anything is written to the underlying writer.
+    pub fn write_record(&mut self, data: &T) -> Result<(), SavefileError> {
+        self.buf.clear();
+        let mut serializer = Serializer {
+            writer: &mut self.buf,
+            file_version: self.version,
+        };
+        data.serialize(&mut serializer)?;
+        self.writer.write_u64::<LittleEndian>(self.buf.len() as u64)?;
+        self.writer.write_all(&self.buf)?;
+        Ok(())
+    }
+
+    /// Flush the underlying writer. All records written so far will then be readable.
+    pub fn flush(&mut self) -> Result<(), SavefileError> {
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    /// Flush, and then return the underlying writer.
+    pub fn into_inner(mut self) -> Result<W, SavefileError> {
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+/// Reads a stream of records of type T, previously written by [StreamWriter].
+///
+/// The header and schema are read and validated once, when the StreamReader is created.
+/// The records are then available through the `Iterator` implementation.
+///
+/// If the final record of the stream is truncated (for example because the writer crashed
+/// while writing it), the iterator simply ends before that record.
+pub struct StreamReader<R: Read, T> {
+    reader: R,
+    file_version: u32,
+    buf: Vec<u8>,
+    done: bool,
+    phantom: PhantomData<fn() -> T>,
+}
+
+impl<R: Read, T: WithSchema + Deserialize> StreamReader<R, T> {
+    /// Create a new StreamReader. This reads the header of the stream and verifies that
+    /// the schema in the stream is compatible with the schema of T. The current version
+    /// of T in memory must be `version`.
+    pub fn new(mut reader: R, version: u32) -> Result<StreamReader<R, T>, SavefileError> {
+        let (savefile_lib_version, file_ver) = read_file_header(&mut reader, version)?;
+        if reader.read_u8()? != 0 {
+            return Err(SavefileError::GeneralError {
+                msg: "Compressed savefile streams are not supported.".into(),
+            });
+        }
+        {
+            let mut schema_deserializer = new_schema_deserializer(&mut reader, savefile_lib_version);
+            let file_schema = Schema::deserialize(&mut schema_deserializer)?;
+            let memory_schema = T::schema(file_ver, &mut WithSchemaContext::new());
+            check_file_schema(&memory_schema, &file_schema, file_ver)?;
+        }
+        Ok(StreamReader {
+            reader,
+            file_version: file_ver,
+            buf: Vec::new(),
+            done: false,
+            phantom: PhantomData,
+        })
+    }
+
+    /// The version of the records in the stream
+    pub fn file_version(&self) -> u32 {
+        self.file_version
+    }
+
+    /// Return the underlying reader
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    /// Read the length prefix of the next record.
+    /// Returns None if the stream ends, either cleanly or in the middle of the prefix.
+    fn read_record_len(&mut self) -> Result<Option<u64>, SavefileError> {
+        let mut sizebuf = [0u8; 8];
+        let mut got = 0;
+        while got < sizebuf.len() {
+            match self.reader.read(&mut sizebuf[got..]) {
+                Ok(0) => return Ok(None),
+                Ok(n) => got += n,
+                Err(err) if err.kind() == ErrorKind::Interrupted => {}
+                Err(err) => return Err(err.into()),
+            }
+        }
+        Ok(Some(u64::from_le_bytes(sizebuf)))
+    }
+
+    fn read_record(&mut self) -> Result<Option<T>, SavefileError> {
+        let Some(len) = self.read_record_len()? else {
+            return Ok(None);
+        };
+        let len: usize = len.try_into().map_err(|_| SavefileError::SizeOverflow)?;
+        self.buf.clear();
+        let got = (&mut self.reader).take(len as u64).read_to_end(&mut self.buf)?;
+        if got < len {
+            // Truncated final record
+            return Ok(None);
+        }
+        let mut deserializer = Deserializer {
+            reader: &mut &self.buf[..],
+            file_version: self.file_version,
+            ephemeral_state: HashMap::new(),
+            limit_state: None,
+        };
+        Ok(Some(T::deserialize(&mut deserializer)?))
+    }
+}
+
+impl<R: Read, T: WithSchema + Deserialize> Iterator for StreamReader<R, T> {
+    type Item = Result<T, SavefileError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        match self.read_record() {
+            Ok(Some(item)) => Some(Ok(item)),
+            Ok(None) => {
+                self.done = true;
+                None
+            }
+            Err(err) => {
+                if let SavefileError::IOError { .. } = err {
+                    self.done = true;
+                }
+                Some(Err(err))
+            }
+        }
+    }
+}
@@ -0,0 +1,965 @@
+use crate::{
+    read_file_header, write_file_header, Deserializer, Field, PayloadLoader, SavefileError, Schema, SchemaEnum,
+    SchemaPrimitive, SchemaStruct, Serialize, Serializer, CURRENT_SAVEFILE_LIB_VERSION,
+};
+use byteorder::WriteBytesExt;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+/// The value of the canary primitive, see [crate::Canary1]
+pub(crate) const CANARY1: u32 = 0x47566843;
+
+/// A value of any type, read from a file without knowing its Rust type.
+///
+/// The structure of a value is given by a [Schema]. Values are read using
+/// [Value::deserialize_with_schema] and written using [Value::serialize_with_schema].
+/// Use [load_dynamic] to read a complete file, with the schema stored in it.
+///
+/// Maps and sets are vectors of structs with the fields `key` and `value`
+/// (or just `key`, for sets). `usize` and `isize` are represented as [Value::U64]
+/// and [Value::I64], and zero sized types as [Value::Unit].
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    /// A zero sized value, with no data
+    Unit,
+    /// bool
+    Bool(bool),
+    /// u8
+    U8(u8),
+    /// i8
+    I8(i8),
+    /// u16
+    U16(u16),
+    /// i16
+    I16(i16),
+    /// u32
+    U32(u32),
+    /// i32
+    I32(i32),
+    /// u64
+    U64(u64),
+    /// i64
+    I64(i64),
+    /// u128
+    U128(u128),
+    /// i128
+    I128(i128),
+    /// f32
+    F32(f32),
+    /// f64
+    F64(f64),
+    /// char
+    Char(char),
+    /// String
+    String(String),
+    /// An optional value
+    Option(Option<Box<Value>>),
+    /// A vector of values, all of the same type
+    Vector(Vec<Value>),
+    /// An array of values, all of the same type. The length is given by the schema.
+    Array(Vec<Value>),
+    /// A struct, with its fields in declaration order
+    Struct {
+        /// Name of the struct
+        name: String,
+        /// Names and values of the fields
+        fields: Vec<(String, Value)>,
+    },
+    /// A variant of an enum
+    Enum {
+        /// Name of the enum
+        name: String,
+        /// Name of the variant
+        variant: String,
+        /// Names and values of the fields of the variant
+        fields: Vec<(String, Value)>,
+    },
+}
+
+impl Value {
+    /// Short description of the type of this value, such as `u32`, `vector` or the name of a struct
+    pub fn type_name(&self) -> &str {
+        match self {
+            Value::Unit => "unit",
+            Value::Bool(_) => "bool",
+            Value::U8(_) => "u8",
+            Value::I8(_) => "i8",
+            Value::U16(_) => "u16",
+            Value::I16(_) => "i16",
+            Value::U32(_) => "u32",
+            Value::I32(_) => "i32",
+            Value::U64(_) => "u64",
+            Value::I64(_) => "i64",
+            Value::U128(_) => "u128",
+            Value::I128(_) => "i128",
+            Value::F32(_) => "f32",
+            Value::F64(_) => "f64",
+            Value::Char(_) => "char",
+            Value::String(_) => "String",
+            Value::Option(_) => "option",
+            Value::Vector(_) => "vector",
+            Value::Array(_) => "array",
+            Value::Struct { name, .. } | Value::Enum { name, .. } => name,
+        }
+    }
+    /// The value of the field with the given name, if this is a struct or an enum variant
+    /// with such a field.
+    pub fn field(&self, name: &str) -> Option<&Value> {
+        match self {
+            Value::Struct { fields, .. } | Value::Enum { fields, .. } => {
+                fields.iter().find(|(x, _)| x == name).map(|(_, value)| value)
+            }
+            _ => None,
+        }
+    }
+    /// Mutable reference to the value of the field with the given name, see [Value::field].
+    pub fn field_mut(&mut self, name: &str) -> Option<&mut Value> {
+        match self {
+            Value::Struct { fields, .. } | Value::Enum { fields, .. } => {
+                fields.iter_mut().find(|(x, _)| x == name).map(|(_, value)| value)
+            }
+            _ => None,
+        }
+    }
+
+    /// Read a value with the given schema from `deserializer`.
+    ///
+    /// Fails if the schema contains types which savefile cannot deserialize,
+    /// such as [Schema::Custom] or trait objects.
+    ///
+    /// Recursive data types are supported, but the schema of `Box<T>`, `Rc<T>` and `Arc<T>`
+    /// is identical to that of `T`. The target of a [Schema::Recursion] through such
+    /// a pointer therefore has to be inferred, and in rare cases this fails with an error.
+    pub fn deserialize_with_schema(
+        deserializer: &mut Deserializer<impl Read>,
+        schema: &Schema,
+    ) -> Result<Value, SavefileError> {
+        let mut path = SchemaPath::new(schema);
+        read_value(&mut path, deserializer, schema)
+    }
+
+    /// Write this value to `serializer`, in the format given by `schema`.
+    ///
+    /// Fails if the value does not match the schema. Some of the value may
+    /// have been written by then.
+    pub fn serialize_with_schema(
+        &self,
+        serializer: &mut Serializer<impl Write>,
+        schema: &Schema,
+    ) -> Result<(), SavefileError> {
+        let mut path = SchemaPath::new(schema);
+        write_value(&mut path, serializer, schema, self)
+    }
+}
+
+/// The contents of a savefile, read without knowing the Rust type of the data.
+/// See [load_dynamic].
+#[derive(Debug, Clone, PartialEq)]
+pub struct DynamicFile {
+    /// The version of the data in the file
+    pub version: u32,
+    /// The schema of the data, as stored in the file
+    pub schema: Schema,
+    /// The data
+    pub value: Value,
+}
+
+struct DynamicLoader;
+
+impl PayloadLoader for DynamicLoader {
+    type Output = DynamicFile;
+    fn reads_schema(&self) -> bool {
+        true
+    }
+    fn load_data(
+        self,
+        deserializer: &mut Deserializer<impl Read>,
+        file_schema: Option<Schema>,
+    ) -> Result<DynamicFile, SavefileError> {
+        let schema = file_schema.ok_or_else(|| SavefileError::GeneralError {
+            msg: "File has no schema.".into(),
+        })?;
+        let value = Value::deserialize_with_schema(deserializer, &schema)?;
+        Ok(DynamicFile {
+            version: deserializer.file_version,
+            schema,
+            value,
+        })
+    }
+}
+
+/// Load the data from `reader`, without knowing its Rust type. The schema stored in
+/// the file is used to interpret the data, see [Value].
+///
+/// Data of any version can be loaded. The file must have been saved with a schema,
+/// which is the case unless one of the `*_noschema` functions was used.
+/// Compressed files and files with checksums are supported.
+pub fn load_dynamic(reader: &mut impl Read) -> Result<DynamicFile, SavefileError> {
+    let (savefile_lib_version, file_ver) = read_file_header(reader, u32::MAX)?;
+    Deserializer::<_>::load_payload_with(reader, savefile_lib_version, file_ver, DynamicLoader)
+}
+
+/// Like [load_dynamic], but loads from the given file.
+pub fn load_file_dynamic<P: AsRef<Path>>(path: P) -> Result<DynamicFile, SavefileError> {
+    let mut f = BufReader::new(File::open(path)?);
+    load_dynamic(&mut f)
+}
+
+/// Write `file` to `writer`: its value, in the format given by its schema, preceded
+/// by the schema itself. The data is written as version `file.version`.
+///
+/// The result can be loaded by [load_dynamic], or by [crate::load] with the
+/// corresponding Rust type. The data is neither compressed nor checksummed.
+pub fn save_dynamic(writer: &mut impl Write, file: &DynamicFile) -> Result<(), SavefileError> {
+    write_dynamic(writer, file.version, &file.schema, &file.value)
+}
+
+/// Write the header, `schema` and `value`, as data of version `version`
+pub(crate) fn write_dynamic(
+    writer: &mut impl Write,
+    version: u32,
+    schema: &Schema,
+    value: &Value,
+) -> Result<(), SavefileError> {
+    write_file_header(writer, CURRENT_SAVEFILE_LIB_VERSION, version)?;
+    writer.write_u8(0)?; // No compression, no checksum
+    let mut schema_serializer = Serializer {
+        writer: &mut *writer,
+        file_version: CURRENT_SAVEFILE_LIB_VERSION as u32,
+    };
+    schema.serialize(&mut schema_serializer)?;
+    let mut serializer = Serializer {
+        writer: &mut *writer,
+        file_version: version,
+    };
+    value.serialize_with_schema(&mut serializer, schema)?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Like [save_dynamic], but writes to the given file.
+pub fn save_file_dynamic<P: AsRef<Path>>(path: P, file: &DynamicFile) -> Result<(), SavefileError> {
+    let mut f = BufWriter::new(File::create(path)?);
+    save_dynamic(&mut f, file)
+}
+
+fn mismatch(msg: String) -> SavefileError {
+    SavefileError::GeneralError { msg }
+}
+
+/// Which child of a schema node a node in a [SchemaPath] is: the variant index (for enums)
+/// and the field index (for structs and enums). Other nodes have a single child.
+pub(crate) type Step = (usize, usize);
+
+/// The schema of `step` in `schema`
+fn child_schema(schema: &Schema, (variant, field): Step) -> Option<&Schema> {
+    match schema {
+        Schema::Struct(s) => s.fields.get(field).map(|x| &*x.value),
+        Schema::Enum(e) => e.variants.get(variant)?.fields.get(field).map(|x| &*x.value),
+        Schema::Vector(item, _) | Schema::SchemaOption(item) | Schema::Boxed(item) => Some(item),
+        Schema::Array(array) => Some(&array.item_type),
+        _ => None,
+    }
+}
+
+/// Maps and sets are serialized as vectors of these structs
+pub(crate) fn is_map_entry(s: &SchemaStruct) -> bool {
+    let names: Vec<&str> = s.fields.iter().map(|x| x.name.as_str()).collect();
+    match s.dbg_name.as_str() {
+        "KeyValuePair" => names == ["key", "value"],
+        "Key" => names == ["key"],
+        _ => false,
+    }
+}
+
+/// [crate::Lazy] values are serialized as these structs. The length is that of the serialized value.
+fn is_lazy(s: &SchemaStruct) -> bool {
+    match &s.fields[..] {
+        [length, value] => {
+            s.dbg_name == "Lazy"
+                && length.name == "length"
+                && *length.value == Schema::Primitive(SchemaPrimitive::schema_u64)
+                && value.name == "value"
+        }
+        _ => false,
+    }
+}
+
+/// The discriminant of each variant, as written to disk.
+///
+/// The schema only records the lowest byte of the discriminant. Larger discriminants can
+/// only be determined if the variants have the discriminants 0, 1, 2 and so on, as written
+/// by the derive macro for enums which have never had variants removed. Otherwise,
+/// guessing could decode the wrong variant, so this fails instead.
+fn wire_discriminants(e: &SchemaEnum) -> Result<Vec<u32>, SavefileError> {
+    if e.discriminant_size == 1 {
+        return Ok(e.variants.iter().map(|x| x.discriminant as u32).collect());
+    }
+    if e.variants.iter().enumerate().any(|(i, x)| x.discriminant != i as u8) {
+        return Err(mismatch(format!(
+            "The discriminants of enum {} cannot be determined from the schema, since it has \
+             {} byte discriminants which are not 0, 1, 2...",
+            e.dbg_name, e.discriminant_size
+        )));
+    }
+    Ok((0..e.variants.len() as u32).collect())
+}
+
+/// A node on the path from the root schema to the node currently being read or written.
+pub(crate) struct PathNode<'s> {
+    schema: &'s Schema,
+    step: Step,
+    /// The least and greatest number of recursion points (see [crate::WithSchemaContext])
+    /// which may be at this node.
+    min_frames: usize,
+    max_frames: usize,
+}
+
+/// The path from the root schema to the node currently being read or written.
+/// Used to resolve [Schema::Recursion].
+///
+/// A recursion refers to the node `depth` recursion points up the path. Vectors, arrays
+/// and maps have recursion points at their elements. But `Box<T>`, `Rc<T>` and `Arc<T>`
+/// also add recursion points, which are not visible in the schema. Any node may thus have
+/// an additional recursion point.
+pub(crate) struct SchemaPath<'s> {
+    nodes: Vec<PathNode<'s>>,
+}
+
+impl<'s> SchemaPath<'s> {
+    pub(crate) fn new(root: &'s Schema) -> SchemaPath<'s> {
+        SchemaPath {
+            nodes: vec![PathNode {
+                schema: root,
+                step: (0, 0),
+                min_frames: 0,
+                max_frames: 0,
+            }],
+        }
+    }
+    pub(crate) fn enter(&mut self, schema: &'s Schema, step: Step, recursion_point: bool, transparent: bool) {
+        let (min_frames, max_frames) = match (recursion_point, transparent) {
+            (_, true) => (0, 0),
+            (true, false) => (1, 2),
+            (false, false) => (0, 1),
+        };
+        self.nodes.push(PathNode {
+            schema,
+            step,
+            min_frames,
+            max_frames,
+        });
+    }
+    pub(crate) fn leave(&mut self) {
+        self.nodes.pop();
+    }
+
+    /// Name of the type a recursion at `step` of the current node refers to, if it can be
+    /// determined. This is the case if a struct or enum on the path occurs earlier
+    /// on the path as well, with the recursion expanded there.
+    fn recursion_type_hint(&self, step: Step) -> Option<&'s str> {
+        let name_of = |schema: &Schema| match schema {
+            Schema::Struct(s) => Some(s.dbg_name.clone()),
+            Schema::Enum(e) => Some(e.dbg_name.clone()),
+            _ => None,
+        };
+        for later in (1..self.nodes.len()).rev() {
+            let Some(name) = name_of(self.nodes[later].schema) else {
+                continue;
+            };
+            let steps = self.nodes[later + 1..]
+                .iter()
+                .map(|x| x.step)
+                .chain(std::iter::once(step));
+            for earlier in 0..later {
+                if name_of(self.nodes[earlier].schema).as_ref() != Some(&name) {
+                    continue;
+                }
+                let mut schema = Some(self.nodes[earlier].schema);
+                for step in steps.clone() {
+                    schema = schema.and_then(|x| child_schema(x, step));
+                }
+                match schema {
+                    Some(Schema::Struct(s)) => return Some(&s.dbg_name),
+                    Some(Schema::Enum(e)) => return Some(&e.dbg_name),
+                    _ => {}
+                }
+            }
+        }
+        None
+    }
+
+    /// Find the node a recursion of `depth` at `step` of the current node refers to.
+    /// The path is truncated to end at that node, the removed part is returned
+    /// and must be given to [SchemaPath::restore] afterwards.
+    pub(crate) fn resolve(
+        &mut self,
+        depth: usize,
+        step: Step,
+    ) -> Result<(&'s Schema, Vec<PathNode<'s>>), SavefileError> {
+        let hint = self.recursion_type_hint(step);
+        for target in 0..self.nodes.len() {
+            let node = &self.nodes[target];
+            let name_matches = match (node.schema, hint) {
+                (Schema::Struct(s), Some(hint)) => s.dbg_name == hint,
+                (Schema::Enum(e), Some(hint)) => e.dbg_name == hint,
+                (Schema::Struct(_) | Schema::Enum(_) | Schema::Vector(..) | Schema::Array(_), None) => true,
+                _ => false,
+            };
+            if !name_matches || node.max_frames == 0 {
+                continue;
+            }
+            let rest = &self.nodes[target + 1..];
+            let min_frames = node.min_frames.max(1) + rest.iter().map(|x| x.min_frames).sum::<usize>();
+            let max_frames = node.max_frames + rest.iter().map(|x| x.max_frames).sum::<usize>();
+            if (min_frames..=max_frames).contains(&depth) {
+                let schema = node.schema;
+                return Ok((schema, self.nodes.split_off(target + 1)));
+            }
+        }
+        Err(mismatch(format!(
+            "Could not determine the target of recursive schema (depth {}).",
+            depth
+        )))
+    }
+
+    /// True if a recursion of `depth` at `step` of the current node may refer to the root.
+    ///
+    /// [SchemaPath::resolve] never resolves to the root, since the schema of a type obtained
+    /// directly has no recursion point at the root. But a type such as `Box<T>` does, so the
+    /// schema of a file saved from one may have recursions referring to the root.
+    pub(crate) fn may_refer_to_root(&self, depth: usize, step: Step) -> bool {
+        let name_matches = match (self.nodes[0].schema, self.recursion_type_hint(step)) {
+            (Schema::Struct(s), Some(hint)) => s.dbg_name == hint,
+            (Schema::Enum(e), Some(hint)) => e.dbg_name == hint,
+            (Schema::Struct(_) | Schema::Enum(_), None) => true,
+            _ => false,
+        };
+        let rest = &self.nodes[1..];
+        let min_frames = 1 + rest.iter().map(|x| x.min_frames).sum::<usize>();
+        let max_frames = 1 + rest.iter().map(|x| x.max_frames).sum::<usize>();
+        name_matches && (min_frames..=max_frames).contains(&depth)
+    }
+    pub(crate) fn restore(&mut self, removed: Vec<PathNode<'s>>) {
+        self.nodes.extend(removed);
+    }
+}
+
+fn read_child<'s>(
+    path: &mut SchemaPath<'s>,
+    deserializer: &mut Deserializer<impl Read>,
+    schema: &'s Schema,
+    step: Step,
+    recursion_point: bool,
+) -> Result<Value, SavefileError> {
+    if let Schema::Recursion(depth) = schema {
+        let (target, removed) = path.resolve(*depth, step)?;
+        let value = read_value(path, deserializer, target)?;
+        path.restore(removed);
+        return Ok(value);
+    }
+    path.enter(schema, step, recursion_point, false);
+    let value = read_value(path, deserializer, schema)?;
+    path.leave();
+    Ok(value)
+}
+
+fn read_fields<'s>(
+    path: &mut SchemaPath<'s>,
+    deserializer: &mut Deserializer<impl Read>,
+    fields: &'s [Field],
+    variant: usize,
+    recursion_points: bool,
+) -> Result<Vec<(String, Value)>, SavefileError> {
+    let mut values = Vec::with_capacity(fields.len());
+    for (index, field) in fields.iter().enumerate() {
+        let value = read_child(path, deserializer, &field.value, (variant, index), recursion_points)?;
+        values.push((field.name.clone(), value));
+    }
+    Ok(values)
+}
+
+fn read_primitive(
+    deserializer: &mut Deserializer<impl Read>,
+    primitive: &SchemaPrimitive,
+) -> Result<Value, SavefileError> {
+    Ok(match primitive {
+        SchemaPrimitive::schema_bool => Value::Bool(deserializer.read_bool()?),
+        SchemaPrimitive::schema_u8 => Value::U8(deserializer.read_u8()?),
+        SchemaPrimitive::schema_i8 => Value::I8(deserializer.read_i8()?),
+        SchemaPrimitive::schema_u16 => Value::U16(deserializer.read_u16()?),
+        SchemaPrimitive::schema_i16 => Value::I16(deserializer.read_i16()?),
+        SchemaPrimitive::schema_u32 => Value::U32(deserializer.read_u32()?),
+        SchemaPrimitive::schema_i32 => Value::I32(deserializer.read_i32()?),
+        SchemaPrimitive::schema_u64 => Value::U64(deserializer.read_u64()?),
+        SchemaPrimitive::schema_i64 => Value::I64(deserializer.read_i64()?),
+        SchemaPrimitive::schema_u128 => Value::U128(deserializer.read_u128()?),
+        SchemaPrimitive::schema_i128 => Value::I128(deserializer.read_i128()?),
+        SchemaPrimitive::schema_f32 => Value::F32(deserializer.read_f32()?),
+        SchemaPrimitive::schema_f64 => Value::F64(deserializer.read_f64()?),
+        SchemaPrimitive::schema_char => {
+            Value::Char(char::from_u32(deserializer.read_u32()?).ok_or(SavefileError::InvalidChar)?)
+        }
+        SchemaPrimitive::schema_string(_) => Value::String(deserializer.read_string()?),
+        SchemaPrimitive::schema_canary1 => {
+            let canary = deserializer.read_u32()?;
+            if canary != CANARY1 {
+                return Err(mismatch(format!(
+                    "Encountered bad magic value when deserializing Canary1. Expected {} but got {}",
+                    CANARY1, canary
+                )));
+            }
+            Value::U32(canary)
+        }
+    })
+}
+
+pub(crate) fn read_value<'s>(
+    path: &mut SchemaPath<'s>,
+    deserializer: &mut Deserializer<impl Read>,
+    schema: &'s Schema,
+) -> Result<Value, SavefileError> {
+    Ok(match schema {
+        Schema::Struct(s) => Value::Struct {
+            name: s.dbg_name.clone(),
+            fields: read_fields(path, deserializer, &s.fields, 0, false)?,
+        },
+        Schema::Enum(e) => {
+            let discriminant = match e.discriminant_size {
+                1 => deserializer.read_u8()? as u32,
+                2 => deserializer.read_u16()? as u32,
+                4 => deserializer.read_u32()?,
+                size => return Err(mismatch(format!("Unsupported discriminant size {}.", size))),
+            };
+            let Some(index) = wire_discriminants(e)?.iter().position(|x| *x == discriminant) else {
+                return Err(mismatch(format!(
+                    "Corrupt file - unknown variant {} of enum {} detected.",
+                    discriminant, e.dbg_name
+                )));
+            };
+            let variant = &e.variants[index];
+            Value::Enum {
+                name: e.dbg_name.clone(),
+                variant: variant.name.clone(),
+                fields: read_fields(path, deserializer, &variant.fields, index, false)?,
+            }
+        }
+        Schema::Primitive(primitive) => read_primitive(deserializer, primitive)?,
+        Schema::Vector(item, _) => {
+            let len = deserializer.read_usize()?;
+            deserializer.check_collection_len(len, std::mem::size_of::<Value>())?;
+            // The length may be corrupt, don't trust it for the initial allocation
+            let mut items = Vec::with_capacity(len.min(4096));
+            for _ in 0..len {
+                match &**item {
+                    Schema::Struct(entry) if is_map_entry(entry) => {
+                        path.enter(item, (0, 0), false, true);
+                        items.push(Value::Struct {
+                            name: entry.dbg_name.clone(),
+                            fields: read_fields(path, deserializer, &entry.fields, 0, true)?,
+                        });
+                        path.leave();
+                    }
+                    _ => items.push(read_child(path, deserializer, item, (0, 0), true)?),
+                }
+            }
+            Value::Vector(items)
+        }
+        Schema::Array(array) => {
+            let mut items = Vec::with_capacity(array.count.min(4096));
+            for _ in 0..array.count {
+                items.push(read_child(path, deserializer, &array.item_type, (0, 0), true)?);
+            }
+            Value::Array(items)
+        }
+        Schema::SchemaOption(inner) => {
+            if deserializer.read_bool()? {
+                Value::Option(Some(Box::new(read_child(path, deserializer, inner, (0, 0), false)?)))
+            } else {
+                Value::Option(None)
+            }
+        }
+        Schema::ZeroSize => Value::Unit,
+        Schema::Boxed(inner) => read_child(path, deserializer, inner, (0, 0), false)?,
+        other => {
+            return Err(mismatch(format!(
+                "Values of schema type {} cannot be read.",
+                other.top_level_description()
+            )))
+        }
+    })
+}
+
+/// The number of bytes in the serialized form of any value of the given schema, if it is
+/// the same for all values
+fn fixed_size(schema: &Schema) -> Option<u64> {
+    match schema {
+        Schema::Primitive(primitive) => match primitive {
+            SchemaPrimitive::schema_bool | SchemaPrimitive::schema_u8 | SchemaPrimitive::schema_i8 => Some(1),
+            SchemaPrimitive::schema_u16 | SchemaPrimitive::schema_i16 => Some(2),
+            SchemaPrimitive::schema_u32
+            | SchemaPrimitive::schema_i32
+            | SchemaPrimitive::schema_f32
+            | SchemaPrimitive::schema_char => Some(4),
+            SchemaPrimitive::schema_u64 | SchemaPrimitive::schema_i64 | SchemaPrimitive::schema_f64 => Some(8),
+            SchemaPrimitive::schema_u128 | SchemaPrimitive::schema_i128 => Some(16),
+            // The canary is verified, so it is not skipped as plain bytes
+            SchemaPrimitive::schema_string(_) | SchemaPrimitive::schema_canary1 => None,
+        },
+        Schema::Array(array) => fixed_size(&array.item_type)?.checked_mul(array.count as u64),
+        Schema::Struct(s) => s
+            .fields
+            .iter()
+            .try_fold(0u64, |sum, field| sum.checked_add(fixed_size(&field.value)?)),
+        Schema::ZeroSize => Some(0),
+        _ => None,
+    }
+}
+
+fn skip_bytes(deserializer: &mut Deserializer<impl Read>, count: u64) -> Result<(), SavefileError> {
+    let skipped = std::io::copy(&mut (&mut *deserializer.reader).take(count), &mut std::io::sink())?;
+    if skipped != count {
+        return Err(SavefileError::ShortRead);
+    }
+    Ok(())
+}
+
+pub(crate) fn skip_child<'s>(
+    path: &mut SchemaPath<'s>,
+    deserializer: &mut Deserializer<impl Read>,
+    schema: &'s Schema,
+    step: Step,
+    recursion_point: bool,
+) -> Result<(), SavefileError> {
+    if let Schema::Recursion(depth) = schema {
+        let (target, removed) = path.resolve(*depth, step)?;
+        skip(path, deserializer, target)?;
+        path.restore(removed);
+        return Ok(());
+    }
+    path.enter(schema, step, recursion_point, false);
+    skip(path, deserializer, schema)?;
+    path.leave();
+    Ok(())
+}
+
+fn skip_fields<'s>(
+    path: &mut SchemaPath<'s>,
+    deserializer: &mut Deserializer<impl Read>,
+    fields: &'s [Field],
+    variant: usize,
+    recursion_points: bool,
+) -> Result<(), SavefileError> {
+    for (index, field) in fields.iter().enumerate() {
+        skip_child(path, deserializer, &field.value, (variant, index), recursion_points)?;
+    }
+    Ok(())
+}
+
+/// Like [read_value], but the value is discarded instead of being returned
+pub(crate) fn skip<'s>(
+    path: &mut SchemaPath<'s>,
+    deserializer: &mut Deserializer<impl Read>,
+    schema: &'s Schema,
+) -> Result<(), SavefileError> {
+    if let Some(size) = fixed_size(schema) {
+        return skip_bytes(deserializer, size);
+    }
+    match schema {
+        Schema::Struct(s) => skip_fields(path, deserializer, &s.fields, 0, false),
+        Schema::Enum(e) => {
+            let discriminant = match e.discriminant_size {
+                1 => deserializer.read_u8()? as u32,
+                2 => deserializer.read_u16()? as u32,
+                4 => deserializer.read_u32()?,
+                size => return Err(mismatch(format!("Unsupported discriminant size {}.", size))),
+            };
+            let Some(index) = wire_discriminants(e)?.iter().position(|x| *x == discriminant) else {
+                return Err(mismatch(format!(
+                    "Corrupt file - unknown variant {} of enum {} detected.",
+                    discriminant, e.dbg_name
+                )));
+            };
+            skip_fields(path, deserializer, &e.variants[index].fields, index, false)
+        }
+        Schema::Primitive(SchemaPrimitive::schema_string(_)) => {
+            let len = deserializer.read_usize()?;
+            skip_bytes(deserializer, len as u64)
+        }
+        Schema::Primitive(primitive) => read_primitive(deserializer, primitive).map(|_| ()),
+        Schema::Vector(item, _) => {
+            let len = deserializer.read_usize()?;
+            if let Some(size) = fixed_size(item) {
+                let total = size.checked_mul(len as u64).ok_or(SavefileError::SizeOverflow)?;
+                return skip_bytes(deserializer, total);
+            }
+            for _ in 0..len {
+                match &**item {
+                    Schema::Struct(entry) if is_map_entry(entry) => {
+                        path.enter(item, (0, 0), false, true);
+                        skip_fields(path, deserializer, &entry.fields, 0, true)?;
+                        path.leave();
+                    }
+                    _ => skip_child(path, deserializer, item, (0, 0), true)?,
+                }
+            }
+            Ok(())
+        }
+        Schema::Array(array) => {
+            for _ in 0..array.count {
+                skip_child(path, deserializer, &array.item_type, (0, 0), true)?;
+            }
+            Ok(())
+        }
+        Schema::SchemaOption(inner) => {
+            if deserializer.read_bool()? {
+                skip_child(path, deserializer, inner, (0, 0), false)?;
+            }
+            Ok(())
+        }
+        Schema::Boxed(inner) => skip_child(path, deserializer, inner, (0, 0), false),
+        other => Err(mismatch(format!(
+            "Values of schema type {} cannot be skipped.",
+            other.top_level_description()
+        ))),
+    }
+}
+
+/// True if `schema`, the child `step` of the current node of `path`, contains a
+/// [Schema::Recursion] which may refer to the root of the path, see [SchemaPath::may_refer_to_root].
+/// Recursions are not followed.
+pub(crate) fn may_recurse_to_root<'s>(
+    path: &mut SchemaPath<'s>,
+    schema: &'s Schema,
+    step: Step,
+    recursion_point: bool,
+) -> bool {
+    if let Schema::Recursion(depth) = schema {
+        return path.may_refer_to_root(*depth, step);
+    }
+    let fields_recurse = |path: &mut SchemaPath<'s>, fields: &'s [Field], variant: usize, recursion_points: bool| {
+        fields
+            .iter()
+            .enumerate()
+            .any(|(index, field)| may_recurse_to_root(path, &field.value, (variant, index), recursion_points))
+    };
+    path.enter(schema, step, recursion_point, false);
+    let result = match schema {
+        Schema::Struct(s) => fields_recurse(path, &s.fields, 0, false),
+        Schema::Enum(e) => e
+            .variants
+            .iter()
+            .enumerate()
+            .any(|(index, variant)| fields_recurse(path, &variant.fields, index, false)),
+        Schema::Vector(item, _) => match &**item {
+            Schema::Struct(entry) if is_map_entry(entry) => {
+                path.enter(item, (0, 0), false, true);
+                let result = fields_recurse(path, &entry.fields, 0, true);
+                path.leave();
+                result
+            }
+            _ => may_recurse_to_root(path, item, (0, 0), true),
+        },
+        Schema::Array(array) => may_recurse_to_root(path, &array.item_type, (0, 0), true),
+        Schema::SchemaOption(inner) | Schema::Boxed(inner) => may_recurse_to_root(path, inner, (0, 0), false),
+        _ => false,
+    };
+    path.leave();
+    result
+}
+
+impl<'a, TR: Read> Deserializer<'a, TR> {
+    /// Read past a value with the given schema, without deserializing it.
+    ///
+    /// Only the data needed to find the end of the value is read, such as the lengths of
+    /// strings and vectors, and the discriminants of enums. Nothing is allocated for the
+    /// contents of the value, and vectors of fixed size items are skipped in one go.
+    ///
+    /// Fails for the same schemas as [Value::deserialize_with_schema].
+    pub fn skip_value(&mut self, schema: &Schema) -> Result<(), SavefileError> {
+        let mut path = SchemaPath::new(schema);
+        skip(&mut path, self, schema)
+    }
+}
+
+fn write_child<'s>(
+    path: &mut SchemaPath<'s>,
+    serializer: &mut Serializer<impl Write>,
+    schema: &'s Schema,
+    step: Step,
+    recursion_point: bool,
+    value: &Value,
+) -> Result<(), SavefileError> {
+    if let Schema::Recursion(depth) = schema {
+        let (target, removed) = path.resolve(*depth, step)?;
+        write_value(path, serializer, target, value)?;
+        path.restore(removed);
+        return Ok(());
+    }
+    path.enter(schema, step, recursion_point, false);
+    write_value(path, serializer, schema, value)?;
+    path.leave();
+    Ok(())
+}
+
+fn write_fields<'s>(
+    path: &mut SchemaPath<'s>,
+    serializer: &mut Serializer<impl Write>,
+    schema_fields: &'s [Field],
+    variant: usize,
+    recursion_points: bool,
+    fields: &[(String, Value)],
+    what: &str,
+) -> Result<(), SavefileError> {
+    if fields.len() != schema_fields.len() {
+        return Err(mismatch(format!(
+            "{} has {} fields, but the schema has {}.",
+            what,
+            fields.len(),
+            schema_fields.len()
+        )));
+    }
+    for (index, (field, (name, value))) in schema_fields.iter().zip(fields).enumerate() {
+        if field.name != *name {
+            return Err(mismatch(format!(
+                "{} has field '{}' where the schema has field '{}'.",
+                what, name, field.name
+            )));
+        }
+        write_child(
+            path,
+            serializer,
+            &field.value,
+            (variant, index),
+            recursion_points,
+            value,
+        )?;
+    }
+    Ok(())
+}
+
+fn write_primitive(
+    serializer: &mut Serializer<impl Write>,
+    primitive: &SchemaPrimitive,
+    value: &Value,
+) -> Result<(), SavefileError> {
+    match (primitive, value) {
+        (SchemaPrimitive::schema_bool, Value::Bool(x)) => serializer.write_bool(*x),
+        (SchemaPrimitive::schema_u8, Value::U8(x)) => serializer.write_u8(*x),
+        (SchemaPrimitive::schema_i8, Value::I8(x)) => serializer.write_i8(*x),
+        (SchemaPrimitive::schema_u16, Value::U16(x)) => serializer.write_u16(*x),
+        (SchemaPrimitive::schema_i16, Value::I16(x)) => serializer.write_i16(*x),
+        (SchemaPrimitive::schema_u32, Value::U32(x)) => serializer.write_u32(*x),
+        (SchemaPrimitive::schema_i32, Value::I32(x)) => serializer.write_i32(*x),
+        (SchemaPrimitive::schema_u64, Value::U64(x)) => serializer.write_u64(*x),
+        (SchemaPrimitive::schema_i64, Value::I64(x)) => serializer.write_i64(*x),
+        (SchemaPrimitive::schema_u128, Value::U128(x)) => serializer.write_u128(*x),
+        (SchemaPrimitive::schema_i128, Value::I128(x)) => serializer.write_i128(*x),
+        (SchemaPrimitive::schema_f32, Value::F32(x)) => serializer.write_f32(*x),
+        (SchemaPrimitive::schema_f64, Value::F64(x)) => serializer.write_f64(*x),
+        (SchemaPrimitive::schema_char, Value::Char(x)) => serializer.write_u32(*x as u32),
+        (SchemaPrimitive::schema_string(_), Value::String(x)) => serializer.write_string(x),
+        (SchemaPrimitive::schema_canary1, Value::U32(CANARY1)) => serializer.write_u32(CANARY1),
+        (primitive, value) => Err(mismatch(format!(
+            "Value of type {} does not match schema type {}.",
+            value.type_name(),
+            primitive.name()
+        ))),
+    }
+}
+
+fn write_value<'s>(
+    path: &mut SchemaPath<'s>,
+    serializer: &mut Serializer<impl Write>,
+    schema: &'s Schema,
+    value: &Value,
+) -> Result<(), SavefileError> {
+    match (schema, value) {
+        (Schema::Struct(s), Value::Struct { name, fields }) if *name == s.dbg_name && is_lazy(s) => {
+            // The length is written for the value as it is now, since the value may have been
+            // changed or converted since it was read.
+            let value = match &fields[..] {
+                [(length, _), (value_name, value)] if length == "length" && value_name == "value" => value,
+                _ => {
+                    return Err(mismatch(
+                        "Lazy value must have the fields 'length' and 'value'.".to_string(),
+                    ))
+                }
+            };
+            let mut data = Vec::new();
+            let mut value_serializer = Serializer {
+                writer: &mut data,
+                file_version: serializer.file_version,
+            };
+            write_child(path, &mut value_serializer, &s.fields[1].value, (0, 1), false, value)?;
+            serializer.write_u64(data.len() as u64)?;
+            serializer.write_bytes(&data)
+        }
+        (Schema::Struct(s), Value::Struct { name, fields }) if *name == s.dbg_name => write_fields(
+            path,
+            serializer,
+            &s.fields,
+            0,
+            false,
+            fields,
+            &format!("Struct {}", name),
+        ),
+        (Schema::Enum(e), Value::Enum { name, variant, fields }) if *name == e.dbg_name => {
+            let Some(index) = e.variants.iter().position(|x| x.name == *variant) else {
+                return Err(mismatch(format!("Enum {} has no variant {}.", name, variant)));
+            };
+            let discriminant = wire_discriminants(e)?[index];
+            match e.discriminant_size {
+                1 => serializer.write_u8(discriminant as u8)?,
+                2 => serializer.write_u16(discriminant as u16)?,
+                4 => serializer.write_u32(discriminant)?,
+                size => return Err(mismatch(format!("Unsupported discriminant size {}.", size))),
+            }
+            let what = format!("Variant {} of enum {}", variant, name);
+            write_fields(path, serializer, &e.variants[index].fields, index, false, fields, &what)
+        }
+        (Schema::Primitive(primitive), value) => write_primitive(serializer, primitive, value),
+        (Schema::Vector(item, _), Value::Vector(items)) => {
+            serializer.write_usize(items.len())?;
+            for value in items {
+                match (&**item, value) {
+                    (Schema::Struct(entry), Value::Struct { fields, .. }) if is_map_entry(entry) => {
+                        path.enter(item, (0, 0), false, true);
+                        write_fields(path, serializer, &entry.fields, 0, true, fields, "Map entry")?;
+                        path.leave();
+                    }
+                    _ => write_child(path, serializer, item, (0, 0), true, value)?,
+                }
+            }
+            Ok(())
+        }
+        (Schema::Array(array), Value::Array(items)) => {
+            if items.len() != array.count {
+                return Err(mismatch(format!(
+                    "Array has {} items, but the schema has {}.",
+                    items.len(),
+                    array.count
+                )));
+            }
+            for value in items {
+                write_child(path, serializer, &array.item_type, (0, 0), true, value)?;
+            }
+            Ok(())
+        }
+        (Schema::SchemaOption(inner), Value::Option(value)) => match value {
+            Some(value) => {
+                serializer.write_bool(true)?;
+                write_child(path, serializer, inner, (0, 0), false, value)
+            }
+            None => serializer.write_bool(false),
+        },
+        (Schema::ZeroSize, Value::Unit) => Ok(()),
+        (Schema::Boxed(inner), value) => write_child(path, serializer, inner, (0, 0), false, value),
+        (schema, value) => Err(mismatch(format!(
+            "Value of type {} does not match schema type {}.",
+            value.type_name(),
+            schema.top_level_description()
+        ))),
+    }
+}